@@ -0,0 +1,19 @@
+-- Document chunks and their local vector representations for semantic retrieval
+
+-- 文档分块表
+CREATE TABLE document_chunks (
+    id TEXT PRIMARY KEY,
+    document_id TEXT NOT NULL,
+    knowledge_base_id TEXT NOT NULL,
+    chunk_index INTEGER NOT NULL,
+    content TEXT NOT NULL,
+    start_offset INTEGER NOT NULL,
+    end_offset INTEGER NOT NULL,
+    embedding BLOB NOT NULL,
+    created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+    FOREIGN KEY (document_id) REFERENCES documents(id) ON DELETE CASCADE,
+    FOREIGN KEY (knowledge_base_id) REFERENCES knowledge_bases(id) ON DELETE CASCADE
+);
+
+CREATE INDEX idx_document_chunks_knowledge_base ON document_chunks(knowledge_base_id);
+CREATE INDEX idx_document_chunks_document ON document_chunks(document_id, chunk_index);
@@ -0,0 +1,9 @@
+-- Link generated questions to the passage they were generated from
+
+ALTER TABLE document_chunks ADD COLUMN page_number INTEGER;
+
+ALTER TABLE questions ADD COLUMN document_id TEXT REFERENCES documents(id) ON DELETE SET NULL;
+ALTER TABLE questions ADD COLUMN chunk_id TEXT REFERENCES document_chunks(id) ON DELETE SET NULL;
+ALTER TABLE questions ADD COLUMN page_number INTEGER;
+
+CREATE INDEX idx_questions_chunk ON questions(chunk_id);
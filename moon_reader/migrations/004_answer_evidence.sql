@@ -0,0 +1,3 @@
+-- Passages an answer was evaluated against, stored as a JSON array
+
+ALTER TABLE answers ADD COLUMN evidence TEXT;
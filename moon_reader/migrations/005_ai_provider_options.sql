@@ -0,0 +1,3 @@
+-- Provider-specific model options (e.g. Ollama's num_ctx), stored as a JSON object
+
+ALTER TABLE ai_config ADD COLUMN provider_options TEXT;
@@ -0,0 +1,5 @@
+-- Timeouts and retry count for AI requests; NULL means the built-in default
+
+ALTER TABLE ai_config ADD COLUMN connect_timeout_secs INTEGER;
+ALTER TABLE ai_config ADD COLUMN request_timeout_secs INTEGER;
+ALTER TABLE ai_config ADD COLUMN max_retries INTEGER;
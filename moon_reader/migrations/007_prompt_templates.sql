@@ -0,0 +1,67 @@
+-- Editable prompt templates; {{variable}} placeholders are filled in when a prompt is rendered
+
+CREATE TABLE prompt_templates (
+    id TEXT PRIMARY KEY,
+    name TEXT NOT NULL UNIQUE,
+    kind TEXT NOT NULL,
+    system_prompt TEXT,
+    user_prompt TEXT NOT NULL,
+    is_default BOOLEAN NOT NULL DEFAULT 0,
+    created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+    updated_at DATETIME DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX idx_prompt_templates_kind ON prompt_templates(kind);
+
+-- Seed with the prompts previously hardcoded in the providers
+INSERT INTO prompt_templates (id, name, kind, system_prompt, user_prompt, is_default) VALUES
+(
+    'builtin-question-en',
+    'English question',
+    'question',
+    'You are a professional educational assistant. Based on the provided learning material content, generate a thoughtful question to test the learner''s understanding. The question should: 1) Test understanding of core concepts 2) Require comprehensive thinking 3) Avoid simple factual questions. Please return only the question itself without other explanations.',
+    'Generate a question based on the following learning material:
+
+{{context}}',
+    1
+),
+(
+    'builtin-evaluation-en',
+    'English evaluation',
+    'evaluation',
+    'You are a professional educational assessment assistant. Please evaluate the learner''s answer and provide constructive feedback. Evaluation criteria: accuracy, completeness, depth. Please return the evaluation result in JSON format, including: score (integer 0-100), feedback (detailed feedback), suggestions (array of improvement suggestions).',
+    'Reference material:
+{{context}}
+
+Question: {{question}}
+
+Learner''s answer: {{answer}}
+
+Please evaluate this answer and return a JSON-formatted evaluation result.',
+    1
+),
+(
+    'builtin-question-zh',
+    'Chinese question',
+    'question',
+    '你是一个专业的教育助手。基于提供的学习材料内容，生成一个有深度的问题来测试学习者对内容的理解。问题应该：1) 测试核心概念的理解 2) 需要综合思考 3) 避免简单的事实性问题。请只返回问题本身，不要包含其他解释。',
+    '基于以下学习材料生成一个问题：
+
+{{context}}',
+    0
+),
+(
+    'builtin-evaluation-zh',
+    'Chinese evaluation',
+    'evaluation',
+    '你是一个专业的教育评估助手。请评估学习者的答案，并提供建设性的反馈。评估标准：准确性、完整性、深度。请以JSON格式返回评估结果，包含：score(0-100的整数)、feedback(详细反馈)、suggestions(改进建议数组)。',
+    '参考材料：
+{{context}}
+
+问题：{{question}}
+
+学习者答案：{{answer}}
+
+请评估这个答案并返回JSON格式的评估结果。',
+    0
+);
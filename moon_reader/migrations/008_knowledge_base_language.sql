@@ -0,0 +1,16 @@
+-- Output language for generated questions and feedback; NULL detects it from the document text
+
+ALTER TABLE knowledge_bases ADD COLUMN language TEXT;
+
+-- Let the built-in templates follow the knowledge base language
+UPDATE prompt_templates SET system_prompt = system_prompt || ' Write the question in {{language}}.'
+WHERE id = 'builtin-question-en';
+
+UPDATE prompt_templates SET system_prompt = system_prompt || ' Write the feedback and suggestions in {{language}}, keeping the JSON field names in English.'
+WHERE id = 'builtin-evaluation-en';
+
+UPDATE prompt_templates SET system_prompt = system_prompt || '请使用{{language}}提出问题。'
+WHERE id = 'builtin-question-zh';
+
+UPDATE prompt_templates SET system_prompt = system_prompt || '请使用{{language}}撰写反馈和改进建议，JSON字段名保持英文。'
+WHERE id = 'builtin-evaluation-zh';
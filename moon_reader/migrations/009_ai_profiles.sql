@@ -0,0 +1,21 @@
+-- Named AI profiles: every ai_config row is a profile and one of them is the default
+
+ALTER TABLE ai_config ADD COLUMN name TEXT;
+ALTER TABLE ai_config ADD COLUMN is_default BOOLEAN NOT NULL DEFAULT 0;
+
+-- The single existing configuration becomes the default profile
+UPDATE ai_config SET name = 'Default', is_default = 1
+WHERE id = (SELECT id FROM ai_config ORDER BY updated_at DESC LIMIT 1);
+UPDATE ai_config SET name = 'Profile ' || id WHERE name IS NULL;
+
+CREATE UNIQUE INDEX idx_ai_config_name ON ai_config(name);
+
+-- Which profile serves a task ('question' or 'evaluation'), for one knowledge base
+-- or, with a NULL knowledge_base_id, for every knowledge base without its own choice
+CREATE TABLE ai_profile_selections (
+    knowledge_base_id TEXT REFERENCES knowledge_bases(id) ON DELETE CASCADE,
+    task TEXT NOT NULL,
+    profile_id INTEGER NOT NULL REFERENCES ai_config(id) ON DELETE CASCADE
+);
+
+CREATE UNIQUE INDEX idx_ai_profile_selections_scope ON ai_profile_selections(COALESCE(knowledge_base_id, ''), task);
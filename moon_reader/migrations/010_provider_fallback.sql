@@ -0,0 +1,6 @@
+-- Ordered fallback profiles (JSON array of ai_config ids) tried when a profile's service is unavailable,
+-- and the provider that actually served each question and evaluation
+
+ALTER TABLE ai_config ADD COLUMN fallback_profile_ids TEXT;
+ALTER TABLE questions ADD COLUMN served_by TEXT;
+ALTER TABLE answers ADD COLUMN served_by TEXT;
@@ -0,0 +1,31 @@
+-- Token usage of every AI call, per-model prices and per-profile monthly budgets
+
+-- Usage rows outlive the questions, answers, knowledge bases and profiles they refer to,
+-- so spending history and budgets stay accurate after deletions
+CREATE TABLE ai_usage (
+    id INTEGER PRIMARY KEY,
+    profile_id INTEGER,
+    provider TEXT NOT NULL,
+    model TEXT NOT NULL DEFAULT '',
+    task TEXT NOT NULL,
+    knowledge_base_id TEXT,
+    question_id TEXT,
+    answer_id TEXT,
+    prompt_tokens INTEGER NOT NULL DEFAULT 0,
+    completion_tokens INTEGER NOT NULL DEFAULT 0,
+    created_at DATETIME DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX idx_ai_usage_created_at ON ai_usage(created_at);
+CREATE INDEX idx_ai_usage_profile ON ai_usage(profile_id, created_at);
+
+-- Prices per million tokens; model '' applies to every model of the provider without its own price
+CREATE TABLE ai_model_prices (
+    provider TEXT NOT NULL,
+    model TEXT NOT NULL DEFAULT '',
+    prompt_price REAL NOT NULL DEFAULT 0,
+    completion_price REAL NOT NULL DEFAULT 0,
+    PRIMARY KEY (provider, model)
+);
+
+ALTER TABLE ai_config ADD COLUMN monthly_budget REAL;
@@ -0,0 +1,5 @@
+-- Multiple choice, true/false, cloze and short answer questions next to open ones;
+-- the answer key of a structured question is kept as JSON so it can be graded without the AI
+
+ALTER TABLE questions ADD COLUMN question_type TEXT NOT NULL DEFAULT 'open';
+ALTER TABLE questions ADD COLUMN payload TEXT;
@@ -0,0 +1,20 @@
+-- Difficulty (1-5) and Bloom's-taxonomy level a question was generated for; NULL when untargeted
+
+ALTER TABLE questions ADD COLUMN difficulty INTEGER;
+ALTER TABLE questions ADD COLUMN cognitive_level TEXT;
+
+-- Let the built-in question templates follow the requested difficulty instead of a fixed one;
+-- templates edited since they were seeded are left alone
+UPDATE prompt_templates SET system_prompt = REPLACE(
+    system_prompt,
+    'generate a thoughtful question to test the learner''s understanding. The question should: 1) Test understanding of core concepts 2) Require comprehensive thinking 3) Avoid simple factual questions.',
+    'generate a question to test the learner''s understanding. {{difficulty}}'
+)
+WHERE id = 'builtin-question-en';
+
+UPDATE prompt_templates SET system_prompt = REPLACE(
+    system_prompt,
+    '生成一个有深度的问题来测试学习者对内容的理解。问题应该：1) 测试核心概念的理解 2) 需要综合思考 3) 避免简单的事实性问题。',
+    '生成一个问题来测试学习者对内容的理解。{{difficulty}}'
+)
+WHERE id = 'builtin-question-zh';
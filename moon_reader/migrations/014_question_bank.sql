@@ -0,0 +1,27 @@
+-- Question bank: questions generated in background batches wait as drafts until approved
+
+CREATE TABLE question_batches (
+    id TEXT PRIMARY KEY,
+    knowledge_base_id TEXT NOT NULL,
+    status TEXT NOT NULL DEFAULT 'running',
+    requested INTEGER NOT NULL,
+    generated INTEGER NOT NULL DEFAULT 0,
+    duplicates INTEGER NOT NULL DEFAULT 0,
+    failures INTEGER NOT NULL DEFAULT 0,
+    question_type TEXT NOT NULL DEFAULT 'open',
+    difficulty INTEGER,
+    cognitive_level TEXT,
+    document_id TEXT,
+    error TEXT,
+    created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+    finished_at DATETIME,
+    FOREIGN KEY (knowledge_base_id) REFERENCES knowledge_bases(id) ON DELETE CASCADE
+);
+
+CREATE INDEX idx_question_batches_kb ON question_batches(knowledge_base_id, created_at);
+
+-- Questions generated one at a time are approved as they are asked
+ALTER TABLE questions ADD COLUMN status TEXT NOT NULL DEFAULT 'approved';
+ALTER TABLE questions ADD COLUMN batch_id TEXT REFERENCES question_batches(id) ON DELETE SET NULL;
+
+CREATE INDEX idx_questions_bank ON questions(knowledge_base_id, status);
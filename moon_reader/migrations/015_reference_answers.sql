@@ -0,0 +1,32 @@
+-- Reference answer and key points stored with each question, the ground truth answers are graded against
+
+ALTER TABLE questions ADD COLUMN reference_answer TEXT;
+ALTER TABLE questions ADD COLUMN key_points TEXT;
+
+-- Let the built-in evaluation templates grade against the key points when a question has them;
+-- {{reference}} renders as nothing for questions without one, and edited templates are left alone
+UPDATE prompt_templates SET
+    system_prompt = REPLACE(
+        system_prompt,
+        'Evaluation criteria: accuracy, completeness, depth.',
+        'Evaluation criteria: accuracy, completeness, depth. When a reference answer and key points are given, treat them as the expected answer and score mainly by how many key points the learner covers correctly.'
+    ),
+    user_prompt = REPLACE(user_prompt, 'Question: {{question}}
+
+', 'Question: {{question}}
+
+{{reference}}')
+WHERE id = 'builtin-evaluation-en';
+
+UPDATE prompt_templates SET
+    system_prompt = REPLACE(
+        system_prompt,
+        '评估标准：准确性、完整性、深度。',
+        '评估标准：准确性、完整性、深度。如果提供了参考答案和要点，请以其为标准答案，主要根据学习者正确覆盖了多少要点来评分。'
+    ),
+    user_prompt = REPLACE(user_prompt, '问题：{{question}}
+
+', '问题：{{question}}
+
+{{reference}}')
+WHERE id = 'builtin-evaluation-zh';
@@ -0,0 +1,35 @@
+-- Rubric scoring: answers keep a score per criterion and the key points they covered or missed,
+-- and each knowledge base can weight the criteria differently
+
+ALTER TABLE answers ADD COLUMN accuracy_score INTEGER;
+ALTER TABLE answers ADD COLUMN completeness_score INTEGER;
+ALTER TABLE answers ADD COLUMN depth_score INTEGER;
+ALTER TABLE answers ADD COLUMN clarity_score INTEGER;
+ALTER TABLE answers ADD COLUMN matched_key_points TEXT;
+ALTER TABLE answers ADD COLUMN missed_key_points TEXT;
+
+-- JSON object of criterion weights; NULL uses the default rubric
+ALTER TABLE knowledge_bases ADD COLUMN rubric_weights TEXT;
+
+-- Ask the built-in evaluation templates for the per-criterion scores; edited templates are left alone
+UPDATE prompt_templates SET system_prompt = REPLACE(
+    REPLACE(
+        system_prompt,
+        'Evaluation criteria: accuracy, completeness, depth.',
+        'Evaluation criteria: accuracy, completeness, depth, clarity.'
+    ),
+    'including: score (integer 0-100), feedback (detailed feedback), suggestions (array of improvement suggestions).',
+    'including: score (integer 0-100), criteria (object with an integer 0-100 score for each of accuracy, completeness, depth and clarity), feedback (detailed feedback), suggestions (array of improvement suggestions), matched_key_points and missed_key_points (arrays of the given key points the answer covers and misses).'
+)
+WHERE id = 'builtin-evaluation-en';
+
+UPDATE prompt_templates SET system_prompt = REPLACE(
+    REPLACE(
+        system_prompt,
+        '评估标准：准确性、完整性、深度。',
+        '评估标准：准确性、完整性、深度、清晰度。'
+    ),
+    '包含：score(0-100的整数)、feedback(详细反馈)、suggestions(改进建议数组)。',
+    '包含：score(0-100的整数)、criteria(对象，包含accuracy、completeness、depth、clarity四项，各为0-100的整数)、feedback(详细反馈)、suggestions(改进建议数组)、matched_key_points和missed_key_points(答案覆盖和遗漏的给定要点数组)。'
+)
+WHERE id = 'builtin-evaluation-zh';
@@ -0,0 +1,13 @@
+-- Tutoring threads: follow-up questions about an answer's feedback and the AI tutor's replies
+
+CREATE TABLE answer_messages (
+    id TEXT PRIMARY KEY,
+    answer_id TEXT NOT NULL,
+    role TEXT NOT NULL,
+    content TEXT NOT NULL,
+    served_by TEXT,
+    created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+    FOREIGN KEY (answer_id) REFERENCES answers(id) ON DELETE CASCADE
+);
+
+CREATE INDEX idx_answer_messages_answer ON answer_messages(answer_id, created_at);
@@ -0,0 +1,5 @@
+-- A document has at most one chunk per position, so re-indexing can update chunks in place
+-- and keep the ids that questions point at
+
+DROP INDEX idx_document_chunks_document;
+CREATE UNIQUE INDEX idx_document_chunks_document ON document_chunks(document_id, chunk_index);
@@ -0,0 +1,8 @@
+-- DeepSeek was prompted in Chinese before prompts became templates. Where the default AI
+-- profile uses DeepSeek, make the Chinese built-in templates the defaults so its questions and
+-- evaluations read as before; kinds whose default is no longer the English built-in are left alone
+
+UPDATE prompt_templates
+SET is_default = CASE WHEN id LIKE 'builtin-%-zh' THEN 1 ELSE 0 END
+WHERE EXISTS (SELECT 1 FROM ai_config WHERE is_default = 1 AND provider = 'deepseek')
+  AND kind IN (SELECT kind FROM prompt_templates WHERE id LIKE 'builtin-%-en' AND is_default = 1);
@@ -0,0 +1,4 @@
+-- Whether open questions generated in a knowledge base get a reference answer and key points
+-- written for them, at the cost of one more provider call each; on unless turned off
+
+ALTER TABLE knowledge_bases ADD COLUMN reference_answers BOOLEAN NOT NULL DEFAULT 1;
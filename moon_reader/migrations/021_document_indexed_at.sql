@@ -0,0 +1,6 @@
+-- When a document was last chunked, so one that yields no chunks is not chunked again on every search
+
+ALTER TABLE documents ADD COLUMN indexed_at DATETIME;
+
+UPDATE documents SET indexed_at = CURRENT_TIMESTAMP
+WHERE id IN (SELECT DISTINCT document_id FROM document_chunks);
@@ -1,10 +1,13 @@
 // Database module for data access layer
-use sqlx::{SqlitePool, sqlite::SqliteConnectOptions, Row};
+use sqlx::{SqlitePool, sqlite::{SqliteConnectOptions, SqliteRow}, Row, FromRow};
+use std::collections::HashMap;
 use std::str::FromStr;
 use chrono::Utc;
-use crate::models::{KnowledgeBase, Document, Question, Answer, ReviewSession, AIConfig, DocumentType, AIProvider, LearningProgress};
+use crate::services::secrets::{self, SecretCipher, SecretError};
+use crate::models::{KnowledgeBase, Document, DocumentChunk, ChunkCoverage, Question, QuestionBatch, QuestionStatus, Answer, AnswerMessage, CriterionAverages, CriterionScores, ReviewSession, AIConfig, AIProfileSelection, AIUsage, AIModelPrice, AIUsageSummary, UsageGrouping, DocumentType, AIProvider, LearningProgress, PromptTemplate, PromptKind};
 
 #[cfg(test)]
+#[allow(clippy::module_inception)]
 mod tests;
 
 pub async fn create_connection_pool(database_url: &str) -> Result<SqlitePool, sqlx::Error> {
@@ -19,15 +22,238 @@ pub async fn create_connection_pool(database_url: &str) -> Result<SqlitePool, sq
     Ok(pool)
 }
 
+// Columns selected whenever a full Question row is loaded
+const QUESTION_COLUMNS: &str = "id, knowledge_base_id, question_text, context_snippet, generated_at, document_id, chunk_id, page_number, served_by, question_type, payload, difficulty, cognitive_level, status, batch_id, reference_answer, key_points";
+
+const ANSWER_COLUMNS: &str = "id, question_id, user_answer, ai_score, ai_feedback, ai_suggestions, answered_at, evidence, served_by, accuracy_score, completeness_score, depth_score, clarity_score, matched_key_points, missed_key_points";
+
+const ANSWER_MESSAGE_COLUMNS: &str = "id, answer_id, role, content, served_by, created_at";
+
+const QUESTION_BATCH_COLUMNS: &str = "id, knowledge_base_id, status, requested, generated, duplicates, failures, question_type, difficulty, cognitive_level, document_id, error, created_at, finished_at";
+
+// Columns selected by the question/answer join queries, see `history_item_from_row`
+const HISTORY_COLUMNS: &str = "q.id as question_id, q.knowledge_base_id, q.question_text, q.context_snippet, q.generated_at,
+                    q.document_id, q.chunk_id, q.page_number, q.served_by as question_served_by, q.question_type, q.payload, q.difficulty, q.cognitive_level, q.status, q.batch_id, q.reference_answer, q.key_points,
+                    a.id as answer_id, a.user_answer, a.ai_score, a.ai_feedback, a.ai_suggestions, a.answered_at, a.evidence,
+                    a.served_by as answer_served_by, a.accuracy_score, a.completeness_score, a.depth_score, a.clarity_score,
+                    a.matched_key_points, a.missed_key_points";
+
+fn history_item_from_row(row: &SqliteRow) -> (Question, Answer) {
+    let question = Question {
+        id: row.get("question_id"),
+        knowledge_base_id: row.get("knowledge_base_id"),
+        question_text: row.get("question_text"),
+        context_snippet: row.get("context_snippet"),
+        generated_at: row.get("generated_at"),
+        document_id: row.get("document_id"),
+        chunk_id: row.get("chunk_id"),
+        page_number: row.get("page_number"),
+        served_by: row.get("question_served_by"),
+        question_type: row.get("question_type"),
+        payload: row.get("payload"),
+        difficulty: row.get("difficulty"),
+        cognitive_level: row.get("cognitive_level"),
+        status: row.get("status"),
+        batch_id: row.get("batch_id"),
+        reference_answer: row.get("reference_answer"),
+        key_points: row.get("key_points"),
+    };
+    
+    let answer = Answer {
+        id: row.get("answer_id"),
+        question_id: row.get("question_id"),
+        user_answer: row.get("user_answer"),
+        ai_score: row.get("ai_score"),
+        ai_feedback: row.get("ai_feedback"),
+        ai_suggestions: row.get("ai_suggestions"),
+        answered_at: row.get("answered_at"),
+        evidence: row.get("evidence"),
+        served_by: row.get("answer_served_by"),
+        accuracy_score: row.get("accuracy_score"),
+        completeness_score: row.get("completeness_score"),
+        depth_score: row.get("depth_score"),
+        clarity_score: row.get("clarity_score"),
+        matched_key_points: row.get("matched_key_points"),
+        missed_key_points: row.get("missed_key_points"),
+    };
+    
+    (question, answer)
+}
+
+const PROMPT_TEMPLATE_COLUMNS: &str =
+    "id, name, kind, system_prompt, user_prompt, is_default, created_at, updated_at";
+
+fn prompt_template_from_row(row: &SqliteRow) -> PromptTemplate {
+    let kind = match row.get::<String, _>("kind").as_str() {
+        "evaluation" => PromptKind::Evaluation,
+        _ => PromptKind::Question,
+    };
+    
+    PromptTemplate {
+        id: row.get("id"),
+        name: row.get("name"),
+        kind,
+        system_prompt: row.get("system_prompt"),
+        user_prompt: row.get("user_prompt"),
+        is_default: row.get("is_default"),
+        created_at: row.get("created_at"),
+        updated_at: row.get("updated_at"),
+    }
+}
+
+// Columns selected whenever an AI profile is loaded, see `ai_config_from_row`
+const AI_CONFIG_COLUMNS: &str =
+    "id, name, is_default, provider, api_key, api_url, model_name, max_tokens, temperature, provider_options,
+     connect_timeout_secs, request_timeout_secs, max_retries, fallback_profile_ids, monthly_budget, updated_at";
+
+fn ai_config_from_row(row: &SqliteRow) -> AIConfig {
+    let provider = match row.get::<String, _>("provider").as_str() {
+        "deepseek" => AIProvider::DeepSeek,
+        "local" => AIProvider::Local,
+        "openai" => AIProvider::OpenAI,
+        "anthropic" => AIProvider::Anthropic,
+        "ollama" => AIProvider::Ollama,
+        "mock" => AIProvider::Mock,
+        _ => AIProvider::DeepSeek, // Default fallback
+    };
+    
+    AIConfig {
+        id: Some(row.get("id")),
+        name: row.get("name"),
+        is_default: row.get("is_default"),
+        provider,
+        api_key: row.get("api_key"),
+        api_url: row.get("api_url"),
+        model_name: row.get("model_name"),
+        max_tokens: row.get("max_tokens"),
+        temperature: row.get("temperature"),
+        provider_options: row.get("provider_options"),
+        connect_timeout_secs: row.get("connect_timeout_secs"),
+        request_timeout_secs: row.get("request_timeout_secs"),
+        max_retries: row.get("max_retries"),
+        fallback_profile_ids: row
+            .get::<Option<String>, _>("fallback_profile_ids")
+            .and_then(|ids| serde_json::from_str(&ids).ok())
+            .unwrap_or_default(),
+        monthly_budget: row.get("monthly_budget"),
+        updated_at: row.get("updated_at"),
+    }
+}
+
+// Usage rows joined with their model's price, falling back to the provider-wide price
+const USAGE_WITH_PRICES: &str = "FROM ai_usage u
+     LEFT JOIN ai_model_prices p ON p.provider = u.provider AND p.model = u.model
+     LEFT JOIN ai_model_prices d ON d.provider = u.provider AND d.model = ''";
+
+// Cost of one usage row in `USAGE_WITH_PRICES`; prices are per million tokens
+const USAGE_COST: &str = "(u.prompt_tokens * COALESCE(p.prompt_price, d.prompt_price, 0.0)
+     + u.completion_tokens * COALESCE(p.completion_price, d.completion_price, 0.0)) / 1000000.0";
+
+// Fallback profile ids as stored: a JSON array, or NULL when there are none
+fn encode_profile_ids(ids: &[i32]) -> Option<String> {
+    (!ids.is_empty()).then(|| serde_json::to_string(ids).unwrap_or_default())
+}
+
+// sqlx has no encode error to wrap; sealing only fails if the cipher itself fails
+fn seal_error(error: SecretError) -> sqlx::Error {
+    sqlx::Error::Protocol(error.to_string())
+}
+
 // Database manager for handling database operations
 #[derive(Clone)]
 pub struct DatabaseManager {
     pool: SqlitePool,
+    // Encrypts API keys at rest; without it they are stored as given
+    secrets: Option<SecretCipher>,
 }
 
 impl DatabaseManager {
     pub fn new(pool: SqlitePool) -> Self {
-        Self { pool }
+        Self { pool, secrets: None }
+    }
+    
+    /// A manager that stores API keys encrypted with `cipher`
+    pub fn with_secrets(pool: SqlitePool, cipher: SecretCipher) -> Self {
+        Self { pool, secrets: Some(cipher) }
+    }
+    
+    // The form of an API key that goes into the database
+    fn seal_secret(&self, secret: Option<&str>) -> Result<Option<String>, sqlx::Error> {
+        match (secret, &self.secrets) {
+            (Some(secret), Some(cipher)) => cipher.seal(secret).map(Some).map_err(seal_error),
+            (secret, _) => Ok(secret.map(str::to_string)),
+        }
+    }
+    
+    // The plaintext of an API key as stored
+    fn open_secret(&self, stored: String) -> Result<String, sqlx::Error> {
+        match &self.secrets {
+            Some(cipher) => cipher.open(&stored),
+            None if secrets::is_sealed(&stored) => Err(SecretError::MissingKey),
+            None => Ok(stored),
+        }
+        .map_err(|e| sqlx::Error::Decode(Box::new(e)))
+    }
+    
+    // Decrypt the API key of a profile loaded from the database
+    fn open_secrets(&self, mut config: AIConfig) -> Result<AIConfig, sqlx::Error> {
+        config.api_key = config.api_key.map(|stored| self.open_secret(stored)).transpose()?;
+        Ok(config)
+    }
+    
+    /// Encrypt API keys still stored in plaintext, returning how many were encrypted; run at startup
+    pub async fn encrypt_plaintext_secrets(&self) -> Result<usize, sqlx::Error> {
+        let Some(cipher) = &self.secrets else {
+            return Ok(0);
+        };
+        
+        let mut tx = self.pool.begin().await?;
+        let rows = sqlx::query("SELECT id, api_key FROM ai_config WHERE api_key IS NOT NULL")
+            .fetch_all(&mut *tx)
+            .await?;
+        
+        let mut encrypted = 0;
+        for row in rows {
+            let stored: String = row.get("api_key");
+            if secrets::is_sealed(&stored) {
+                // Fail now rather than on first use if the key does not match
+                self.open_secret(stored)?;
+                continue;
+            }
+            
+            sqlx::query("UPDATE ai_config SET api_key = ? WHERE id = ?")
+                .bind(cipher.seal(&stored).map_err(seal_error)?)
+                .bind(row.get::<i32, _>("id"))
+                .execute(&mut *tx)
+                .await?;
+            encrypted += 1;
+        }
+        
+        tx.commit().await?;
+        Ok(encrypted)
+    }
+    
+    /// Re-encrypt every stored API key with `new_cipher`, in one transaction; returns how many were rotated
+    ///
+    /// Keys are decrypted with this manager's cipher, so it must hold the current key.
+    pub async fn rotate_secret_key(&self, new_cipher: &SecretCipher) -> Result<usize, sqlx::Error> {
+        let mut tx = self.pool.begin().await?;
+        let rows = sqlx::query("SELECT id, api_key FROM ai_config WHERE api_key IS NOT NULL")
+            .fetch_all(&mut *tx)
+            .await?;
+        
+        for row in &rows {
+            let api_key = self.open_secret(row.get("api_key"))?;
+            
+            sqlx::query("UPDATE ai_config SET api_key = ? WHERE id = ?")
+                .bind(new_cipher.seal(&api_key).map_err(seal_error)?)
+                .bind(row.get::<i32, _>("id"))
+                .execute(&mut *tx)
+                .await?;
+        }
+        
+        tx.commit().await?;
+        Ok(rows.len())
     }
     
     // Knowledge Base CRUD operations
@@ -40,8 +266,8 @@ impl DatabaseManager {
         .bind(&kb.id)
         .bind(&kb.name)
         .bind(&kb.description)
-        .bind(&kb.created_at)
-        .bind(&kb.updated_at)
+        .bind(kb.created_at)
+        .bind(kb.updated_at)
         .execute(&self.pool)
         .await?;
         
@@ -50,7 +276,7 @@ impl DatabaseManager {
     
     pub async fn get_knowledge_bases(&self) -> Result<Vec<KnowledgeBase>, sqlx::Error> {
         let rows = sqlx::query_as::<_, KnowledgeBase>(
-            "SELECT id, name, description, created_at, updated_at FROM knowledge_bases ORDER BY created_at DESC"
+            "SELECT id, name, description, language, rubric_weights, reference_answers, created_at, updated_at FROM knowledge_bases ORDER BY created_at DESC"
         )
         .fetch_all(&self.pool)
         .await?;
@@ -60,7 +286,7 @@ impl DatabaseManager {
     
     pub async fn get_knowledge_base_by_id(&self, id: &str) -> Result<Option<KnowledgeBase>, sqlx::Error> {
         let row = sqlx::query_as::<_, KnowledgeBase>(
-            "SELECT id, name, description, created_at, updated_at FROM knowledge_bases WHERE id = ?"
+            "SELECT id, name, description, language, rubric_weights, reference_answers, created_at, updated_at FROM knowledge_bases WHERE id = ?"
         )
         .bind(id)
         .fetch_optional(&self.pool)
@@ -84,6 +310,48 @@ impl DatabaseManager {
         Ok(result.rows_affected() > 0)
     }
     
+    /// Set the output language; `None` switches back to detecting it from the documents
+    pub async fn set_knowledge_base_language(&self, id: &str, language: Option<&str>) -> Result<bool, sqlx::Error> {
+        let result = sqlx::query(
+            "UPDATE knowledge_bases SET language = ?, updated_at = ? WHERE id = ?"
+        )
+        .bind(language)
+        .bind(Utc::now())
+        .bind(id)
+        .execute(&self.pool)
+        .await?;
+        
+        Ok(result.rows_affected() > 0)
+    }
+    
+    /// Turn writing reference answers for generated open questions on or off
+    pub async fn set_knowledge_base_reference_answers(&self, id: &str, enabled: bool) -> Result<bool, sqlx::Error> {
+        let result = sqlx::query(
+            "UPDATE knowledge_bases SET reference_answers = ?, updated_at = ? WHERE id = ?"
+        )
+        .bind(enabled)
+        .bind(Utc::now())
+        .bind(id)
+        .execute(&self.pool)
+        .await?;
+        
+        Ok(result.rows_affected() > 0)
+    }
+    
+    /// Set the criterion weights as JSON; `None` switches back to the default rubric
+    pub async fn set_knowledge_base_rubric(&self, id: &str, rubric_weights: Option<&str>) -> Result<bool, sqlx::Error> {
+        let result = sqlx::query(
+            "UPDATE knowledge_bases SET rubric_weights = ?, updated_at = ? WHERE id = ?"
+        )
+        .bind(rubric_weights)
+        .bind(Utc::now())
+        .bind(id)
+        .execute(&self.pool)
+        .await?;
+        
+        Ok(result.rows_affected() > 0)
+    }
+    
     pub async fn delete_knowledge_base(&self, id: &str) -> Result<bool, sqlx::Error> {
         let result = sqlx::query(
             "DELETE FROM knowledge_bases WHERE id = ?"
@@ -107,9 +375,9 @@ impl DatabaseManager {
         .bind(&document.filename)
         .bind(&file_type_str)
         .bind(&document.file_path)
-        .bind(&document.file_size)
+        .bind(document.file_size)
         .bind(&document.content_text)
-        .bind(&document.upload_date)
+        .bind(document.upload_date)
         .execute(&self.pool)
         .await?;
         
@@ -200,16 +468,185 @@ impl DatabaseManager {
         Ok(row.get("count"))
     }
     
+    // Document chunk operations (semantic retrieval index)
+    /// Store a document's chunks, keeping the id of any chunk already stored at the same position
+    /// so that questions generated from it stay linked
+    pub async fn replace_document_chunks(&self, document_id: &str, chunks: &[(DocumentChunk, Vec<u8>)]) -> Result<(), sqlx::Error> {
+        let mut tx = self.pool.begin().await?;
+        
+        sqlx::query("DELETE FROM document_chunks WHERE document_id = ? AND chunk_index >= ?")
+            .bind(document_id)
+            .bind(chunks.len() as i64)
+            .execute(&mut *tx)
+            .await?;
+        
+        for (chunk, embedding) in chunks {
+            sqlx::query(
+                "INSERT INTO document_chunks (id, document_id, knowledge_base_id, chunk_index, content, start_offset, end_offset, page_number, embedding, created_at) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+                 ON CONFLICT(document_id, chunk_index) DO UPDATE SET content = excluded.content, start_offset = excluded.start_offset, end_offset = excluded.end_offset, page_number = excluded.page_number, embedding = excluded.embedding"
+            )
+            .bind(&chunk.id)
+            .bind(&chunk.document_id)
+            .bind(&chunk.knowledge_base_id)
+            .bind(chunk.chunk_index)
+            .bind(&chunk.content)
+            .bind(chunk.start_offset)
+            .bind(chunk.end_offset)
+            .bind(chunk.page_number)
+            .bind(embedding)
+            .bind(chunk.created_at)
+            .execute(&mut *tx)
+            .await?;
+        }
+        
+        // Marks the document indexed even when it yielded no chunks
+        sqlx::query("UPDATE documents SET indexed_at = ? WHERE id = ?")
+            .bind(Utc::now())
+            .bind(document_id)
+            .execute(&mut *tx)
+            .await?;
+        
+        tx.commit().await?;
+        
+        Ok(())
+    }
+    
+    pub async fn get_chunks_by_document(&self, document_id: &str) -> Result<Vec<DocumentChunk>, sqlx::Error> {
+        let rows = sqlx::query_as::<_, DocumentChunk>(
+            "SELECT id, document_id, knowledge_base_id, chunk_index, content, start_offset, end_offset, page_number, created_at FROM document_chunks WHERE document_id = ? ORDER BY chunk_index"
+        )
+        .bind(document_id)
+        .fetch_all(&self.pool)
+        .await?;
+        
+        Ok(rows)
+    }
+    
+    pub async fn get_chunks_by_knowledge_base(&self, knowledge_base_id: &str, document_id: Option<&str>) -> Result<Vec<DocumentChunk>, sqlx::Error> {
+        let rows = sqlx::query_as::<_, DocumentChunk>(
+            "SELECT id, document_id, knowledge_base_id, chunk_index, content, start_offset, end_offset, page_number, created_at FROM document_chunks
+             WHERE knowledge_base_id = ? AND (? IS NULL OR document_id = ?)
+             ORDER BY document_id, chunk_index"
+        )
+        .bind(knowledge_base_id)
+        .bind(document_id)
+        .bind(document_id)
+        .fetch_all(&self.pool)
+        .await?;
+        
+        Ok(rows)
+    }
+    
+    pub async fn get_chunk_by_id(&self, id: &str) -> Result<Option<DocumentChunk>, sqlx::Error> {
+        let row = sqlx::query_as::<_, DocumentChunk>(
+            "SELECT id, document_id, knowledge_base_id, chunk_index, content, start_offset, end_offset, page_number, created_at FROM document_chunks WHERE id = ?"
+        )
+        .bind(id)
+        .fetch_optional(&self.pool)
+        .await?;
+        
+        Ok(row)
+    }
+    
+    // Number of questions generated from each chunk of a knowledge base
+    pub async fn get_question_counts_by_chunk(&self, knowledge_base_id: &str) -> Result<HashMap<String, i64>, sqlx::Error> {
+        let rows = sqlx::query(
+            "SELECT chunk_id, COUNT(*) as count FROM questions WHERE knowledge_base_id = ? AND chunk_id IS NOT NULL GROUP BY chunk_id"
+        )
+        .bind(knowledge_base_id)
+        .fetch_all(&self.pool)
+        .await?;
+        
+        Ok(rows.into_iter().map(|row| (row.get("chunk_id"), row.get("count"))).collect())
+    }
+    
+    // Per-chunk question/answer aggregates, optionally restricted to one document
+    pub async fn get_chunk_coverage(&self, knowledge_base_id: &str, document_id: Option<&str>) -> Result<Vec<ChunkCoverage>, sqlx::Error> {
+        let rows = sqlx::query_as::<_, ChunkCoverage>(
+            "SELECT c.id as chunk_id, c.document_id, c.chunk_index, c.page_number, c.start_offset, c.end_offset,
+                    substr(c.content, 1, 80) as preview,
+                    COUNT(DISTINCT q.id) as question_count,
+                    COUNT(a.id) as answer_count,
+                    AVG(a.ai_score) as average_score
+             FROM document_chunks c
+             LEFT JOIN questions q ON q.chunk_id = c.id
+             LEFT JOIN answers a ON a.question_id = q.id
+             WHERE c.knowledge_base_id = ? AND (? IS NULL OR c.document_id = ?)
+             GROUP BY c.id
+             ORDER BY c.document_id, c.chunk_index"
+        )
+        .bind(knowledge_base_id)
+        .bind(document_id)
+        .bind(document_id)
+        .fetch_all(&self.pool)
+        .await?;
+        
+        Ok(rows)
+    }
+    
+    pub async fn get_chunk_embeddings_by_knowledge_base(&self, knowledge_base_id: &str) -> Result<Vec<(DocumentChunk, Vec<u8>)>, sqlx::Error> {
+        let rows = sqlx::query(
+            "SELECT id, document_id, knowledge_base_id, chunk_index, content, start_offset, end_offset, page_number, embedding, created_at FROM document_chunks WHERE knowledge_base_id = ?"
+        )
+        .bind(knowledge_base_id)
+        .fetch_all(&self.pool)
+        .await?;
+        
+        let chunks = rows.into_iter().map(|row| {
+            let chunk = DocumentChunk {
+                id: row.get("id"),
+                document_id: row.get("document_id"),
+                knowledge_base_id: row.get("knowledge_base_id"),
+                chunk_index: row.get("chunk_index"),
+                content: row.get("content"),
+                start_offset: row.get("start_offset"),
+                end_offset: row.get("end_offset"),
+                page_number: row.get("page_number"),
+                created_at: row.get("created_at"),
+            };
+            
+            (chunk, row.get("embedding"))
+        }).collect();
+        
+        Ok(chunks)
+    }
+    
+    pub async fn get_unindexed_documents(&self, knowledge_base_id: &str) -> Result<Vec<String>, sqlx::Error> {
+        let rows = sqlx::query(
+            "SELECT d.id FROM documents d
+             WHERE d.knowledge_base_id = ?
+             AND d.content_text IS NOT NULL
+             AND d.indexed_at IS NULL"
+        )
+        .bind(knowledge_base_id)
+        .fetch_all(&self.pool)
+        .await?;
+        
+        Ok(rows.into_iter().map(|row| row.get("id")).collect())
+    }
+    
     // Question and Answer CRUD operations
     pub async fn save_question(&self, question: &Question) -> Result<(), sqlx::Error> {
         sqlx::query(
-            "INSERT INTO questions (id, knowledge_base_id, question_text, context_snippet, generated_at) VALUES (?, ?, ?, ?, ?)"
+            "INSERT INTO questions (id, knowledge_base_id, question_text, context_snippet, generated_at, document_id, chunk_id, page_number, served_by, question_type, payload, difficulty, cognitive_level, status, batch_id, reference_answer, key_points) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)"
         )
         .bind(&question.id)
         .bind(&question.knowledge_base_id)
         .bind(&question.question_text)
         .bind(&question.context_snippet)
-        .bind(&question.generated_at)
+        .bind(question.generated_at)
+        .bind(&question.document_id)
+        .bind(&question.chunk_id)
+        .bind(question.page_number)
+        .bind(&question.served_by)
+        .bind(question.question_type)
+        .bind(&question.payload)
+        .bind(question.difficulty)
+        .bind(question.cognitive_level)
+        .bind(question.status)
+        .bind(&question.batch_id)
+        .bind(&question.reference_answer)
+        .bind(&question.key_points)
         .execute(&self.pool)
         .await?;
         
@@ -218,15 +655,23 @@ impl DatabaseManager {
     
     pub async fn save_answer(&self, answer: &Answer) -> Result<(), sqlx::Error> {
         sqlx::query(
-            "INSERT INTO answers (id, question_id, user_answer, ai_score, ai_feedback, ai_suggestions, answered_at) VALUES (?, ?, ?, ?, ?, ?, ?)"
+            "INSERT INTO answers (id, question_id, user_answer, ai_score, ai_feedback, ai_suggestions, answered_at, evidence, served_by, accuracy_score, completeness_score, depth_score, clarity_score, matched_key_points, missed_key_points) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)"
         )
         .bind(&answer.id)
         .bind(&answer.question_id)
         .bind(&answer.user_answer)
-        .bind(&answer.ai_score)
+        .bind(answer.ai_score)
         .bind(&answer.ai_feedback)
         .bind(&answer.ai_suggestions)
-        .bind(&answer.answered_at)
+        .bind(answer.answered_at)
+        .bind(&answer.evidence)
+        .bind(&answer.served_by)
+        .bind(answer.accuracy_score)
+        .bind(answer.completeness_score)
+        .bind(answer.depth_score)
+        .bind(answer.clarity_score)
+        .bind(&answer.matched_key_points)
+        .bind(&answer.missed_key_points)
         .execute(&self.pool)
         .await?;
         
@@ -235,7 +680,7 @@ impl DatabaseManager {
     
     pub async fn get_questions_by_knowledge_base(&self, knowledge_base_id: &str) -> Result<Vec<Question>, sqlx::Error> {
         let rows = sqlx::query_as::<_, Question>(
-            "SELECT id, knowledge_base_id, question_text, context_snippet, generated_at FROM questions WHERE knowledge_base_id = ? ORDER BY generated_at DESC"
+            &format!("SELECT {} FROM questions WHERE knowledge_base_id = ? ORDER BY generated_at DESC", QUESTION_COLUMNS)
         )
         .bind(knowledge_base_id)
         .fetch_all(&self.pool)
@@ -246,7 +691,7 @@ impl DatabaseManager {
     
     pub async fn get_question_by_id(&self, id: &str) -> Result<Option<Question>, sqlx::Error> {
         let row = sqlx::query_as::<_, Question>(
-            "SELECT id, knowledge_base_id, question_text, context_snippet, generated_at FROM questions WHERE id = ?"
+            &format!("SELECT {} FROM questions WHERE id = ?", QUESTION_COLUMNS)
         )
         .bind(id)
         .fetch_optional(&self.pool)
@@ -257,7 +702,7 @@ impl DatabaseManager {
     
     pub async fn get_answers_by_question(&self, question_id: &str) -> Result<Vec<Answer>, sqlx::Error> {
         let rows = sqlx::query_as::<_, Answer>(
-            "SELECT id, question_id, user_answer, ai_score, ai_feedback, ai_suggestions, answered_at FROM answers WHERE question_id = ? ORDER BY answered_at DESC"
+            &format!("SELECT {} FROM answers WHERE question_id = ? ORDER BY answered_at DESC", ANSWER_COLUMNS)
         )
         .bind(question_id)
         .fetch_all(&self.pool)
@@ -266,6 +711,226 @@ impl DatabaseManager {
         Ok(rows)
     }
     
+    pub async fn get_answer_by_id(&self, id: &str) -> Result<Option<Answer>, sqlx::Error> {
+        let row = sqlx::query_as::<_, Answer>(
+            &format!("SELECT {} FROM answers WHERE id = ?", ANSWER_COLUMNS)
+        )
+        .bind(id)
+        .fetch_optional(&self.pool)
+        .await?;
+        
+        Ok(row)
+    }
+    
+    pub async fn save_answer_message(&self, message: &AnswerMessage) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            &format!("INSERT INTO answer_messages ({}) VALUES (?, ?, ?, ?, ?, ?)", ANSWER_MESSAGE_COLUMNS)
+        )
+        .bind(&message.id)
+        .bind(&message.answer_id)
+        .bind(message.role)
+        .bind(&message.content)
+        .bind(&message.served_by)
+        .bind(message.created_at)
+        .execute(&self.pool)
+        .await?;
+        
+        Ok(())
+    }
+    
+    /// The tutoring thread of an answer, oldest message first
+    pub async fn get_answer_messages(&self, answer_id: &str) -> Result<Vec<AnswerMessage>, sqlx::Error> {
+        let rows = sqlx::query_as::<_, AnswerMessage>(
+            &format!("SELECT {} FROM answer_messages WHERE answer_id = ? ORDER BY created_at, rowid", ANSWER_MESSAGE_COLUMNS)
+        )
+        .bind(answer_id)
+        .fetch_all(&self.pool)
+        .await?;
+        
+        Ok(rows)
+    }
+    
+    /// The tutoring threads of every answer in a knowledge base, oldest message first
+    pub async fn get_answer_messages_by_knowledge_base(&self, knowledge_base_id: &str) -> Result<Vec<AnswerMessage>, sqlx::Error> {
+        let rows = sqlx::query_as::<_, AnswerMessage>(
+            "SELECT m.id, m.answer_id, m.role, m.content, m.served_by, m.created_at
+             FROM answer_messages m
+             JOIN answers a ON a.id = m.answer_id
+             JOIN questions q ON q.id = a.question_id
+             WHERE q.knowledge_base_id = ?
+             ORDER BY m.created_at, m.rowid"
+        )
+        .bind(knowledge_base_id)
+        .fetch_all(&self.pool)
+        .await?;
+        
+        Ok(rows)
+    }
+    
+    /// Questions of a knowledge base with how often each was answered, newest first
+    pub async fn get_question_bank(
+        &self,
+        knowledge_base_id: &str,
+        status: Option<QuestionStatus>,
+        batch_id: Option<&str>,
+    ) -> Result<Vec<(Question, i64)>, sqlx::Error> {
+        let rows = sqlx::query(&format!(
+            "SELECT {}, (SELECT COUNT(*) FROM answers a WHERE a.question_id = questions.id) as answer_count
+             FROM questions
+             WHERE knowledge_base_id = ? AND (? IS NULL OR status = ?) AND (? IS NULL OR batch_id = ?)
+             ORDER BY generated_at DESC",
+            QUESTION_COLUMNS
+        ))
+        .bind(knowledge_base_id)
+        .bind(status)
+        .bind(status)
+        .bind(batch_id)
+        .bind(batch_id)
+        .fetch_all(&self.pool)
+        .await?;
+        
+        rows.iter()
+            .map(|row| Ok((Question::from_row(row)?, row.get("answer_count"))))
+            .collect()
+    }
+    
+    /// An approved question of the knowledge base that has not been answered yet, picked at random
+    pub async fn get_unanswered_question(&self, knowledge_base_id: &str) -> Result<Option<Question>, sqlx::Error> {
+        sqlx::query_as::<_, Question>(&format!(
+            "SELECT {} FROM questions
+             WHERE knowledge_base_id = ? AND status = 'approved'
+               AND NOT EXISTS (SELECT 1 FROM answers a WHERE a.question_id = questions.id)
+             ORDER BY RANDOM() LIMIT 1",
+            QUESTION_COLUMNS
+        ))
+        .bind(knowledge_base_id)
+        .fetch_optional(&self.pool)
+        .await
+    }
+    
+    /// Save edits to a question's text, answer key, reference answer, targeting and status
+    pub async fn update_question(&self, question: &Question) -> Result<bool, sqlx::Error> {
+        let result = sqlx::query(
+            "UPDATE questions SET question_text = ?, question_type = ?, payload = ?, difficulty = ?, cognitive_level = ?, status = ?, reference_answer = ?, key_points = ? WHERE id = ?"
+        )
+        .bind(&question.question_text)
+        .bind(question.question_type)
+        .bind(&question.payload)
+        .bind(question.difficulty)
+        .bind(question.cognitive_level)
+        .bind(question.status)
+        .bind(&question.reference_answer)
+        .bind(&question.key_points)
+        .bind(&question.id)
+        .execute(&self.pool)
+        .await?;
+        
+        Ok(result.rows_affected() > 0)
+    }
+    
+    /// Approve draft questions of a knowledge base, returning how many were drafts
+    pub async fn approve_questions(&self, knowledge_base_id: &str, question_ids: &[String]) -> Result<u64, sqlx::Error> {
+        let mut tx = self.pool.begin().await?;
+        let mut approved = 0;
+        
+        for question_id in question_ids {
+            approved += sqlx::query(
+                "UPDATE questions SET status = 'approved' WHERE id = ? AND knowledge_base_id = ? AND status = 'draft'"
+            )
+            .bind(question_id)
+            .bind(knowledge_base_id)
+            .execute(&mut *tx)
+            .await?
+            .rows_affected();
+        }
+        
+        tx.commit().await?;
+        Ok(approved)
+    }
+    
+    /// Delete a question together with its answers
+    pub async fn delete_question(&self, id: &str) -> Result<bool, sqlx::Error> {
+        let result = sqlx::query("DELETE FROM questions WHERE id = ?")
+            .bind(id)
+            .execute(&self.pool)
+            .await?;
+        
+        Ok(result.rows_affected() > 0)
+    }
+    
+    // Question batch jobs
+    pub async fn create_question_batch(&self, batch: &QuestionBatch) -> Result<(), sqlx::Error> {
+        sqlx::query(&format!(
+            "INSERT INTO question_batches ({}) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
+            QUESTION_BATCH_COLUMNS
+        ))
+        .bind(&batch.id)
+        .bind(&batch.knowledge_base_id)
+        .bind(batch.status)
+        .bind(batch.requested)
+        .bind(batch.generated)
+        .bind(batch.duplicates)
+        .bind(batch.failures)
+        .bind(batch.question_type)
+        .bind(batch.difficulty)
+        .bind(batch.cognitive_level)
+        .bind(&batch.document_id)
+        .bind(&batch.error)
+        .bind(batch.created_at)
+        .bind(batch.finished_at)
+        .execute(&self.pool)
+        .await?;
+        
+        Ok(())
+    }
+    
+    /// Save a running batch's progress or outcome
+    pub async fn update_question_batch(&self, batch: &QuestionBatch) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            "UPDATE question_batches SET status = ?, generated = ?, duplicates = ?, failures = ?, error = ?, finished_at = ? WHERE id = ?"
+        )
+        .bind(batch.status)
+        .bind(batch.generated)
+        .bind(batch.duplicates)
+        .bind(batch.failures)
+        .bind(&batch.error)
+        .bind(batch.finished_at)
+        .bind(&batch.id)
+        .execute(&self.pool)
+        .await?;
+        
+        Ok(())
+    }
+    
+    pub async fn get_question_batch(&self, id: &str) -> Result<Option<QuestionBatch>, sqlx::Error> {
+        sqlx::query_as::<_, QuestionBatch>(&format!("SELECT {} FROM question_batches WHERE id = ?", QUESTION_BATCH_COLUMNS))
+            .bind(id)
+            .fetch_optional(&self.pool)
+            .await
+    }
+    
+    pub async fn get_question_batches(&self, knowledge_base_id: &str) -> Result<Vec<QuestionBatch>, sqlx::Error> {
+        sqlx::query_as::<_, QuestionBatch>(&format!(
+            "SELECT {} FROM question_batches WHERE knowledge_base_id = ? ORDER BY created_at DESC",
+            QUESTION_BATCH_COLUMNS
+        ))
+        .bind(knowledge_base_id)
+        .fetch_all(&self.pool)
+        .await
+    }
+    
+    /// Mark batches still running from before a restart as failed; their jobs are gone
+    pub async fn fail_interrupted_question_batches(&self) -> Result<u64, sqlx::Error> {
+        let result = sqlx::query(
+            "UPDATE question_batches SET status = 'failed', error = 'Interrupted by a server restart', finished_at = ? WHERE status = 'running'"
+        )
+        .bind(Utc::now())
+        .execute(&self.pool)
+        .await?;
+        
+        Ok(result.rows_affected())
+    }
+    
     // Review Session CRUD operations
     pub async fn save_review_session(&self, session: &ReviewSession) -> Result<(), sqlx::Error> {
         sqlx::query(
@@ -273,9 +938,9 @@ impl DatabaseManager {
         )
         .bind(&session.id)
         .bind(&session.knowledge_base_id)
-        .bind(&session.questions_count)
-        .bind(&session.average_score)
-        .bind(&session.session_date)
+        .bind(session.questions_count)
+        .bind(session.average_score)
+        .bind(session.session_date)
         .execute(&self.pool)
         .await?;
         
@@ -298,51 +963,30 @@ impl DatabaseManager {
         let limit = limit.unwrap_or(50);
         let offset = offset.unwrap_or(0);
         
-        let rows = sqlx::query(
-            "SELECT q.id as question_id, q.knowledge_base_id, q.question_text, q.context_snippet, q.generated_at,
-                    a.id as answer_id, a.user_answer, a.ai_score, a.ai_feedback, a.ai_suggestions, a.answered_at
+        let rows = sqlx::query(&format!(
+            "SELECT {}
              FROM questions q 
              INNER JOIN answers a ON q.id = a.question_id 
              WHERE q.knowledge_base_id = ? 
              ORDER BY a.answered_at DESC 
-             LIMIT ? OFFSET ?"
-        )
+             LIMIT ? OFFSET ?",
+            HISTORY_COLUMNS
+        ))
         .bind(knowledge_base_id)
         .bind(limit)
         .bind(offset)
         .fetch_all(&self.pool)
         .await?;
         
-        let history = rows.into_iter().map(|row| {
-            let question = Question {
-                id: row.get("question_id"),
-                knowledge_base_id: row.get("knowledge_base_id"),
-                question_text: row.get("question_text"),
-                context_snippet: row.get("context_snippet"),
-                generated_at: row.get("generated_at"),
-            };
-            
-            let answer = Answer {
-                id: row.get("answer_id"),
-                question_id: row.get("question_id"),
-                user_answer: row.get("user_answer"),
-                ai_score: row.get("ai_score"),
-                ai_feedback: row.get("ai_feedback"),
-                ai_suggestions: row.get("ai_suggestions"),
-                answered_at: row.get("answered_at"),
-            };
-            
-            (question, answer)
-        }).collect();
+        let history = rows.into_iter().map(|row| history_item_from_row(&row)).collect();
         
         Ok(history)
     }
     
     pub async fn get_filtered_history(&self, knowledge_base_id: &str, min_score: Option<i32>, max_score: Option<i32>, start_date: Option<chrono::DateTime<Utc>>, end_date: Option<chrono::DateTime<Utc>>) -> Result<Vec<(Question, Answer)>, sqlx::Error> {
         // Use a simpler approach with fixed parameters and NULL checks
-        let rows = sqlx::query(
-            "SELECT q.id as question_id, q.knowledge_base_id, q.question_text, q.context_snippet, q.generated_at,
-                    a.id as answer_id, a.user_answer, a.ai_score, a.ai_feedback, a.ai_suggestions, a.answered_at
+        let rows = sqlx::query(&format!(
+            "SELECT {}
              FROM questions q 
              INNER JOIN answers a ON q.id = a.question_id 
              WHERE q.knowledge_base_id = ? 
@@ -350,8 +994,9 @@ impl DatabaseManager {
              AND (? IS NULL OR a.ai_score <= ?)
              AND (? IS NULL OR a.answered_at >= ?)
              AND (? IS NULL OR a.answered_at <= ?)
-             ORDER BY a.answered_at DESC"
-        )
+             ORDER BY a.answered_at DESC",
+            HISTORY_COLUMNS
+        ))
         .bind(knowledge_base_id)
         .bind(min_score)
         .bind(min_score)
@@ -364,27 +1009,7 @@ impl DatabaseManager {
         .fetch_all(&self.pool)
         .await?;
         
-        let history = rows.into_iter().map(|row| {
-            let question = Question {
-                id: row.get("question_id"),
-                knowledge_base_id: row.get("knowledge_base_id"),
-                question_text: row.get("question_text"),
-                context_snippet: row.get("context_snippet"),
-                generated_at: row.get("generated_at"),
-            };
-            
-            let answer = Answer {
-                id: row.get("answer_id"),
-                question_id: row.get("question_id"),
-                user_answer: row.get("user_answer"),
-                ai_score: row.get("ai_score"),
-                ai_feedback: row.get("ai_feedback"),
-                ai_suggestions: row.get("ai_suggestions"),
-                answered_at: row.get("answered_at"),
-            };
-            
-            (question, answer)
-        }).collect();
+        let history = rows.into_iter().map(|row| history_item_from_row(&row)).collect();
         
         Ok(history)
     }
@@ -414,41 +1039,21 @@ impl DatabaseManager {
     
     // Get random questions from history for review
     pub async fn get_random_review_questions(&self, knowledge_base_id: &str, count: i32) -> Result<Vec<(Question, Answer)>, sqlx::Error> {
-        let history = sqlx::query(
-            "SELECT q.id as question_id, q.knowledge_base_id, q.question_text, q.context_snippet, q.generated_at,
-                    a.id as answer_id, a.user_answer, a.ai_score, a.ai_feedback, a.ai_suggestions, a.answered_at
+        let history = sqlx::query(&format!(
+            "SELECT {}
              FROM questions q 
              INNER JOIN answers a ON q.id = a.question_id 
              WHERE q.knowledge_base_id = ? 
              ORDER BY RANDOM() 
-             LIMIT ?"
-        )
+             LIMIT ?",
+            HISTORY_COLUMNS
+        ))
         .bind(knowledge_base_id)
         .bind(count)
         .fetch_all(&self.pool)
         .await?;
         
-        let questions = history.into_iter().map(|row| {
-            let question = Question {
-                id: row.get("question_id"),
-                knowledge_base_id: row.get("knowledge_base_id"),
-                question_text: row.get("question_text"),
-                context_snippet: row.get("context_snippet"),
-                generated_at: row.get("generated_at"),
-            };
-            
-            let answer = Answer {
-                id: row.get("answer_id"),
-                question_id: row.get("question_id"),
-                user_answer: row.get("user_answer"),
-                ai_score: row.get("ai_score"),
-                ai_feedback: row.get("ai_feedback"),
-                ai_suggestions: row.get("ai_suggestions"),
-                answered_at: row.get("answered_at"),
-            };
-            
-            (question, answer)
-        }).collect();
+        let questions = history.into_iter().map(|row| history_item_from_row(&row)).collect();
         
         Ok(questions)
     }
@@ -510,67 +1115,430 @@ impl DatabaseManager {
             None
         };
         
+        // Break the scores down by criterion to show where the learner is weakest
+        let criteria: Vec<CriterionScores> = history.iter()
+            .filter_map(|(_, answer)| answer.criteria())
+            .collect();
+        let criterion_averages = CriterionAverages::of(&criteria);
+        
         Ok(LearningProgress {
             total_questions_answered: total_answered,
             average_score: avg_score,
             recent_average_score: recent_average,
             improvement_trend,
             total_review_sessions: 0, // Will be calculated separately if needed
+            criterion_averages,
+            weakest_criterion: criterion_averages.map(|averages| averages.weakest()),
         })
     }
     
-    // AI Config CRUD operations
-    pub async fn save_ai_config(&self, config: &AIConfig) -> Result<(), sqlx::Error> {
-        let provider_str = config.provider.to_string();
+    // AI profile operations; each ai_config row is a named profile
+    
+    /// Store `config` as the settings of the default profile, creating it if there is none
+    pub async fn save_ai_config(&self, config: &AIConfig) -> Result<AIConfig, sqlx::Error> {
+        let mut saved = config.clone();
+        saved.is_default = true;
+        
+        match self.get_ai_config().await? {
+            Some(existing) => {
+                saved.id = existing.id;
+                saved.name = existing.name;
+                self.update_ai_profile(&saved).await?;
+            }
+            None => saved.id = Some(self.create_ai_profile(&saved).await?),
+        }
+        
+        Ok(saved)
+    }
+    
+    /// The default profile
+    pub async fn get_ai_config(&self) -> Result<Option<AIConfig>, sqlx::Error> {
+        let row = sqlx::query(&format!(
+            "SELECT {} FROM ai_config WHERE is_default = 1 ORDER BY updated_at DESC LIMIT 1",
+            AI_CONFIG_COLUMNS
+        ))
+        .fetch_optional(&self.pool)
+        .await?;
         
-        // Delete existing config first (since we only want one config)
-        sqlx::query("DELETE FROM ai_config")
-            .execute(&self.pool)
+        row.as_ref().map(ai_config_from_row).map(|config| self.open_secrets(config)).transpose()
+    }
+    
+    pub async fn get_ai_profiles(&self) -> Result<Vec<AIConfig>, sqlx::Error> {
+        let rows = sqlx::query(&format!(
+            "SELECT {} FROM ai_config ORDER BY is_default DESC, name",
+            AI_CONFIG_COLUMNS
+        ))
+        .fetch_all(&self.pool)
+        .await?;
+        
+        rows.iter().map(|row| self.open_secrets(ai_config_from_row(row))).collect()
+    }
+    
+    pub async fn get_ai_profile(&self, id: i32) -> Result<Option<AIConfig>, sqlx::Error> {
+        let row = sqlx::query(&format!("SELECT {} FROM ai_config WHERE id = ?", AI_CONFIG_COLUMNS))
+            .bind(id)
+            .fetch_optional(&self.pool)
             .await?;
         
-        sqlx::query(
-            "INSERT INTO ai_config (provider, api_key, api_url, model_name, max_tokens, temperature, updated_at) VALUES (?, ?, ?, ?, ?, ?, ?)"
+        row.as_ref().map(ai_config_from_row).map(|config| self.open_secrets(config)).transpose()
+    }
+    
+    /// Insert a new profile and return its id; a new default replaces the previous one
+    pub async fn create_ai_profile(&self, config: &AIConfig) -> Result<i32, sqlx::Error> {
+        let mut tx = self.pool.begin().await?;
+        
+        if config.is_default {
+            sqlx::query("UPDATE ai_config SET is_default = 0")
+                .execute(&mut *tx)
+                .await?;
+        }
+        
+        let result = sqlx::query(
+            "INSERT INTO ai_config (name, is_default, provider, api_key, api_url, model_name, max_tokens, temperature,
+                                    provider_options, connect_timeout_secs, request_timeout_secs, max_retries,
+                                    fallback_profile_ids, monthly_budget, updated_at)
+             VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)"
         )
-        .bind(&provider_str)
-        .bind(&config.api_key)
+        .bind(&config.name)
+        .bind(config.is_default)
+        .bind(config.provider.to_string())
+        .bind(self.seal_secret(config.api_key.as_deref())?)
         .bind(&config.api_url)
         .bind(&config.model_name)
-        .bind(&config.max_tokens)
-        .bind(&config.temperature)
-        .bind(&config.updated_at)
+        .bind(config.max_tokens)
+        .bind(config.temperature)
+        .bind(&config.provider_options)
+        .bind(config.connect_timeout_secs)
+        .bind(config.request_timeout_secs)
+        .bind(config.max_retries)
+        .bind(encode_profile_ids(&config.fallback_profile_ids))
+        .bind(config.monthly_budget)
+        .bind(config.updated_at)
+        .execute(&mut *tx)
+        .await?;
+        
+        tx.commit().await?;
+        
+        Ok(result.last_insert_rowid() as i32)
+    }
+    
+    /// Overwrite the profile with `config.id`; making it the default unsets the previous one
+    pub async fn update_ai_profile(&self, config: &AIConfig) -> Result<bool, sqlx::Error> {
+        let mut tx = self.pool.begin().await?;
+        
+        if config.is_default {
+            sqlx::query("UPDATE ai_config SET is_default = 0 WHERE id != ?")
+                .bind(config.id)
+                .execute(&mut *tx)
+                .await?;
+        }
+        
+        let result = sqlx::query(
+            "UPDATE ai_config SET name = ?, is_default = ?, provider = ?, api_key = ?, api_url = ?, model_name = ?,
+                    max_tokens = ?, temperature = ?, provider_options = ?, connect_timeout_secs = ?,
+                    request_timeout_secs = ?, max_retries = ?, fallback_profile_ids = ?, monthly_budget = ?, updated_at = ?
+             WHERE id = ?"
+        )
+        .bind(&config.name)
+        .bind(config.is_default)
+        .bind(config.provider.to_string())
+        .bind(self.seal_secret(config.api_key.as_deref())?)
+        .bind(&config.api_url)
+        .bind(&config.model_name)
+        .bind(config.max_tokens)
+        .bind(config.temperature)
+        .bind(&config.provider_options)
+        .bind(config.connect_timeout_secs)
+        .bind(config.request_timeout_secs)
+        .bind(config.max_retries)
+        .bind(encode_profile_ids(&config.fallback_profile_ids))
+        .bind(config.monthly_budget)
+        .bind(config.updated_at)
+        .bind(config.id)
+        .execute(&mut *tx)
+        .await?;
+        
+        tx.commit().await?;
+        
+        Ok(result.rows_affected() > 0)
+    }
+    
+    /// Delete a profile; selections that pointed at it are removed with it
+    pub async fn delete_ai_profile(&self, id: i32) -> Result<bool, sqlx::Error> {
+        let result = sqlx::query("DELETE FROM ai_config WHERE id = ?")
+            .bind(id)
+            .execute(&self.pool)
+            .await?;
+        
+        Ok(result.rows_affected() > 0)
+    }
+    
+    /// Profiles chosen for a knowledge base, or the global choice when `knowledge_base_id` is `None`
+    pub async fn get_ai_profile_selection(&self, knowledge_base_id: Option<&str>) -> Result<AIProfileSelection, sqlx::Error> {
+        let rows = sqlx::query(
+            "SELECT task, profile_id FROM ai_profile_selections WHERE knowledge_base_id IS ?"
+        )
+        .bind(knowledge_base_id)
+        .fetch_all(&self.pool)
+        .await?;
+        
+        let mut selection = AIProfileSelection::default();
+        for row in rows {
+            let profile_id = Some(row.get("profile_id"));
+            match row.get::<String, _>("task").as_str() {
+                "question" => selection.question_profile_id = profile_id,
+                "evaluation" => selection.evaluation_profile_id = profile_id,
+                _ => {}
+            }
+        }
+        
+        Ok(selection)
+    }
+    
+    pub async fn set_ai_profile_selection(
+        &self,
+        knowledge_base_id: Option<&str>,
+        selection: &AIProfileSelection,
+    ) -> Result<(), sqlx::Error> {
+        let mut tx = self.pool.begin().await?;
+        
+        sqlx::query("DELETE FROM ai_profile_selections WHERE knowledge_base_id IS ?")
+            .bind(knowledge_base_id)
+            .execute(&mut *tx)
+            .await?;
+        
+        for task in [PromptKind::Question, PromptKind::Evaluation] {
+            if let Some(profile_id) = selection.profile_for(task) {
+                sqlx::query("INSERT INTO ai_profile_selections (knowledge_base_id, task, profile_id) VALUES (?, ?, ?)")
+                    .bind(knowledge_base_id)
+                    .bind(task.to_string())
+                    .bind(profile_id)
+                    .execute(&mut *tx)
+                    .await?;
+            }
+        }
+        
+        tx.commit().await
+    }
+    
+    /// The profile serving `task`: the knowledge base's choice, else the global choice, else the default
+    pub async fn resolve_ai_profile(
+        &self,
+        knowledge_base_id: Option<&str>,
+        task: PromptKind,
+    ) -> Result<Option<AIConfig>, sqlx::Error> {
+        let row = sqlx::query(&format!(
+            "SELECT {} FROM ai_config WHERE id = COALESCE(
+                (SELECT profile_id FROM ai_profile_selections WHERE knowledge_base_id = ? AND task = ?),
+                (SELECT profile_id FROM ai_profile_selections WHERE knowledge_base_id IS NULL AND task = ?),
+                (SELECT id FROM ai_config WHERE is_default = 1 ORDER BY updated_at DESC LIMIT 1))",
+            AI_CONFIG_COLUMNS
+        ))
+        .bind(knowledge_base_id)
+        .bind(task.to_string())
+        .bind(task.to_string())
+        .fetch_optional(&self.pool)
+        .await?;
+        
+        row.as_ref().map(ai_config_from_row).map(|config| self.open_secrets(config)).transpose()
+    }
+    
+    // AI usage and cost operations
+    
+    pub async fn record_ai_usage(&self, usage: &AIUsage) -> Result<i64, sqlx::Error> {
+        let result = sqlx::query(
+            "INSERT INTO ai_usage (profile_id, provider, model, task, knowledge_base_id, question_id, answer_id,
+                                   prompt_tokens, completion_tokens, created_at)
+             VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?)"
+        )
+        .bind(usage.profile_id)
+        .bind(&usage.provider)
+        .bind(&usage.model)
+        .bind(usage.task.to_string())
+        .bind(&usage.knowledge_base_id)
+        .bind(&usage.question_id)
+        .bind(&usage.answer_id)
+        .bind(usage.prompt_tokens)
+        .bind(usage.completion_tokens)
+        .bind(usage.created_at)
         .execute(&self.pool)
         .await?;
         
-        Ok(())
+        Ok(result.last_insert_rowid())
     }
     
-    pub async fn get_ai_config(&self) -> Result<Option<AIConfig>, sqlx::Error> {
-        let row = sqlx::query(
-            "SELECT id, provider, api_key, api_url, model_name, max_tokens, temperature, updated_at FROM ai_config ORDER BY updated_at DESC LIMIT 1"
+    /// Token totals and cost at the current prices, grouped by day, knowledge base or provider
+    pub async fn get_ai_usage_summary(
+        &self,
+        grouping: UsageGrouping,
+        start_date: Option<chrono::DateTime<Utc>>,
+        end_date: Option<chrono::DateTime<Utc>>,
+    ) -> Result<Vec<AIUsageSummary>, sqlx::Error> {
+        let key = match grouping {
+            UsageGrouping::Day => "substr(u.created_at, 1, 10)",
+            UsageGrouping::KnowledgeBase => "COALESCE(u.knowledge_base_id, '')",
+            UsageGrouping::Provider => "u.provider",
+        };
+        
+        let rows = sqlx::query(&format!(
+            "SELECT {} AS key, COUNT(*) AS calls,
+                    SUM(u.prompt_tokens) AS prompt_tokens, SUM(u.completion_tokens) AS completion_tokens,
+                    SUM({}) AS cost
+             {}
+             WHERE (? IS NULL OR u.created_at >= ?)
+             AND (? IS NULL OR u.created_at < ?)
+             GROUP BY key
+             ORDER BY key",
+            key, USAGE_COST, USAGE_WITH_PRICES
+        ))
+        .bind(start_date)
+        .bind(start_date)
+        .bind(end_date)
+        .bind(end_date)
+        .fetch_all(&self.pool)
+        .await?;
+        
+        Ok(rows
+            .iter()
+            .map(|row| AIUsageSummary {
+                key: row.get("key"),
+                calls: row.get("calls"),
+                prompt_tokens: row.get("prompt_tokens"),
+                completion_tokens: row.get("completion_tokens"),
+                cost: row.get("cost"),
+            })
+            .collect())
+    }
+    
+    /// What a profile's calls since `since` cost at the current prices
+    pub async fn get_ai_profile_cost(&self, profile_id: i32, since: chrono::DateTime<Utc>) -> Result<f64, sqlx::Error> {
+        let row = sqlx::query(&format!(
+            "SELECT COALESCE(SUM({}), 0.0) AS cost {} WHERE u.profile_id = ? AND u.created_at >= ?",
+            USAGE_COST, USAGE_WITH_PRICES
+        ))
+        .bind(profile_id)
+        .bind(since)
+        .fetch_one(&self.pool)
+        .await?;
+        
+        Ok(row.get("cost"))
+    }
+    
+    pub async fn get_ai_model_prices(&self) -> Result<Vec<AIModelPrice>, sqlx::Error> {
+        let rows = sqlx::query(
+            "SELECT provider, model, prompt_price, completion_price FROM ai_model_prices ORDER BY provider, model"
         )
+        .fetch_all(&self.pool)
+        .await?;
+        
+        Ok(rows
+            .iter()
+            .map(|row| AIModelPrice {
+                provider: row.get("provider"),
+                model: row.get("model"),
+                prompt_price: row.get("prompt_price"),
+                completion_price: row.get("completion_price"),
+            })
+            .collect())
+    }
+    
+    /// Replace the whole price list
+    pub async fn set_ai_model_prices(&self, prices: &[AIModelPrice]) -> Result<(), sqlx::Error> {
+        let mut tx = self.pool.begin().await?;
+        
+        sqlx::query("DELETE FROM ai_model_prices")
+            .execute(&mut *tx)
+            .await?;
+        
+        for price in prices {
+            sqlx::query(
+                "INSERT OR REPLACE INTO ai_model_prices (provider, model, prompt_price, completion_price) VALUES (?, ?, ?, ?)"
+            )
+            .bind(&price.provider)
+            .bind(&price.model)
+            .bind(price.prompt_price)
+            .bind(price.completion_price)
+            .execute(&mut *tx)
+            .await?;
+        }
+        
+        tx.commit().await
+    }
+    
+    // Prompt template operations
+    pub async fn get_prompt_templates(&self, kind: Option<PromptKind>) -> Result<Vec<PromptTemplate>, sqlx::Error> {
+        let rows = sqlx::query(&format!(
+            "SELECT {} FROM prompt_templates WHERE (? IS NULL OR kind = ?) ORDER BY kind, is_default DESC, name",
+            PROMPT_TEMPLATE_COLUMNS
+        ))
+        .bind(kind.map(|k| k.to_string()))
+        .bind(kind.map(|k| k.to_string()))
+        .fetch_all(&self.pool)
+        .await?;
+        
+        Ok(rows.iter().map(prompt_template_from_row).collect())
+    }
+    
+    pub async fn get_prompt_template_by_id(&self, id: &str) -> Result<Option<PromptTemplate>, sqlx::Error> {
+        let row = sqlx::query(&format!("SELECT {} FROM prompt_templates WHERE id = ?", PROMPT_TEMPLATE_COLUMNS))
+            .bind(id)
+            .fetch_optional(&self.pool)
+            .await?;
+        
+        Ok(row.as_ref().map(prompt_template_from_row))
+    }
+    
+    pub async fn get_default_prompt_template(&self, kind: PromptKind) -> Result<Option<PromptTemplate>, sqlx::Error> {
+        let row = sqlx::query(&format!(
+            "SELECT {} FROM prompt_templates WHERE kind = ? AND is_default = 1 LIMIT 1",
+            PROMPT_TEMPLATE_COLUMNS
+        ))
+        .bind(kind.to_string())
         .fetch_optional(&self.pool)
         .await?;
         
-        if let Some(row) = row {
-            let provider = match row.get::<String, _>("provider").as_str() {
-                "deepseek" => AIProvider::DeepSeek,
-                "local" => AIProvider::Local,
-                "openai" => AIProvider::OpenAI,
-                _ => AIProvider::DeepSeek, // Default fallback
-            };
-            
-            Ok(Some(AIConfig {
-                id: Some(row.get("id")),
-                provider,
-                api_key: row.get("api_key"),
-                api_url: row.get("api_url"),
-                model_name: row.get("model_name"),
-                max_tokens: row.get("max_tokens"),
-                temperature: row.get("temperature"),
-                updated_at: row.get("updated_at"),
-            }))
-        } else {
-            Ok(None)
+        Ok(row.as_ref().map(prompt_template_from_row))
+    }
+    
+    /// Insert or update a template; making it the default unsets the previous default of its kind
+    pub async fn save_prompt_template(&self, template: &PromptTemplate) -> Result<(), sqlx::Error> {
+        let mut tx = self.pool.begin().await?;
+        
+        if template.is_default {
+            sqlx::query("UPDATE prompt_templates SET is_default = 0 WHERE kind = ? AND id != ?")
+                .bind(template.kind.to_string())
+                .bind(&template.id)
+                .execute(&mut *tx)
+                .await?;
         }
+        
+        sqlx::query(
+            "INSERT INTO prompt_templates (id, name, kind, system_prompt, user_prompt, is_default, created_at, updated_at)
+             VALUES (?, ?, ?, ?, ?, ?, ?, ?)
+             ON CONFLICT(id) DO UPDATE SET
+                name = excluded.name, kind = excluded.kind, system_prompt = excluded.system_prompt,
+                user_prompt = excluded.user_prompt, is_default = excluded.is_default, updated_at = excluded.updated_at"
+        )
+        .bind(&template.id)
+        .bind(&template.name)
+        .bind(template.kind.to_string())
+        .bind(&template.system_prompt)
+        .bind(&template.user_prompt)
+        .bind(template.is_default)
+        .bind(template.created_at)
+        .bind(template.updated_at)
+        .execute(&mut *tx)
+        .await?;
+        
+        tx.commit().await
+    }
+    
+    pub async fn delete_prompt_template(&self, id: &str) -> Result<bool, sqlx::Error> {
+        let result = sqlx::query("DELETE FROM prompt_templates WHERE id = ?")
+            .bind(id)
+            .execute(&self.pool)
+            .await?;
+        
+        Ok(result.rows_affected() > 0)
     }
-}
\ No newline at end of file
+}
@@ -1,15 +1,16 @@
 #[cfg(test)]
 mod tests {
     use crate::database::{create_connection_pool, DatabaseManager};
-    use crate::models::{Document, DocumentType, Question, Answer, ReviewSession, AIConfig, AIProvider};
+    use crate::models::{Document, DocumentChunk, DocumentType, Question, QuestionBatch, BatchStatus, QuestionPayload, QuestionStatus, QuestionType, Answer, AnswerMessage, ThreadRole, ReviewSession, AIConfig, AIProvider, PromptKind, PromptTemplate};
+    use crate::services::secrets::SecretCipher;
     use sqlx::SqlitePool;
 
     async fn setup_test_db() -> SqlitePool {
         // Use in-memory database for tests
         let database_url = "sqlite::memory:";
         
-        let pool = create_connection_pool(database_url).await.unwrap();
-        pool
+        
+        create_connection_pool(database_url).await.unwrap()
     }
 
     #[tokio::test]
@@ -114,6 +115,96 @@ mod tests {
         assert_eq!(answers[0].user_answer, "Paris");
     }
 
+    #[tokio::test]
+    async fn test_answer_threads() {
+        let pool = setup_test_db().await;
+        let db = DatabaseManager::new(pool);
+        let kb = db.create_knowledge_base("Test KB", None).await.unwrap();
+        let other_kb = db.create_knowledge_base("Other KB", None).await.unwrap();
+
+        let question = Question::new(kb.id.clone(), "What is the capital of France?".to_string(), None);
+        db.save_question(&question).await.unwrap();
+        let answer = Answer::new(question.id.clone(), "Lyon".to_string());
+        db.save_answer(&answer).await.unwrap();
+        assert_eq!(db.get_answer_by_id(&answer.id).await.unwrap().unwrap().user_answer, "Lyon");
+        assert!(db.get_answer_by_id("missing").await.unwrap().is_none());
+
+        db.save_answer_message(&AnswerMessage::new(answer.id.clone(), ThreadRole::Learner, "Why not Lyon?".to_string())).await.unwrap();
+        let mut reply = AnswerMessage::new(answer.id.clone(), ThreadRole::Tutor, "Paris is the capital.".to_string());
+        reply.served_by = Some("mock".to_string());
+        db.save_answer_message(&reply).await.unwrap();
+
+        let thread = db.get_answer_messages(&answer.id).await.unwrap();
+        assert_eq!(thread.len(), 2);
+        assert_eq!(thread[0].role, ThreadRole::Learner);
+        assert_eq!(thread[1].content, "Paris is the capital.");
+        assert_eq!(thread[1].served_by.as_deref(), Some("mock"));
+
+        assert_eq!(db.get_answer_messages_by_knowledge_base(&kb.id).await.unwrap().len(), 2);
+        assert!(db.get_answer_messages_by_knowledge_base(&other_kb.id).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_structured_question_round_trip() {
+        let pool = setup_test_db().await;
+        let db = DatabaseManager::new(pool);
+        let kb = db.create_knowledge_base("Test KB", None).await.unwrap();
+
+        let key = QuestionPayload::Cloze { answers: vec!["Paris".to_string()] };
+        let question = Question::new(kb.id.clone(), "The capital of France is ____.".to_string(), None).with_payload(&key);
+        db.save_question(&question).await.unwrap();
+        db.save_answer(&Answer::new(question.id.clone(), "Paris".to_string())).await.unwrap();
+
+        let stored = db.get_question_by_id(&question.id).await.unwrap().unwrap();
+        assert_eq!(stored.question_type, QuestionType::Cloze);
+        assert_eq!(stored.answer_key(), Some(key.clone()));
+
+        let history = db.get_question_answer_history(&kb.id, None, None).await.unwrap();
+        assert_eq!(history[0].0.question_type, QuestionType::Cloze);
+        assert_eq!(history[0].0.answer_key(), Some(key));
+    }
+
+    #[tokio::test]
+    async fn test_question_bank_and_batches() {
+        let pool = setup_test_db().await;
+        let db = DatabaseManager::new(pool);
+        let kb = db.create_knowledge_base("Test KB", None).await.unwrap();
+
+        let batch = QuestionBatch::new(kb.id.clone(), 2);
+        db.create_question_batch(&batch).await.unwrap();
+
+        let mut draft = Question::new(kb.id.clone(), "What is ownership?".to_string(), None);
+        draft.status = QuestionStatus::Draft;
+        draft.batch_id = Some(batch.id.clone());
+        db.save_question(&draft).await.unwrap();
+        let answered = Question::new(kb.id.clone(), "What is borrowing?".to_string(), None);
+        db.save_question(&answered).await.unwrap();
+        db.save_answer(&Answer::new(answered.id.clone(), "Lending".to_string())).await.unwrap();
+
+        let bank = db.get_question_bank(&kb.id, None, None).await.unwrap();
+        assert_eq!(bank.len(), 2);
+        let drafts = db.get_question_bank(&kb.id, Some(QuestionStatus::Draft), Some(&batch.id)).await.unwrap();
+        assert_eq!(drafts.len(), 1);
+        assert_eq!(drafts[0].0.batch_id.as_deref(), Some(batch.id.as_str()));
+        assert_eq!(drafts[0].1, 0);
+
+        // Drafts and answered questions are never next
+        assert!(db.get_unanswered_question(&kb.id).await.unwrap().is_none());
+        assert_eq!(db.approve_questions(&kb.id, std::slice::from_ref(&draft.id)).await.unwrap(), 1);
+        assert_eq!(db.approve_questions(&kb.id, std::slice::from_ref(&draft.id)).await.unwrap(), 0);
+        assert_eq!(db.get_unanswered_question(&kb.id).await.unwrap().unwrap().id, draft.id);
+
+        // Batches left running by a previous process are failed
+        assert_eq!(db.fail_interrupted_question_batches().await.unwrap(), 1);
+        let stored = db.get_question_batch(&batch.id).await.unwrap().unwrap();
+        assert_eq!(stored.status, BatchStatus::Failed);
+        assert!(stored.error.is_some());
+
+        assert!(db.delete_question(&answered.id).await.unwrap());
+        assert!(!db.delete_question(&answered.id).await.unwrap());
+        assert!(db.get_answers_by_question(&answered.id).await.unwrap().is_empty());
+    }
+
     #[tokio::test]
     async fn test_review_session_crud() {
         let pool = setup_test_db().await;
@@ -179,6 +270,112 @@ mod tests {
         assert_eq!(updated_config.max_tokens, 2000);
     }
 
+    #[tokio::test]
+    async fn test_ai_profiles_keep_single_default() {
+        let pool = setup_test_db().await;
+        let db = DatabaseManager::new(pool);
+
+        // Saving the configuration updates the default profile in place
+        let first = db.save_ai_config(&AIConfig::new(AIProvider::Mock, None, None, None, 1000, 0.7)).await.unwrap();
+        let second = db.save_ai_config(&AIConfig::new(AIProvider::Mock, None, None, None, 500, 0.7)).await.unwrap();
+        assert_eq!(first.id, second.id);
+        assert_eq!(db.get_ai_profiles().await.unwrap().len(), 1);
+
+        let mut local = AIConfig::new(AIProvider::Ollama, None, Some("http://localhost:11434".to_string()), None, 1000, 0.7);
+        local.name = "Local".to_string();
+        local.is_default = true;
+        let local_id = db.create_ai_profile(&local).await.unwrap();
+
+        let profiles = db.get_ai_profiles().await.unwrap();
+        assert_eq!(profiles.len(), 2);
+        assert_eq!(profiles.iter().filter(|profile| profile.is_default).count(), 1);
+        assert_eq!(db.get_ai_config().await.unwrap().unwrap().id, Some(local_id));
+
+        // Fallback lists survive the round trip in order
+        local.id = Some(local_id);
+        local.fallback_profile_ids = vec![first.id.unwrap()];
+        assert!(db.update_ai_profile(&local).await.unwrap());
+        let local = db.get_ai_profile(local_id).await.unwrap().unwrap();
+        assert_eq!(local.fallback_profile_ids, vec![first.id.unwrap()]);
+        assert!(db.get_ai_profile(first.id.unwrap()).await.unwrap().unwrap().fallback_profile_ids.is_empty());
+
+        assert!(db.delete_ai_profile(first.id.unwrap()).await.unwrap());
+        assert!(db.get_ai_profile(first.id.unwrap()).await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_deepseek_installs_keep_chinese_prompts() {
+        let pool = setup_test_db().await;
+        let db = DatabaseManager::new(pool);
+
+        // Fresh installs default to the English templates
+        let evaluation = db.get_default_prompt_template(PromptKind::Evaluation).await.unwrap().unwrap();
+        assert_eq!(evaluation.id, "builtin-evaluation-en");
+
+        // An upgraded install prompting DeepSeek, with its own question template chosen
+        db.save_ai_config(&AIConfig::new(AIProvider::DeepSeek, Some("sk-test".to_string()), None, None, 1000, 0.7)).await.unwrap();
+        let mut custom = PromptTemplate::new("Custom".to_string(), PromptKind::Question, None, "Ask about {{context}}".to_string());
+        custom.is_default = true;
+        db.save_prompt_template(&custom).await.unwrap();
+
+        sqlx::query(include_str!("../../migrations/019_deepseek_prompt_defaults.sql"))
+            .execute(&db.pool)
+            .await
+            .unwrap();
+
+        let evaluation = db.get_default_prompt_template(PromptKind::Evaluation).await.unwrap().unwrap();
+        assert_eq!(evaluation.id, "builtin-evaluation-zh");
+        let question = db.get_default_prompt_template(PromptKind::Question).await.unwrap().unwrap();
+        assert_eq!(question.id, custom.id);
+        let defaults = db.get_prompt_templates(None).await.unwrap().into_iter().filter(|template| template.is_default).count();
+        assert_eq!(defaults, 2);
+    }
+
+    #[tokio::test]
+    async fn test_api_keys_encrypted_at_rest() {
+        let pool = setup_test_db().await;
+        let stored_key = |pool: SqlitePool| async move {
+            sqlx::query_scalar::<_, String>("SELECT api_key FROM ai_config")
+                .fetch_one(&pool)
+                .await
+                .unwrap()
+        };
+
+        // A key saved before encryption was enabled is encrypted by the startup migration
+        let plain = DatabaseManager::new(pool.clone());
+        plain.save_ai_config(&AIConfig::new(AIProvider::OpenAI, Some("sk-live-123".to_string()), None, None, 1000, 0.7)).await.unwrap();
+        assert_eq!(stored_key(pool.clone()).await, "sk-live-123");
+
+        let cipher = SecretCipher::from_base64(&SecretCipher::generate_key()).unwrap();
+        let db = DatabaseManager::with_secrets(pool.clone(), cipher.clone());
+        assert_eq!(db.get_ai_config().await.unwrap().unwrap().api_key.as_deref(), Some("sk-live-123"));
+        assert_eq!(db.encrypt_plaintext_secrets().await.unwrap(), 1);
+        assert_eq!(db.encrypt_plaintext_secrets().await.unwrap(), 0);
+
+        let sealed = stored_key(pool.clone()).await;
+        assert!(!sealed.contains("sk-live-123"));
+        assert_eq!(db.get_ai_config().await.unwrap().unwrap().api_key.as_deref(), Some("sk-live-123"));
+        assert_eq!(db.get_ai_profiles().await.unwrap()[0].api_key.as_deref(), Some("sk-live-123"));
+
+        // New keys are sealed on write
+        let mut profile = AIConfig::new(AIProvider::DeepSeek, Some("sk-deep".to_string()), None, None, 1000, 0.7);
+        profile.name = "Deep".to_string();
+        let id = db.create_ai_profile(&profile).await.unwrap();
+        assert_eq!(db.get_ai_profile(id).await.unwrap().unwrap().api_key.as_deref(), Some("sk-deep"));
+
+        // Without the key, or with another one, sealed keys cannot be read
+        assert!(plain.get_ai_config().await.is_err());
+        let other = SecretCipher::from_base64(&SecretCipher::generate_key()).unwrap();
+        assert!(DatabaseManager::with_secrets(pool.clone(), other.clone()).get_ai_config().await.is_err());
+
+        // Rotation re-encrypts every key for the new cipher
+        assert_eq!(db.rotate_secret_key(&other).await.unwrap(), 2);
+        assert!(db.get_ai_config().await.is_err());
+        let rotated = DatabaseManager::with_secrets(pool, other);
+        assert_eq!(rotated.get_ai_config().await.unwrap().unwrap().api_key.as_deref(), Some("sk-live-123"));
+        assert_eq!(rotated.get_ai_profile(id).await.unwrap().unwrap().api_key.as_deref(), Some("sk-deep"));
+    }
+
     #[tokio::test]
     async fn test_question_answer_history() {
         let pool = setup_test_db().await;
@@ -314,7 +511,7 @@ mod tests {
         let kb = db.create_knowledge_base("Test KB", None).await.unwrap();
 
         // Create questions and answers with varying scores
-        let scores = vec![60, 70, 80, 90, 95];
+        let scores = [60, 70, 80, 90, 95];
         for (i, score) in scores.iter().enumerate() {
             let question = Question::new(
                 kb.id.clone(),
@@ -338,4 +535,150 @@ mod tests {
         assert!(progress.improvement_trend.is_some());
         assert_eq!(progress.improvement_trend.unwrap(), "improving"); // Later scores are higher
     }
-}
\ No newline at end of file
+
+    #[tokio::test]
+    async fn test_document_chunks_replace_and_cascade() {
+        let pool = setup_test_db().await;
+        let db = DatabaseManager::new(pool);
+
+        let kb = db.create_knowledge_base("Test KB", None).await.unwrap();
+        let document = Document::new(
+            kb.id.clone(),
+            "test.txt".to_string(),
+            DocumentType::Txt,
+            "/path/to/test.txt".to_string(),
+            100,
+            Some("Chunk one. Chunk two.".to_string()),
+        );
+        db.save_document(&document).await.unwrap();
+
+        // Documents with content but no chunks are reported as unindexed
+        let unindexed = db.get_unindexed_documents(&kb.id).await.unwrap();
+        assert_eq!(unindexed, vec![document.id.clone()]);
+
+        let chunks = vec![
+            (DocumentChunk::new(document.id.clone(), kb.id.clone(), 0, "Chunk one.".to_string(), 0, 10), vec![0u8; 8]),
+            (DocumentChunk::new(document.id.clone(), kb.id.clone(), 1, "Chunk two.".to_string(), 11, 21), vec![1u8; 8]),
+        ];
+        db.replace_document_chunks(&document.id, &chunks).await.unwrap();
+        assert_eq!(db.get_chunks_by_document(&document.id).await.unwrap().len(), 2);
+        assert!(db.get_unindexed_documents(&kb.id).await.unwrap().is_empty());
+
+        // Replacing drops the previous chunks
+        db.replace_document_chunks(&document.id, &chunks[..1]).await.unwrap();
+        let stored = db.get_chunk_embeddings_by_knowledge_base(&kb.id).await.unwrap();
+        assert_eq!(stored.len(), 1);
+        assert_eq!(stored[0].1, vec![0u8; 8]);
+
+        // Deleting the document removes its chunks
+        db.delete_document(&document.id).await.unwrap();
+        assert!(db.get_chunks_by_document(&document.id).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_document_without_chunks_is_indexed_once() {
+        let pool = setup_test_db().await;
+        let db = DatabaseManager::new(pool);
+
+        let kb = db.create_knowledge_base("Test KB", None).await.unwrap();
+        let document = Document::new(
+            kb.id.clone(),
+            "blank.txt".to_string(),
+            DocumentType::Txt,
+            "/path/to/blank.txt".to_string(),
+            3,
+            Some("   ".to_string()),
+        );
+        db.save_document(&document).await.unwrap();
+        assert_eq!(db.get_unindexed_documents(&kb.id).await.unwrap(), vec![document.id.clone()]);
+
+        // Indexing found nothing to chunk, but the document is not offered for indexing again
+        db.replace_document_chunks(&document.id, &[]).await.unwrap();
+        assert!(db.get_chunks_by_document(&document.id).await.unwrap().is_empty());
+        assert!(db.get_unindexed_documents(&kb.id).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_reindexing_keeps_question_sources() {
+        let pool = setup_test_db().await;
+        let db = DatabaseManager::new(pool);
+
+        let kb = db.create_knowledge_base("Test KB", None).await.unwrap();
+        let document = Document::new(
+            kb.id.clone(),
+            "test.txt".to_string(),
+            DocumentType::Txt,
+            "/path/to/test.txt".to_string(),
+            100,
+            Some("Chunk one. Chunk two.".to_string()),
+        );
+        db.save_document(&document).await.unwrap();
+
+        let first = DocumentChunk::new(document.id.clone(), kb.id.clone(), 0, "Chunk one.".to_string(), 0, 10);
+        let second = DocumentChunk::new(document.id.clone(), kb.id.clone(), 1, "Chunk two.".to_string(), 11, 21);
+        db.replace_document_chunks(&document.id, &[(first.clone(), vec![0u8; 8]), (second, vec![])]).await.unwrap();
+        let question = Question::new(kb.id.clone(), "Q1?".to_string(), None).with_source(&first);
+        db.save_question(&question).await.unwrap();
+
+        // Re-indexing builds fresh chunks; the one at the question's position keeps its id
+        let rebuilt = DocumentChunk::new(document.id.clone(), kb.id.clone(), 0, "Chunk one, revised.".to_string(), 0, 19);
+        assert_ne!(rebuilt.id, first.id);
+        db.replace_document_chunks(&document.id, &[(rebuilt, vec![2u8; 8])]).await.unwrap();
+
+        let stored = db.get_chunks_by_document(&document.id).await.unwrap();
+        assert_eq!(stored.len(), 1);
+        assert_eq!(stored[0].id, first.id);
+        assert_eq!(stored[0].content, "Chunk one, revised.");
+        assert_eq!(stored[0].end_offset, 19);
+        let embeddings = db.get_chunk_embeddings_by_knowledge_base(&kb.id).await.unwrap();
+        assert_eq!(embeddings[0].1, vec![2u8; 8]);
+
+        let question = db.get_question_by_id(&question.id).await.unwrap().unwrap();
+        assert_eq!(question.chunk_id, Some(first.id));
+    }
+
+    #[tokio::test]
+    async fn test_chunk_coverage_aggregates() {
+        let pool = setup_test_db().await;
+        let db = DatabaseManager::new(pool);
+
+        let kb = db.create_knowledge_base("Test KB", None).await.unwrap();
+        let document = Document::new(
+            kb.id.clone(),
+            "test.txt".to_string(),
+            DocumentType::Txt,
+            "/path/to/test.txt".to_string(),
+            100,
+            Some("Chunk one. Chunk two.".to_string()),
+        );
+        db.save_document(&document).await.unwrap();
+
+        let first = DocumentChunk::new(document.id.clone(), kb.id.clone(), 0, "Chunk one.".to_string(), 0, 10);
+        let second = DocumentChunk::new(document.id.clone(), kb.id.clone(), 1, "Chunk two.".to_string(), 11, 21);
+        db.replace_document_chunks(&document.id, &[(first.clone(), vec![]), (second.clone(), vec![])]).await.unwrap();
+
+        // Two questions on the first chunk, one of them answered twice
+        let answered = Question::new(kb.id.clone(), "Q1?".to_string(), None).with_source(&first);
+        let unanswered = Question::new(kb.id.clone(), "Q2?".to_string(), None).with_source(&first);
+        db.save_question(&answered).await.unwrap();
+        db.save_question(&unanswered).await.unwrap();
+        for score in [60, 80] {
+            let mut answer = Answer::new(answered.id.clone(), "A".to_string());
+            answer.ai_score = Some(score);
+            db.save_answer(&answer).await.unwrap();
+        }
+
+        let coverage = db.get_chunk_coverage(&kb.id, Some(&document.id)).await.unwrap();
+        assert_eq!(coverage.len(), 2);
+        assert_eq!(coverage[0].chunk_id, first.id);
+        assert_eq!(coverage[0].preview, "Chunk one.");
+        assert_eq!(coverage[0].question_count, 2);
+        assert_eq!(coverage[0].answer_count, 2);
+        assert_eq!(coverage[0].average_score, Some(70.0));
+        assert_eq!(coverage[1].question_count, 0);
+        assert_eq!(coverage[1].answer_count, 0);
+        assert_eq!(coverage[1].average_score, None);
+
+        assert!(db.get_chunk_coverage(&kb.id, Some("other-doc")).await.unwrap().is_empty());
+    }
+}
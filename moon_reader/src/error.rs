@@ -27,6 +27,12 @@ pub enum AppError {
     #[error("Service unavailable: {0}")]
     ServiceUnavailable(String),
     
+    #[error("Conflict: {0}")]
+    Conflict(String),
+    
+    #[error("Too many requests: {0}")]
+    TooManyRequests(String),
+    
     #[error("File upload error: {0}")]
     FileUpload(String),
     
@@ -81,6 +87,20 @@ impl IntoResponse for AppError {
                     Some(msg.clone()),
                 )
             }
+            AppError::Conflict(ref msg) => {
+                (
+                    StatusCode::CONFLICT,
+                    "Conflict".to_string(),
+                    Some(msg.clone()),
+                )
+            }
+            AppError::TooManyRequests(ref msg) => {
+                (
+                    StatusCode::TOO_MANY_REQUESTS,
+                    "Too many requests".to_string(),
+                    Some(msg.clone()),
+                )
+            }
             AppError::FileUpload(ref msg) => {
                 (
                     StatusCode::BAD_REQUEST,
@@ -122,4 +142,16 @@ pub fn validation_error_to_app_error(errors: validator::ValidationErrors) -> App
 }
 
 // Result type alias for convenience
-pub type AppResult<T> = Result<T, AppError>;
\ No newline at end of file
+pub type AppResult<T> = Result<T, AppError>;
+
+impl From<crate::services::ProviderLoadError> for AppError {
+    fn from(error: crate::services::ProviderLoadError) -> Self {
+        use crate::services::ProviderLoadError;
+
+        match error {
+            ProviderLoadError::Database(e) => AppError::Database(e),
+            ProviderLoadError::BudgetExceeded { .. } => AppError::TooManyRequests(error.to_string()),
+            ProviderLoadError::NotConfigured | ProviderLoadError::Provider(_) => AppError::BadRequest(error.to_string()),
+        }
+    }
+}
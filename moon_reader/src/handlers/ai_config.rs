@@ -5,10 +5,12 @@ use axum::{
 };
 use serde::{Deserialize, Serialize};
 use serde_json::{json, Value};
-use std::collections::HashMap;
+use std::sync::Arc;
 use validator::Validate;
 
-use crate::services::{AppState, ai::{AIServiceFactory, AIProviderType}};
+use crate::services::{AppState, ai::{self, AIError}};
+use crate::handlers::ai_profile::invalid_fallbacks;
+use crate::handlers::ai_quiz::provider_error_response;
 use crate::models::{AIConfig, AIProvider};
 
 #[derive(Debug, Serialize, Deserialize, Validate)]
@@ -24,33 +26,121 @@ pub struct AIConfigRequest {
     pub max_tokens: Option<i32>,
     #[validate(range(min = 0.0, max = 2.0, message = "Temperature must be between 0.0 and 2.0"))]
     pub temperature: Option<f64>,
+    /// Provider-specific model options, e.g. `{"num_ctx": 8192}` for Ollama
+    pub provider_options: Option<Value>,
+    #[validate(range(min = 1, max = 300, message = "Connect timeout must be between 1 and 300 seconds"))]
+    pub connect_timeout_secs: Option<i32>,
+    #[validate(range(min = 1, max = 1800, message = "Request timeout must be between 1 and 1800 seconds"))]
+    pub request_timeout_secs: Option<i32>,
+    #[validate(range(min = 0, max = 10, message = "Max retries must be between 0 and 10"))]
+    pub max_retries: Option<i32>,
+    /// Profiles to try in order when this one's service is unavailable
+    pub fallback_profile_ids: Option<Vec<i32>>,
+    /// Spending limit per calendar month; unset for no limit
+    #[validate(range(min = 0.0, message = "Monthly budget cannot be negative"))]
+    pub monthly_budget: Option<f64>,
 }
 
 #[derive(Debug, Serialize)]
 pub struct AIConfigResponse {
+    pub id: Option<i32>,
+    pub name: String,
+    pub is_default: bool,
     pub provider: AIProvider,
     pub api_key_configured: bool,
     pub api_url: Option<String>,
     pub model_name: Option<String>,
     pub max_tokens: i32,
     pub temperature: f64,
+    pub provider_options: Option<Value>,
+    pub connect_timeout_secs: Option<i32>,
+    pub request_timeout_secs: Option<i32>,
+    pub max_retries: Option<i32>,
+    pub fallback_profile_ids: Vec<i32>,
+    pub monthly_budget: Option<f64>,
     pub updated_at: chrono::DateTime<chrono::Utc>,
 }
 
 impl From<AIConfig> for AIConfigResponse {
     fn from(config: AIConfig) -> Self {
         Self {
+            id: config.id,
+            name: config.name,
+            is_default: config.is_default,
             provider: config.provider,
             api_key_configured: config.api_key.is_some(),
             api_url: config.api_url,
             model_name: config.model_name,
             max_tokens: config.max_tokens,
             temperature: config.temperature,
+            provider_options: config.provider_options.and_then(|o| serde_json::from_str(&o).ok()),
+            connect_timeout_secs: config.connect_timeout_secs,
+            request_timeout_secs: config.request_timeout_secs,
+            max_retries: config.max_retries,
+            fallback_profile_ids: config.fallback_profile_ids,
+            monthly_budget: config.monthly_budget,
             updated_at: config.updated_at,
         }
     }
 }
 
+/// Settings to store from a request, keeping `existing_key` when no new key is given
+pub(crate) fn config_from_request(payload: AIConfigRequest, existing_key: Option<String>) -> Result<AIConfig, String> {
+    let api_key = provider_api_key(&payload.provider, payload.api_key, payload.api_url.as_deref(), existing_key)?;
+    let provider_options = encode_provider_options(payload.provider_options)?;
+
+    let mut config = AIConfig::new(
+        payload.provider,
+        api_key,
+        payload.api_url,
+        payload.model_name,
+        payload.max_tokens.unwrap_or(1000),
+        payload.temperature.unwrap_or(0.7),
+    );
+    config.provider_options = provider_options;
+    config.connect_timeout_secs = payload.connect_timeout_secs;
+    config.request_timeout_secs = payload.request_timeout_secs;
+    config.max_retries = payload.max_retries;
+    config.fallback_profile_ids = payload.fallback_profile_ids.unwrap_or_default();
+    config.monthly_budget = payload.monthly_budget;
+
+    Ok(config)
+}
+
+/// The API key to store for a provider, keeping `existing_key` when none is given,
+/// or why the settings are incomplete
+fn provider_api_key(
+    provider: &AIProvider,
+    api_key: Option<String>,
+    api_url: Option<&str>,
+    existing_key: Option<String>,
+) -> Result<Option<String>, String> {
+    match provider {
+        AIProvider::DeepSeek | AIProvider::OpenAI | AIProvider::Anthropic => api_key
+            .filter(|key| !key.trim().is_empty())
+            .or(existing_key)
+            .map(Some)
+            .ok_or_else(|| "API key is required".to_string()),
+        AIProvider::Local | AIProvider::Ollama => {
+            if api_url.is_none_or(|url| url.trim().is_empty()) {
+                return Err(format!("API URL is required for {} provider", provider));
+            }
+            Ok(api_key)
+        }
+        // Needs neither a key nor a URL; its behaviour comes from provider_options
+        AIProvider::Mock => Ok(None),
+    }
+}
+
+/// Provider options as stored: a non-empty JSON object, or nothing
+fn encode_provider_options(options: Option<Value>) -> Result<Option<String>, String> {
+    match options {
+        Some(Value::Object(options)) if !options.is_empty() => Ok(Some(Value::Object(options).to_string())),
+        Some(Value::Object(_)) | Some(Value::Null) | None => Ok(None),
+        Some(_) => Err("provider_options must be a JSON object".to_string()),
+    }
+}
+
 /// Get current AI configuration
 pub async fn get_ai_config(
     State(state): State<AppState>,
@@ -63,12 +153,21 @@ pub async fn get_ai_config(
         Ok(None) => {
             // Return default configuration if none exists
             Ok(Json(json!({
+                "id": null,
+                "name": null,
+                "is_default": false,
                 "provider": "deepseek",
                 "api_key_configured": false,
                 "api_url": null,
                 "model_name": null,
                 "max_tokens": 1000,
                 "temperature": 0.7,
+                "provider_options": null,
+                "connect_timeout_secs": null,
+                "request_timeout_secs": null,
+                "max_retries": null,
+                "fallback_profile_ids": [],
+                "monthly_budget": null,
                 "updated_at": null
             })))
         }
@@ -101,52 +200,27 @@ pub async fn save_ai_config(
     // Get existing config to preserve API key if not provided
     let existing_config = state.db.get_ai_config().await.unwrap_or(None);
     
-    // Validate provider-specific requirements
-    let api_key = match payload.provider {
-        AIProvider::DeepSeek | AIProvider::OpenAI => {
-            if let Some(ref key) = payload.api_key {
-                if !key.trim().is_empty() {
-                    Some(key.clone())
-                } else if let Some(ref existing) = existing_config {
-                    existing.api_key.clone()
-                } else {
-                    return Err((
-                        StatusCode::BAD_REQUEST,
-                        Json(json!({"error": "API key is required"})),
-                    ));
-                }
-            } else if let Some(ref existing) = existing_config {
-                existing.api_key.clone()
-            } else {
-                return Err((
-                    StatusCode::BAD_REQUEST,
-                    Json(json!({"error": "API key is required"})),
-                ));
-            }
-        }
-        AIProvider::Local => {
-            if payload.api_url.is_none() || payload.api_url.as_ref().unwrap().trim().is_empty() {
-                return Err((
-                    StatusCode::BAD_REQUEST,
-                    Json(json!({"error": "API URL is required for Local AI provider"})),
-                ));
-            }
-            payload.api_key
+    let existing_id = existing_config.as_ref().and_then(|config| config.id);
+    let existing_key = existing_config.and_then(|config| config.api_key);
+    let config = config_from_request(payload, existing_key)
+        .map_err(|error| (StatusCode::BAD_REQUEST, Json(json!({"error": error}))))?;
+    match invalid_fallbacks(&state, existing_id, &config.fallback_profile_ids).await {
+        Ok(None) => {}
+        Ok(Some(reason)) => return Err((StatusCode::BAD_REQUEST, Json(json!({"error": reason})))),
+        Err(e) => {
+            tracing::error!("Failed to check fallback AI profiles: {}", e);
+            return Err((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({"error": "Failed to save AI configuration"})),
+            ));
         }
-    };
-
-    let config = AIConfig::new(
-        payload.provider,
-        api_key,
-        payload.api_url,
-        payload.model_name,
-        payload.max_tokens.unwrap_or(1000),
-        payload.temperature.unwrap_or(0.7),
-    );
+    }
 
     match state.db.save_ai_config(&config).await {
-        Ok(_) => {
-            let response: AIConfigResponse = config.into();
+        Ok(saved) => {
+            // Rebuild the provider with the new settings on next use
+            state.invalidate_ai_provider().await;
+            let response: AIConfigResponse = saved.into();
             Ok(Json(json!({
                 "message": "AI configuration saved successfully",
                 "config": response
@@ -162,10 +236,10 @@ pub async fn save_ai_config(
     }
 }
 
-/// Test AI connection with current configuration
-pub async fn test_ai_connection(
-    State(state): State<AppState>,
-) -> Result<Json<Value>, (StatusCode, Json<Value>)> {
+// The configured provider kind together with its (cached) provider instance
+async fn load_configured_provider(
+    state: &AppState,
+) -> Result<(AIProvider, Arc<dyn ai::AIProvider>), (StatusCode, Json<Value>)> {
     // Get current AI configuration
     let config = match state.db.get_ai_config().await {
         Ok(Some(config)) => config,
@@ -184,108 +258,84 @@ pub async fn test_ai_connection(
         }
     };
 
-    // Create AI provider based on configuration
-    let provider_type = match config.provider {
-        AIProvider::DeepSeek => AIProviderType::DeepSeek,
-        AIProvider::Local => AIProviderType::Local,
-        AIProvider::OpenAI => {
-            return Err((
-                StatusCode::NOT_IMPLEMENTED,
-                Json(json!({"error": "OpenAI provider not yet implemented"})),
-            ));
-        }
-    };
+    let provider = state.ai_provider().await.map_err(provider_error_response)?;
 
-    let mut provider_config = HashMap::new();
-    
-    match config.provider {
-        AIProvider::DeepSeek => {
-            if let Some(api_key) = config.api_key {
-                provider_config.insert("api_key".to_string(), api_key);
-            } else {
-                return Err((
-                    StatusCode::BAD_REQUEST,
-                    Json(json!({"error": "API key not configured for DeepSeek"})),
-                ));
-            }
-        }
-        AIProvider::Local => {
-            if let Some(api_url) = config.api_url {
-                provider_config.insert("api_url".to_string(), api_url);
-            } else {
-                return Err((
-                    StatusCode::BAD_REQUEST,
-                    Json(json!({"error": "API URL not configured for Local AI"})),
-                ));
-            }
-        }
-        _ => {}
-    }
+    Ok((config.provider, provider))
+}
 
-    if let Some(model_name) = config.model_name {
-        provider_config.insert("model".to_string(), model_name);
-    }
-    provider_config.insert("max_tokens".to_string(), config.max_tokens.to_string());
-    provider_config.insert("temperature".to_string(), config.temperature.to_string());
+/// Test AI connection with current configuration
+pub async fn test_ai_connection(
+    State(state): State<AppState>,
+) -> Result<Json<Value>, (StatusCode, Json<Value>)> {
+    let (provider_name, provider) = load_configured_provider(&state).await?;
 
-    // Create AI provider and test connection
-    match AIServiceFactory::create_provider(provider_type, provider_config) {
-        Ok(provider) => {
-            match provider.test_connection().await {
-                Ok(true) => {
-                    Ok(Json(json!({
-                        "status": "success",
-                        "message": "AI service connection successful",
-                        "provider": config.provider.to_string()
-                    })))
-                }
-                Ok(false) => {
-                    Err((
-                        StatusCode::SERVICE_UNAVAILABLE,
-                        Json(json!({
-                            "status": "failed",
-                            "message": "AI service connection failed",
-                            "provider": config.provider.to_string()
-                        })),
-                    ))
-                }
-                Err(e) => {
-                    tracing::error!("AI connection test error: {}", e);
-                    Err((
-                        StatusCode::SERVICE_UNAVAILABLE,
-                        Json(json!({
-                            "status": "error",
-                            "message": format!("AI service error: {}", e),
-                            "provider": config.provider.to_string()
-                        })),
-                    ))
-                }
-            }
+    match provider.test_connection().await {
+        Ok(true) => {
+            Ok(Json(json!({
+                "status": "success",
+                "message": "AI service connection successful",
+                "provider": provider_name.to_string()
+            })))
+        }
+        Ok(false) => {
+            Err((
+                StatusCode::SERVICE_UNAVAILABLE,
+                Json(json!({
+                    "status": "failed",
+                    "message": "AI service connection failed",
+                    "provider": provider_name.to_string()
+                })),
+            ))
         }
         Err(e) => {
-            tracing::error!("Failed to create AI provider: {}", e);
+            tracing::error!("AI connection test error: {}", e);
             Err((
-                StatusCode::INTERNAL_SERVER_ERROR,
+                StatusCode::SERVICE_UNAVAILABLE,
                 Json(json!({
                     "status": "error",
-                    "message": format!("Failed to create AI provider: {}", e)
+                    "message": format!("AI service error: {}", e),
+                    "provider": provider_name.to_string()
                 })),
             ))
         }
     }
 }
+
+/// List the models available from the configured provider
+pub async fn list_ai_models(
+    State(state): State<AppState>,
+) -> Result<Json<Value>, (StatusCode, Json<Value>)> {
+    let (provider_name, provider) = load_configured_provider(&state).await?;
+
+    match provider.list_models().await {
+        Ok(models) => Ok(Json(json!({
+            "provider": provider_name.to_string(),
+            "models": models
+        }))),
+        Err(AIError::Unsupported(_)) => Err((
+            StatusCode::BAD_REQUEST,
+            Json(json!({"error": format!("Model listing is not supported by the {} provider", provider_name)})),
+        )),
+        Err(e) => {
+            tracing::error!("Failed to list AI models: {}", e);
+            Err((
+                StatusCode::SERVICE_UNAVAILABLE,
+                Json(json!({"error": format!("Failed to list models: {}", e)})),
+            ))
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::database::create_connection_pool;
     use crate::services::AppState;
     use axum::extract::State;
-    use tempfile::NamedTempFile;
 
     async fn create_test_app_state() -> AppState {
-        let temp_file = NamedTempFile::new().unwrap();
-        let database_url = format!("sqlite:{}", temp_file.path().to_str().unwrap());
-        let pool = create_connection_pool(&database_url).await.unwrap();
+        // Use in-memory database for tests
+        let pool = create_connection_pool("sqlite::memory:").await.unwrap();
         AppState::new(pool)
     }
 
@@ -321,6 +371,12 @@ mod tests {
             model_name: Some("deepseek-chat".to_string()),
             max_tokens: Some(1500),
             temperature: Some(0.8),
+            provider_options: None,
+            connect_timeout_secs: None,
+            request_timeout_secs: None,
+            max_retries: None,
+            fallback_profile_ids: None,
+            monthly_budget: None,
         };
         
         let result = save_ai_config(State(state.clone()), Json(request)).await;
@@ -351,6 +407,12 @@ mod tests {
             model_name: Some("local-model".to_string()),
             max_tokens: Some(2000),
             temperature: Some(0.5),
+            provider_options: None,
+            connect_timeout_secs: None,
+            request_timeout_secs: None,
+            max_retries: None,
+            fallback_profile_ids: None,
+            monthly_budget: None,
         };
         
         let result = save_ai_config(State(state.clone()), Json(request)).await;
@@ -371,6 +433,36 @@ mod tests {
         assert_eq!(json_value["temperature"], 0.5);
     }
 
+    #[tokio::test]
+    async fn test_save_and_test_mock_config() {
+        let state = create_test_app_state().await;
+        
+        let request = AIConfigRequest {
+            provider: AIProvider::Mock,
+            api_key: None,
+            api_url: None,
+            model_name: None,
+            max_tokens: None,
+            temperature: None,
+            provider_options: Some(json!({"latency_ms": 10})),
+            connect_timeout_secs: None,
+            request_timeout_secs: None,
+            max_retries: None,
+            fallback_profile_ids: None,
+            monthly_budget: None,
+        };
+        
+        let Json(saved) = save_ai_config(State(state.clone()), Json(request)).await.unwrap();
+        assert_eq!(saved["config"]["provider"], "mock");
+        assert_eq!(saved["config"]["provider_options"]["latency_ms"], 10);
+        
+        let Json(tested) = test_ai_connection(State(state.clone())).await.unwrap();
+        assert_eq!(tested["status"], "success");
+        
+        let Json(models) = list_ai_models(State(state)).await.unwrap();
+        assert_eq!(models["models"], json!(["mock"]));
+    }
+
     #[tokio::test]
     async fn test_save_ai_config_validation_error() {
         let state = create_test_app_state().await;
@@ -382,6 +474,12 @@ mod tests {
             model_name: None,
             max_tokens: Some(1000),
             temperature: Some(0.7),
+            provider_options: None,
+            connect_timeout_secs: None,
+            request_timeout_secs: None,
+            max_retries: None,
+            fallback_profile_ids: None,
+            monthly_budget: None,
         };
         
         let result = save_ai_config(State(state), Json(request)).await;
@@ -401,4 +499,77 @@ mod tests {
         let (status, _) = result.unwrap_err();
         assert_eq!(status, StatusCode::BAD_REQUEST);
     }
-}
\ No newline at end of file
+
+    #[tokio::test]
+    async fn test_save_ai_config_provider_options() {
+        let state = create_test_app_state().await;
+        
+        let request = AIConfigRequest {
+            provider: AIProvider::Ollama,
+            api_key: None,
+            api_url: Some("http://localhost:11434".to_string()),
+            model_name: Some("llama3.1".to_string()),
+            max_tokens: None,
+            temperature: None,
+            provider_options: Some(json!({"num_ctx": 8192})),
+            connect_timeout_secs: None,
+            request_timeout_secs: None,
+            max_retries: None,
+            fallback_profile_ids: None,
+            monthly_budget: None,
+        };
+        assert!(save_ai_config(State(state.clone()), Json(request)).await.is_ok());
+        
+        let json_value = get_ai_config(State(state.clone())).await.unwrap().0;
+        assert_eq!(json_value["provider"], "ollama");
+        assert_eq!(json_value["provider_options"]["num_ctx"], 8192);
+        
+        let request = AIConfigRequest {
+            provider: AIProvider::Ollama,
+            api_key: None,
+            api_url: Some("http://localhost:11434".to_string()),
+            model_name: None,
+            max_tokens: None,
+            temperature: None,
+            provider_options: Some(json!(["num_ctx"])),
+            connect_timeout_secs: None,
+            request_timeout_secs: None,
+            max_retries: None,
+            fallback_profile_ids: None,
+            monthly_budget: None,
+        };
+        let (status, _) = save_ai_config(State(state), Json(request)).await.unwrap_err();
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn test_list_ai_models() {
+        use axum::{routing::get, Router};
+        
+        let app = Router::new().route(
+            "/api/tags",
+            get(|| async { Json(json!({"models": [{"name": "llama3.1:latest"}]})) }),
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            axum::serve(listener, app).await.unwrap();
+        });
+        
+        let state = create_test_app_state().await;
+        let mut config = AIConfig::new(AIProvider::Ollama, None, Some(format!("http://{}", addr)), None, 1000, 0.7);
+        config.provider_options = Some(r#"{"num_ctx": 4096}"#.to_string());
+        state.db.save_ai_config(&config).await.unwrap();
+        
+        let json_value = list_ai_models(State(state.clone())).await.unwrap().0;
+        assert_eq!(json_value["provider"], "ollama");
+        assert_eq!(json_value["models"], json!(["llama3.1:latest"]));
+        
+        // Providers without a listing endpoint report it as unsupported
+        let config = AIConfig::new(AIProvider::DeepSeek, Some("key".to_string()), None, None, 1000, 0.7);
+        state.db.save_ai_config(&config).await.unwrap();
+        state.invalidate_ai_provider().await;
+        let (status, _) = list_ai_models(State(state)).await.unwrap_err();
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+    }
+}
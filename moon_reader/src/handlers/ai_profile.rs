@@ -0,0 +1,398 @@
+use axum::{
+    extract::{Path, State},
+    response::Json,
+};
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use validator::Validate;
+
+use crate::error::{AppError, AppResult, validation_error_to_app_error};
+use crate::handlers::ai_config::{AIConfigRequest, AIConfigResponse, config_from_request};
+use crate::models::{AIConfig, AIProfileSelection};
+use crate::services::AppState;
+
+// Request DTOs
+#[derive(Debug, Deserialize, Validate)]
+pub struct AIProfileRequest {
+    #[validate(length(min = 1, max = 100, message = "Name must be between 1 and 100 characters"))]
+    pub name: String,
+    /// Leave unset to keep the current default flag (new profiles are not the default)
+    pub is_default: Option<bool>,
+    #[validate]
+    #[serde(flatten)]
+    pub settings: AIConfigRequest,
+}
+
+// Response DTOs
+#[derive(Debug, Serialize)]
+pub struct ListAIProfilesResponse {
+    pub profiles: Vec<AIConfigResponse>,
+    /// Global per-task choices; unset tasks use the default profile
+    pub selection: AIProfileSelection,
+}
+
+// Handler functions
+pub async fn list_ai_profiles(
+    State(state): State<AppState>,
+) -> AppResult<Json<ListAIProfilesResponse>> {
+    let profiles = state.db.get_ai_profiles().await?;
+    let selection = state.db.get_ai_profile_selection(None).await?;
+
+    Ok(Json(ListAIProfilesResponse {
+        profiles: profiles.into_iter().map(AIConfigResponse::from).collect(),
+        selection,
+    }))
+}
+
+pub async fn get_ai_profile(
+    Path(id): Path<i32>,
+    State(state): State<AppState>,
+) -> AppResult<Json<AIConfigResponse>> {
+    Ok(Json(find_profile(&state, id).await?.into()))
+}
+
+pub async fn create_ai_profile(
+    State(state): State<AppState>,
+    Json(payload): Json<AIProfileRequest>,
+) -> AppResult<Json<AIConfigResponse>> {
+    // Validate input
+    if let Err(validation_errors) = payload.validate() {
+        return Err(validation_error_to_app_error(validation_errors));
+    }
+
+    let mut profile = config_from_request(payload.settings, None).map_err(AppError::Validation)?;
+    if let Some(reason) = invalid_fallbacks(&state, None, &profile.fallback_profile_ids).await? {
+        return Err(AppError::BadRequest(reason));
+    }
+    profile.name = payload.name;
+    // The first profile is the default whatever was asked
+    profile.is_default = payload.is_default.unwrap_or(false) || state.db.get_ai_config().await?.is_none();
+
+    let id = state.db.create_ai_profile(&profile).await.map_err(|e| duplicate_name_error(e, &profile.name))?;
+    profile.id = Some(id);
+    state.invalidate_ai_provider().await;
+
+    tracing::info!("Created AI profile: {}", id);
+    Ok(Json(profile.into()))
+}
+
+pub async fn update_ai_profile(
+    Path(id): Path<i32>,
+    State(state): State<AppState>,
+    Json(payload): Json<AIProfileRequest>,
+) -> AppResult<Json<AIConfigResponse>> {
+    // Validate input
+    if let Err(validation_errors) = payload.validate() {
+        return Err(validation_error_to_app_error(validation_errors));
+    }
+
+    let existing = find_profile(&state, id).await?;
+
+    // There is always a default; it changes by promoting another profile
+    if existing.is_default && payload.is_default == Some(false) {
+        return Err(AppError::BadRequest(
+            "Make another profile the default instead of unsetting this one".to_string(),
+        ));
+    }
+
+    let mut profile = config_from_request(payload.settings, existing.api_key).map_err(AppError::Validation)?;
+    if let Some(reason) = invalid_fallbacks(&state, Some(id), &profile.fallback_profile_ids).await? {
+        return Err(AppError::BadRequest(reason));
+    }
+    profile.id = Some(id);
+    profile.name = payload.name;
+    profile.is_default = payload.is_default.unwrap_or(existing.is_default);
+    profile.updated_at = Utc::now();
+
+    state.db.update_ai_profile(&profile).await.map_err(|e| duplicate_name_error(e, &profile.name))?;
+    state.invalidate_ai_provider().await;
+
+    tracing::info!("Updated AI profile: {}", id);
+    Ok(Json(profile.into()))
+}
+
+pub async fn delete_ai_profile(
+    Path(id): Path<i32>,
+    State(state): State<AppState>,
+) -> AppResult<Json<Value>> {
+    let profile = find_profile(&state, id).await?;
+
+    if profile.is_default {
+        return Err(AppError::BadRequest(
+            "The default profile cannot be deleted; make another profile the default first".to_string(),
+        ));
+    }
+
+    state.db.delete_ai_profile(id).await?;
+    state.invalidate_ai_provider().await;
+
+    tracing::info!("Deleted AI profile: {}", id);
+    Ok(Json(json!({"message": "AI profile deleted successfully"})))
+}
+
+/// Test the connection of one profile
+pub async fn test_ai_profile(
+    Path(id): Path<i32>,
+    State(state): State<AppState>,
+) -> AppResult<Json<Value>> {
+    let profile = find_profile(&state, id).await?;
+    let provider = state
+        .profile_provider(&profile)
+        .await
+        .map_err(|e| AppError::BadRequest(format!("Failed to create AI provider: {}", e)))?;
+
+    match provider.test_connection().await {
+        Ok(true) => Ok(Json(json!({
+            "status": "success",
+            "message": "AI service connection successful",
+            "provider": profile.provider.to_string()
+        }))),
+        Ok(false) => Err(AppError::ServiceUnavailable("AI service connection failed".to_string())),
+        Err(e) => Err(AppError::ServiceUnavailable(format!("AI service error: {}", e))),
+    }
+}
+
+/// Profiles used for each task when a knowledge base has no choice of its own
+pub async fn get_ai_profile_selection(
+    State(state): State<AppState>,
+) -> AppResult<Json<AIProfileSelection>> {
+    Ok(Json(state.db.get_ai_profile_selection(None).await?))
+}
+
+pub async fn update_ai_profile_selection(
+    State(state): State<AppState>,
+    Json(payload): Json<AIProfileSelection>,
+) -> AppResult<Json<AIProfileSelection>> {
+    check_selection(&state, &payload).await?;
+    state.db.set_ai_profile_selection(None, &payload).await?;
+
+    tracing::info!("Updated global AI profile selection");
+    Ok(Json(payload))
+}
+
+/// Profiles a knowledge base uses for each task, overriding the global choice
+pub async fn get_knowledge_base_ai_profiles(
+    Path(kb_id): Path<String>,
+    State(state): State<AppState>,
+) -> AppResult<Json<AIProfileSelection>> {
+    find_knowledge_base(&state, &kb_id).await?;
+
+    Ok(Json(state.db.get_ai_profile_selection(Some(&kb_id)).await?))
+}
+
+pub async fn update_knowledge_base_ai_profiles(
+    Path(kb_id): Path<String>,
+    State(state): State<AppState>,
+    Json(payload): Json<AIProfileSelection>,
+) -> AppResult<Json<AIProfileSelection>> {
+    find_knowledge_base(&state, &kb_id).await?;
+    check_selection(&state, &payload).await?;
+    state.db.set_ai_profile_selection(Some(&kb_id), &payload).await?;
+
+    tracing::info!("Updated AI profile selection for knowledge base: {}", kb_id);
+    Ok(Json(payload))
+}
+
+async fn find_profile(state: &AppState, id: i32) -> AppResult<AIConfig> {
+    state.db.get_ai_profile(id).await?
+        .ok_or_else(|| AppError::NotFound("AI profile not found".to_string()))
+}
+
+async fn find_knowledge_base(state: &AppState, kb_id: &str) -> AppResult<()> {
+    state.db.get_knowledge_base_by_id(kb_id).await?
+        .ok_or_else(|| AppError::NotFound("Knowledge base not found".to_string()))?;
+    Ok(())
+}
+
+/// Why a profile's fallback list is unusable, if it is: fallbacks must be other existing profiles
+pub(crate) async fn invalid_fallbacks(
+    state: &AppState,
+    profile_id: Option<i32>,
+    fallback_ids: &[i32],
+) -> Result<Option<String>, sqlx::Error> {
+    for &id in fallback_ids {
+        if profile_id == Some(id) {
+            return Ok(Some("A profile cannot fall back to itself".to_string()));
+        }
+        if state.db.get_ai_profile(id).await?.is_none() {
+            return Ok(Some(format!("Fallback AI profile {} does not exist", id)));
+        }
+    }
+    Ok(None)
+}
+
+// Every selected profile must exist
+async fn check_selection(state: &AppState, selection: &AIProfileSelection) -> AppResult<()> {
+    for id in [selection.question_profile_id, selection.evaluation_profile_id].into_iter().flatten() {
+        if state.db.get_ai_profile(id).await?.is_none() {
+            return Err(AppError::BadRequest(format!("AI profile {} does not exist", id)));
+        }
+    }
+    Ok(())
+}
+
+// Report a clash with an existing profile name as a client error
+fn duplicate_name_error(error: sqlx::Error, name: &str) -> AppError {
+    match error {
+        sqlx::Error::Database(ref db_error) if db_error.is_unique_violation() => {
+            AppError::BadRequest(format!("An AI profile named '{}' already exists", name))
+        }
+        other => AppError::Database(other),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::create_connection_pool;
+    use crate::models::{AIProvider, PromptKind};
+
+    async fn create_test_app_state() -> AppState {
+        let pool = create_connection_pool("sqlite::memory:").await.unwrap();
+        AppState::new(pool)
+    }
+
+    fn profile_request(name: &str, provider: AIProvider) -> AIProfileRequest {
+        AIProfileRequest {
+            name: name.to_string(),
+            is_default: None,
+            settings: AIConfigRequest {
+                provider,
+                api_key: Some("sk-test".to_string()),
+                api_url: Some("http://localhost:11434".to_string()),
+                model_name: None,
+                max_tokens: None,
+                temperature: None,
+                provider_options: None,
+                connect_timeout_secs: None,
+                request_timeout_secs: None,
+                max_retries: None,
+                fallback_profile_ids: None,
+                monthly_budget: None,
+            },
+        }
+    }
+
+    async fn create(state: &AppState, name: &str, provider: AIProvider) -> i32 {
+        let Json(profile) = create_ai_profile(State(state.clone()), Json(profile_request(name, provider)))
+            .await
+            .unwrap();
+        profile.id.unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_first_profile_becomes_default() {
+        let state = create_test_app_state().await;
+
+        let local = create(&state, "Local", AIProvider::Ollama).await;
+        let cloud = create(&state, "Cloud", AIProvider::OpenAI).await;
+
+        let Json(list) = list_ai_profiles(State(state.clone())).await.unwrap();
+        assert_eq!(list.profiles.len(), 2);
+        assert_eq!(list.profiles[0].id, Some(local));
+        assert!(list.profiles[0].is_default);
+        assert!(!list.profiles[1].is_default);
+        assert_eq!(list.selection, AIProfileSelection::default());
+
+        // Promoting another profile replaces the default
+        let mut request = profile_request("Cloud", AIProvider::OpenAI);
+        request.is_default = Some(true);
+        request.settings.api_key = None;
+        let Json(updated) = update_ai_profile(Path(cloud), State(state.clone()), Json(request)).await.unwrap();
+        assert!(updated.is_default);
+        assert!(updated.api_key_configured);
+        assert_eq!(state.db.get_ai_config().await.unwrap().unwrap().id, Some(cloud));
+
+        // Names are unique
+        let duplicate = create_ai_profile(State(state.clone()), Json(profile_request("Local", AIProvider::Mock))).await;
+        assert!(matches!(duplicate, Err(AppError::BadRequest(_))));
+    }
+
+    #[tokio::test]
+    async fn test_fallbacks_must_be_other_profiles() {
+        let state = create_test_app_state().await;
+        let local = create(&state, "Local", AIProvider::Ollama).await;
+        let cloud = create(&state, "Cloud", AIProvider::OpenAI).await;
+
+        let mut request = profile_request("Cloud", AIProvider::OpenAI);
+        request.settings.fallback_profile_ids = Some(vec![local]);
+        let Json(updated) = update_ai_profile(Path(cloud), State(state.clone()), Json(request)).await.unwrap();
+        assert_eq!(updated.fallback_profile_ids, vec![local]);
+
+        let mut request = profile_request("Cloud", AIProvider::OpenAI);
+        request.settings.fallback_profile_ids = Some(vec![cloud]);
+        let result = update_ai_profile(Path(cloud), State(state.clone()), Json(request)).await;
+        assert!(matches!(result, Err(AppError::BadRequest(_))));
+
+        let mut request = profile_request("Spare", AIProvider::Mock);
+        request.settings.fallback_profile_ids = Some(vec![999]);
+        let result = create_ai_profile(State(state.clone()), Json(request)).await;
+        assert!(matches!(result, Err(AppError::BadRequest(_))));
+    }
+
+    #[tokio::test]
+    async fn test_selection_per_task_and_knowledge_base() {
+        let state = create_test_app_state().await;
+        let default = create(&state, "Default", AIProvider::Ollama).await;
+        let grader = create(&state, "Grader", AIProvider::OpenAI).await;
+        let private = create(&state, "Private", AIProvider::Mock).await;
+
+        let work = state.db.create_knowledge_base("Work", None).await.unwrap();
+        let hobby = state.db.create_knowledge_base("Hobby", None).await.unwrap();
+
+        let resolved = |kb_id: String, task| {
+            let state = state.clone();
+            async move { state.db.resolve_ai_profile(Some(&kb_id), task).await.unwrap().unwrap().id.unwrap() }
+        };
+        assert_eq!(resolved(hobby.id.clone(), PromptKind::Evaluation).await, default);
+
+        // Globally, grading goes to the stronger model
+        let _ = update_ai_profile_selection(
+            State(state.clone()),
+            Json(AIProfileSelection { question_profile_id: None, evaluation_profile_id: Some(grader) }),
+        ).await.unwrap();
+        assert_eq!(resolved(hobby.id.clone(), PromptKind::Question).await, default);
+        assert_eq!(resolved(hobby.id.clone(), PromptKind::Evaluation).await, grader);
+
+        // The work knowledge base keeps everything on its own profile
+        let both = AIProfileSelection { question_profile_id: Some(private), evaluation_profile_id: Some(private) };
+        let _ = update_knowledge_base_ai_profiles(Path(work.id.clone()), State(state.clone()), Json(both.clone()))
+            .await
+            .unwrap();
+        assert_eq!(resolved(work.id.clone(), PromptKind::Evaluation).await, private);
+        let Json(selection) = get_knowledge_base_ai_profiles(Path(work.id.clone()), State(state.clone())).await.unwrap();
+        assert_eq!(selection, both);
+
+        // Deleting a profile drops the selections that used it
+        let _ = delete_ai_profile(Path(private), State(state.clone())).await.unwrap();
+        assert_eq!(resolved(work.id.clone(), PromptKind::Question).await, default);
+        assert_eq!(resolved(work.id, PromptKind::Evaluation).await, grader);
+
+        let missing = update_ai_profile_selection(
+            State(state.clone()),
+            Json(AIProfileSelection { question_profile_id: Some(999), evaluation_profile_id: None }),
+        ).await;
+        assert!(matches!(missing, Err(AppError::BadRequest(_))));
+    }
+
+    #[tokio::test]
+    async fn test_default_profile_rules() {
+        let state = create_test_app_state().await;
+        let default = create(&state, "Default", AIProvider::Mock).await;
+
+        let delete = delete_ai_profile(Path(default), State(state.clone())).await;
+        assert!(matches!(delete, Err(AppError::BadRequest(_))));
+
+        let mut request = profile_request("Default", AIProvider::Mock);
+        request.is_default = Some(false);
+        let unset = update_ai_profile(Path(default), State(state.clone()), Json(request)).await;
+        assert!(matches!(unset, Err(AppError::BadRequest(_))));
+
+        let Json(tested) = test_ai_profile(Path(default), State(state.clone())).await.unwrap();
+        assert_eq!(tested["status"], "success");
+
+        let missing = get_ai_profile(Path(42), State(state)).await;
+        assert!(matches!(missing, Err(AppError::NotFound(_))));
+    }
+}
@@ -1,15 +1,20 @@
 use axum::{
-    extract::{Path, State},
+    extract::{rejection::JsonRejection, Path, State},
     http::StatusCode,
-    response::Json,
+    response::{
+        sse::{Event, KeepAlive, Sse},
+        Json,
+    },
 };
 use serde::{Deserialize, Serialize};
 use serde_json::{json, Value};
-use std::collections::HashMap;
+use std::convert::Infallible;
+use tokio::sync::mpsc;
+use tokio_stream::wrappers::ReceiverStream;
 use validator::Validate;
 
-use crate::services::{AppState, ai::{AIServiceFactory, AIProviderType}};
-use crate::models::{Question, Answer, AIProvider};
+use crate::services::{AppState, ProviderLoadError, ai::{AIError, AIEvaluation}, fallback::{ProviderChain, Served}, language, prompts::{self, Prompt, PromptVariables}, difficulty::{self, QuestionTarget, DEFAULT_DIFFICULTY}, question_types::{self, GeneratedQuestion, LOCAL_GRADER}, reference_answers, retrieval::{EvidencePassage, Retriever, SourceStrategy, DEFAULT_EVIDENCE_PASSAGES}};
+use crate::models::{CognitiveLevel, CriterionScores, Question, QuestionPayload, QuestionStatus, QuestionType, Answer, RubricWeights, AIUsage, DocumentChunk, PromptKind, PromptTemplate};
 
 #[derive(Debug, Serialize, Deserialize, Validate)]
 pub struct AnswerRequest {
@@ -17,21 +22,72 @@ pub struct AnswerRequest {
     pub user_answer: String,
 }
 
+#[derive(Debug, Default, Serialize, Deserialize, Validate)]
+pub struct GenerateQuestionRequest {
+    /// How to pick the source passage when no chunk_id is given
+    pub strategy: Option<SourceStrategy>,
+    /// Restrict generation to a single document
+    pub document_id: Option<String>,
+    /// Generate from this exact passage
+    pub chunk_id: Option<String>,
+    #[serde(default)]
+    pub question_type: QuestionType,
+    /// 1 (easiest) to 5; with `adaptive`, where to start when no answered question has one
+    #[validate(range(min = 1, max = 5, message = "Difficulty must be between 1 and 5"))]
+    pub difficulty: Option<u8>,
+    pub cognitive_level: Option<CognitiveLevel>,
+    /// Raise or lower the difficulty of the last answered question based on recent scores
+    #[serde(default)]
+    pub adaptive: bool,
+}
+
+/// A question as shown to the learner: the options of a structured question but not its answer key
 #[derive(Debug, Serialize)]
 pub struct QuestionResponse {
     pub id: String,
+    pub knowledge_base_id: String,
     pub question_text: String,
+    pub question_type: QuestionType,
+    /// Choices of a multiple choice question, labelled A, B, C... in order
+    pub options: Option<Vec<String>>,
+    /// Number of correct options, so multiple-select questions can be told apart
+    pub correct_count: Option<usize>,
+    /// Number of `____` gaps in a cloze question
+    pub blanks: Option<usize>,
+    pub difficulty: Option<i32>,
+    pub cognitive_level: Option<CognitiveLevel>,
     pub context_snippet: Option<String>,
+    pub document_id: Option<String>,
+    pub chunk_id: Option<String>,
+    pub page_number: Option<i32>,
     pub generated_at: chrono::DateTime<chrono::Utc>,
+    pub served_by: Option<String>,
 }
 
 impl From<Question> for QuestionResponse {
     fn from(question: Question) -> Self {
+        let (options, correct_count, blanks) = match question.answer_key() {
+            Some(QuestionPayload::MultipleChoice { options, correct }) => (Some(options), Some(correct.len()), None),
+            Some(QuestionPayload::Cloze { answers }) => (None, None, Some(answers.len())),
+            _ => (None, None, None),
+        };
+
         Self {
             id: question.id,
+            knowledge_base_id: question.knowledge_base_id,
             question_text: question.question_text,
+            question_type: question.question_type,
+            options,
+            correct_count,
+            blanks,
+            difficulty: question.difficulty,
+            cognitive_level: question.cognitive_level,
             context_snippet: question.context_snippet,
+            document_id: question.document_id,
+            chunk_id: question.chunk_id,
+            page_number: question.page_number,
             generated_at: question.generated_at,
+            served_by: question.served_by,
         }
     }
 }
@@ -45,13 +101,42 @@ pub struct AnswerResponse {
     pub ai_feedback: Option<String>,
     pub ai_suggestions: Vec<String>,
     pub answered_at: chrono::DateTime<chrono::Utc>,
+    pub evidence: Vec<EvidencePassage>,
+    pub served_by: Option<String>,
+    /// Revealed once the question is answered, so the learner can compare
+    pub reference_answer: Option<String>,
+    pub key_points: Vec<String>,
+    /// Per-criterion scores behind `ai_score`, when the answer was graded with the rubric
+    pub criteria: Option<CriterionScores>,
+    pub matched_key_points: Vec<String>,
+    pub missed_key_points: Vec<String>,
+}
+
+impl AnswerResponse {
+    /// Reveal the question's reference answer and key points
+    pub fn with_reference(mut self, question: &Question) -> Self {
+        if let Some(reference) = question.reference() {
+            self.reference_answer = Some(reference.answer);
+            self.key_points = reference.key_points;
+        }
+        self
+    }
 }
 
 impl From<Answer> for AnswerResponse {
     fn from(answer: Answer) -> Self {
+        let criteria = answer.criteria();
         let suggestions = answer.ai_suggestions
             .map(|s| serde_json::from_str::<Vec<String>>(&s).unwrap_or_else(|_| vec![s]))
             .unwrap_or_default();
+        let evidence = answer.evidence
+            .and_then(|e| serde_json::from_str::<Vec<EvidencePassage>>(&e).ok())
+            .unwrap_or_default();
+        let key_points = |points: Option<&str>| points
+            .and_then(|p| serde_json::from_str::<Vec<String>>(p).ok())
+            .unwrap_or_default();
+        let matched_key_points = key_points(answer.matched_key_points.as_deref());
+        let missed_key_points = key_points(answer.missed_key_points.as_deref());
             
         Self {
             id: answer.id,
@@ -61,17 +146,224 @@ impl From<Answer> for AnswerResponse {
             ai_feedback: answer.ai_feedback,
             ai_suggestions: suggestions,
             answered_at: answer.answered_at,
+            evidence,
+            served_by: answer.served_by,
+            reference_answer: None,
+            key_points: Vec::new(),
+            criteria,
+            matched_key_points,
+            missed_key_points,
         }
     }
 }
 
+// Map a failure to obtain the AI provider onto an HTTP error
+pub(crate) fn provider_error_response(error: ProviderLoadError) -> (StatusCode, Json<Value>) {
+    match error {
+        ProviderLoadError::NotConfigured => (
+            StatusCode::BAD_REQUEST,
+            Json(json!({"error": error.to_string()})),
+        ),
+        ProviderLoadError::Database(e) => {
+            tracing::error!("Failed to get AI config: {}", e);
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({"error": "Failed to retrieve AI configuration"})),
+            )
+        }
+        ProviderLoadError::Provider(e) => {
+            tracing::error!("Failed to create AI provider: {}", e);
+            (
+                StatusCode::BAD_REQUEST,
+                Json(json!({"error": format!("Failed to create AI provider: {}", e)})),
+            )
+        }
+        ProviderLoadError::BudgetExceeded { .. } => (
+            StatusCode::TOO_MANY_REQUESTS,
+            Json(json!({"error": error.to_string()})),
+        ),
+    }
+}
+
+// HTTP status for a failed AI call, so clients can tell timeouts and rate limits apart
+pub(crate) fn ai_error_status(error: &AIError) -> StatusCode {
+    match error {
+        AIError::Timeout(_) => StatusCode::GATEWAY_TIMEOUT,
+        AIError::RateLimited { .. } => StatusCode::TOO_MANY_REQUESTS,
+        AIError::RetriesExhausted { last_error, .. } => ai_error_status(last_error),
+        AIError::StreamFailed { error, .. } => ai_error_status(error),
+        _ => StatusCode::SERVICE_UNAVAILABLE,
+    }
+}
+
 /// Generate a question based on knowledge base content
 pub async fn generate_question(
     Path(kb_id): Path<String>,
     State(state): State<AppState>,
+    payload: Result<Json<GenerateQuestionRequest>, JsonRejection>,
 ) -> Result<Json<Value>, (StatusCode, Json<Value>)> {
+    let request = generation_request(payload)?;
+    let target = question_target(&state, &kb_id, &request).await?;
+    let source = select_question_source(&state, &kb_id, &request).await?;
+    let prompt = question_prompt(&state, &source, request.question_type, &target).await?;
+
+    // Get the providers selected for question generation
+    let providers = state.provider_chain(&kb_id, PromptKind::Question).await.map_err(provider_error_response)?;
+
+    // Generate question using AI
+    let generated = match generate_with(&providers, &prompt, request.question_type).await {
+        Ok(question) => question,
+        Err(e) => {
+            tracing::error!("Failed to generate question: {}", e);
+            return Err((
+                ai_error_status(&e),
+                Json(json!({"error": format!("Failed to generate question: {}", e)})),
+            ));
+        }
+    };
+
+    let question = save_generated_question(&state, &providers, kb_id, generated, &source, &target).await?;
+    let response: QuestionResponse = question.into();
+    Ok(Json(json!(response)))
+}
+
+/// Generate a question, streaming the text over Server-Sent Events as it is produced
+///
+/// Emits `token` events with partial text, then a single `done` event carrying the
+/// saved question, or an `error` event if generation fails. Structured question types
+/// are only shown once complete, so they send no `token` events.
+pub async fn generate_question_stream(
+    Path(kb_id): Path<String>,
+    State(state): State<AppState>,
+    payload: Result<Json<GenerateQuestionRequest>, JsonRejection>,
+) -> Result<Sse<EventStream>, (StatusCode, Json<Value>)> {
+    let request = generation_request(payload)?;
+    let target = question_target(&state, &kb_id, &request).await?;
+    let source = select_question_source(&state, &kb_id, &request).await?;
+    let prompt = question_prompt(&state, &source, request.question_type, &target).await?;
+    let providers = state.provider_chain(&kb_id, PromptKind::Question).await.map_err(provider_error_response)?;
+
+    let (events, stream) = event_channel();
+    tokio::spawn(async move {
+        let result = if request.question_type == QuestionType::Open {
+            let (tokens, token_rx) = mpsc::channel(STREAM_BUFFER);
+            let (result, _) = tokio::join!(
+                providers.stream_question(&prompt, tokens),
+                forward_tokens(token_rx, &events),
+            );
+            result.map(|served| served.map(GeneratedQuestion::open))
+        } else {
+            providers.generate_structured_question(&prompt, request.question_type).await
+        };
+
+        let event = match result {
+            Ok(generated) => match save_generated_question(&state, &providers, kb_id, generated, &source, &target).await {
+                Ok(question) => json_event("done", &QuestionResponse::from(question)),
+                Err((_, Json(body))) => json_event("error", &body),
+            },
+            Err(e) => {
+                tracing::error!("Failed to generate question: {}", e);
+                if let Some(usage) = e.usage() {
+                    let mut usage = usage.clone();
+                    usage.knowledge_base_id = Some(kb_id);
+                    record_usage(&state, &usage).await;
+                }
+                json_event("error", &json!({"error": format!("Failed to generate question: {}", e)}))
+            }
+        };
+        let _ = events.send(Ok(event)).await;
+    });
+
+    Ok(Sse::new(stream).keep_alive(KeepAlive::default()))
+}
+
+// A request without a JSON body asks for the defaults; a body that was sent has to parse
+fn generation_request(
+    payload: Result<Json<GenerateQuestionRequest>, JsonRejection>,
+) -> Result<GenerateQuestionRequest, (StatusCode, Json<Value>)> {
+    match payload {
+        Ok(Json(request)) => Ok(request),
+        Err(JsonRejection::MissingJsonContentType(_)) => Ok(GenerateQuestionRequest::default()),
+        Err(rejection) => Err((
+            StatusCode::BAD_REQUEST,
+            Json(json!({
+                "error": "Invalid request body",
+                "details": rejection.body_text()
+            })),
+        )),
+    }
+}
+
+// Generate a question of the given type; structured types are requested as JSON
+pub(crate) async fn generate_with(
+    providers: &ProviderChain,
+    prompt: &Prompt,
+    question_type: QuestionType,
+) -> Result<Served<GeneratedQuestion>, AIError> {
+    if question_type == QuestionType::Open {
+        providers.generate_question(prompt).await.map(|served| served.map(GeneratedQuestion::open))
+    } else {
+        providers.generate_structured_question(prompt, question_type).await
+    }
+}
+
+// The difficulty and cognitive level to generate for, adapting to recent scores if asked to
+async fn question_target(
+    state: &AppState,
+    kb_id: &str,
+    request: &GenerateQuestionRequest,
+) -> Result<QuestionTarget, (StatusCode, Json<Value>)> {
+    if let Err(validation_errors) = request.validate() {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            Json(json!({
+                "error": "Validation failed",
+                "details": validation_errors.to_string()
+            })),
+        ));
+    }
+
+    let mut target = QuestionTarget {
+        difficulty: request.difficulty,
+        cognitive_level: request.cognitive_level,
+    };
+    if !request.adaptive {
+        return Ok(target);
+    }
+
+    let recent = state.db.get_question_answer_history(kb_id, Some(10), None).await;
+    let progress = state.db.get_learning_progress(kb_id).await;
+    let (recent, progress) = match (recent, progress) {
+        (Ok(recent), Ok(progress)) => (recent, progress),
+        (Err(e), _) | (_, Err(e)) => {
+            tracing::error!("Failed to get learning progress: {}", e);
+            return Err((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({"error": "Failed to retrieve learning progress"})),
+            ));
+        }
+    };
+
+    // Continue from the difficulty of the most recently answered question
+    let current = recent
+        .iter()
+        .find_map(|(question, _)| question.difficulty)
+        .and_then(|difficulty| u8::try_from(difficulty).ok())
+        .or(request.difficulty)
+        .unwrap_or(DEFAULT_DIFFICULTY);
+    target.difficulty = Some(difficulty::adapt_difficulty(current, progress.recent_average_score));
+
+    Ok(target)
+}
+
+// Check the knowledge base and resolve the passage a new question should be based on
+pub(crate) async fn select_question_source(
+    state: &AppState,
+    kb_id: &str,
+    request: &GenerateQuestionRequest,
+) -> Result<DocumentChunk, (StatusCode, Json<Value>)> {
     // Verify knowledge base exists
-    let _knowledge_base = match state.db.get_knowledge_base_by_id(&kb_id).await {
+    let _knowledge_base = match state.db.get_knowledge_base_by_id(kb_id).await {
         Ok(Some(kb)) => kb,
         Ok(None) => {
             return Err((
@@ -89,7 +381,7 @@ pub async fn generate_question(
     };
 
     // Get documents from the knowledge base
-    let documents = match state.db.get_documents_by_knowledge_base(&kb_id).await {
+    let documents = match state.db.get_documents_by_knowledge_base(kb_id).await {
         Ok(docs) => docs,
         Err(e) => {
             tracing::error!("Failed to get documents: {}", e);
@@ -107,123 +399,203 @@ pub async fn generate_question(
         ));
     }
 
-    // Combine document content for context
-    let mut context = String::new();
-    let mut context_snippet = String::new();
-    
-    for doc in &documents {
-        if let Some(content) = &doc.content_text {
-            context.push_str(content);
-            context.push_str("\n\n");
-            
-            // Use first document's content as context snippet (truncated)
-            if context_snippet.is_empty() {
-                context_snippet = content.chars().take(500).collect();
-                if content.len() > 500 {
-                    context_snippet.push_str("...");
-                }
-            }
-        }
-    }
-
-    if context.trim().is_empty() {
+    // Pick the passage the question will be generated from
+    if let Some(document_id) = &request.document_id
+        && !documents.iter().any(|doc| &doc.id == document_id)
+    {
         return Err((
-            StatusCode::BAD_REQUEST,
-            Json(json!({"error": "No content found in documents. Please ensure documents are properly parsed."})),
+            StatusCode::NOT_FOUND,
+            Json(json!({"error": "Document not found in this knowledge base"})),
         ));
     }
-
-    // Get AI configuration
-    let ai_config = match state.db.get_ai_config().await {
-        Ok(Some(config)) => config,
-        Ok(None) => {
-            return Err((
-                StatusCode::BAD_REQUEST,
-                Json(json!({"error": "AI not configured. Please configure AI settings first."})),
-            ));
-        }
-        Err(e) => {
-            tracing::error!("Failed to get AI config: {}", e);
-            return Err((
-                StatusCode::INTERNAL_SERVER_ERROR,
-                Json(json!({"error": "Failed to retrieve AI configuration"})),
-            ));
-        }
-    };
-
-    // Create AI provider
-    let provider_type = match ai_config.provider {
-        AIProvider::DeepSeek => AIProviderType::DeepSeek,
-        AIProvider::Local => AIProviderType::Local,
-        AIProvider::OpenAI => {
-            return Err((
-                StatusCode::NOT_IMPLEMENTED,
-                Json(json!({"error": "OpenAI provider not yet implemented"})),
-            ));
-        }
-    };
-
-    let mut provider_config = HashMap::new();
     
-    match ai_config.provider {
-        AIProvider::DeepSeek => {
-            if let Some(api_key) = ai_config.api_key {
-                provider_config.insert("api_key".to_string(), api_key);
-            } else {
+    let source = if let Some(chunk_id) = &request.chunk_id {
+        match state.db.get_chunk_by_id(chunk_id).await {
+            Ok(Some(chunk)) if chunk.knowledge_base_id == kb_id => Some(chunk),
+            Ok(_) => {
                 return Err((
-                    StatusCode::BAD_REQUEST,
-                    Json(json!({"error": "API key not configured for DeepSeek"})),
+                    StatusCode::NOT_FOUND,
+                    Json(json!({"error": "Source passage not found in this knowledge base"})),
+                ));
+            }
+            Err(e) => {
+                tracing::error!("Failed to get source chunk: {}", e);
+                return Err((
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    Json(json!({"error": "Failed to retrieve source passage"})),
                 ));
             }
         }
-        AIProvider::Local => {
-            if let Some(api_url) = ai_config.api_url {
-                provider_config.insert("api_url".to_string(), api_url);
-            } else {
+    } else {
+        match Retriever::select_source(
+            &state.db,
+            kb_id,
+            request.strategy.unwrap_or_default(),
+            request.document_id.as_deref(),
+        ).await {
+            Ok(chunk) => chunk,
+            Err(e) => {
+                tracing::error!("Failed to select source passage: {}", e);
                 return Err((
-                    StatusCode::BAD_REQUEST,
-                    Json(json!({"error": "API URL not configured for Local AI"})),
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    Json(json!({"error": "Failed to select source passage"})),
                 ));
             }
         }
-        _ => {}
-    }
+    };
+    
+    source.ok_or_else(|| (
+        StatusCode::BAD_REQUEST,
+        Json(json!({"error": "No content found in documents. Please ensure documents are properly parsed."})),
+    ))
+}
+
+// Render the default question template for a source passage, asking for the target difficulty
+// and the type's output format
+pub(crate) async fn question_prompt(
+    state: &AppState,
+    source: &DocumentChunk,
+    question_type: QuestionType,
+    target: &QuestionTarget,
+) -> Result<Prompt, (StatusCode, Json<Value>)> {
+    let language = output_language(state, &source.knowledge_base_id, &source.content).await?;
+    let variables = PromptVariables {
+        context: Some(source.content.clone()),
+        language: Some(language.to_string()),
+        difficulty: Some(target.guidance()),
+        ..Default::default()
+    };
+    let mut prompt = render_default_prompt(state, PromptKind::Question, &variables).await?;
 
-    if let Some(model_name) = ai_config.model_name {
-        provider_config.insert("model".to_string(), model_name);
+    // Appended last so it overrides the template's "return only the question" instruction
+    if let Some(instructions) = question_types::format_instructions(question_type) {
+        prompt.system = Some(match prompt.system {
+            Some(system) => format!("{}\n\n{}", system, instructions),
+            None => instructions.to_string(),
+        });
     }
-    provider_config.insert("max_tokens".to_string(), ai_config.max_tokens.to_string());
-    provider_config.insert("temperature".to_string(), ai_config.temperature.to_string());
 
-    let ai_provider = match AIServiceFactory::create_provider(provider_type, provider_config) {
-        Ok(provider) => provider,
+    Ok(prompt)
+}
+
+// Render the default evaluation template for an answer, its evidence and the question's reference answer
+async fn evaluation_prompt(
+    state: &AppState,
+    question: &Question,
+    user_answer: &str,
+    evidence: &[EvidencePassage],
+) -> Result<Prompt, (StatusCode, Json<Value>)> {
+    // Feedback follows the language of the question's source material
+    let sample = evidence
+        .first()
+        .map(|passage| passage.content.as_str())
+        .unwrap_or(&question.question_text);
+    let language = output_language(state, &question.knowledge_base_id, sample).await?;
+    let template = default_prompt_template(state, PromptKind::Evaluation).await?;
+
+    let variables = PromptVariables {
+        context: Some(EvidencePassage::format_context(evidence)),
+        question: Some(question.question_text.clone()),
+        answer: Some(user_answer.to_string()),
+        language: Some(language.to_string()),
+        reference: Some(reference_answers::evaluation_reference(question.reference().as_ref(), language)),
+        ..Default::default()
+    };
+    Ok(prompts::render(&template, &variables))
+}
+
+// The knowledge base's language setting, or the language detected in `sample`
+pub(crate) async fn output_language(
+    state: &AppState,
+    kb_id: &str,
+    sample: &str,
+) -> Result<&'static str, (StatusCode, Json<Value>)> {
+    match state.db.get_knowledge_base_by_id(kb_id).await {
+        Ok(kb) => Ok(language::resolve_language(
+            kb.as_ref().and_then(|kb| kb.language.as_deref()),
+            sample,
+        )),
         Err(e) => {
-            tracing::error!("Failed to create AI provider: {}", e);
-            return Err((
+            tracing::error!("Failed to get knowledge base: {}", e);
+            Err((
                 StatusCode::INTERNAL_SERVER_ERROR,
-                Json(json!({"error": format!("Failed to create AI provider: {}", e)})),
-            ));
+                Json(json!({"error": "Failed to retrieve knowledge base"})),
+            ))
         }
-    };
+    }
+}
 
-    // Generate question using AI
-    let question_text = match ai_provider.generate_question(&context).await {
-        Ok(question) => question,
+async fn render_default_prompt(
+    state: &AppState,
+    kind: PromptKind,
+    variables: &PromptVariables,
+) -> Result<Prompt, (StatusCode, Json<Value>)> {
+    let template = default_prompt_template(state, kind).await?;
+    Ok(prompts::render(&template, variables))
+}
+
+async fn default_prompt_template(
+    state: &AppState,
+    kind: PromptKind,
+) -> Result<PromptTemplate, (StatusCode, Json<Value>)> {
+    match state.db.get_default_prompt_template(kind).await {
+        Ok(Some(template)) => Ok(template),
+        Ok(None) => {
+            tracing::error!("No default {} prompt template", kind);
+            Err((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({"error": format!("No default {} prompt template is configured", kind)})),
+            ))
+        }
         Err(e) => {
-            tracing::error!("Failed to generate question: {}", e);
-            return Err((
-                StatusCode::SERVICE_UNAVAILABLE,
-                Json(json!({"error": format!("Failed to generate question: {}", e)})),
-            ));
+            tracing::error!("Failed to get prompt template: {}", e);
+            Err((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({"error": "Failed to retrieve prompt template"})),
+            ))
         }
-    };
+    }
+}
 
-    // Create and save question
-    let question = Question::new(
+// The question to store for a generated reply, citing the passage it was generated from
+pub(crate) fn generated_question_record(
+    kb_id: String,
+    generated: GeneratedQuestion,
+    served_by: String,
+    source: &DocumentChunk,
+    target: &QuestionTarget,
+) -> Question {
+    let mut question = Question::new(
         kb_id,
-        question_text,
-        Some(context_snippet),
-    );
+        generated.question_text,
+        Some(source.content.clone()),
+    ).with_source(source);
+    if let Some(payload) = &generated.payload {
+        question = question
+            .with_payload(payload)
+            .with_reference(&reference_answers::from_answer_key(payload));
+    }
+    question.served_by = Some(served_by);
+    question.difficulty = target.difficulty.map(i32::from);
+    question.cognitive_level = target.cognitive_level;
+    question
+}
+
+// Save a generated question with its reference answer, citing the passage it was generated from
+async fn save_generated_question(
+    state: &AppState,
+    providers: &ProviderChain,
+    kb_id: String,
+    generated: Served<GeneratedQuestion>,
+    source: &DocumentChunk,
+    target: &QuestionTarget,
+) -> Result<Question, (StatusCode, Json<Value>)> {
+    let question = generated_question_record(kb_id, generated.value, generated.served_by, source, target);
+    let (question, reference_usage) = if writes_reference_answers(state, &question.knowledge_base_id).await {
+        add_reference_answer(state, providers, question, source).await
+    } else {
+        (question, None)
+    };
 
     if let Err(e) = state.db.save_question(&question).await {
         tracing::error!("Failed to save question: {}", e);
@@ -233,28 +605,51 @@ pub async fn generate_question(
         ));
     }
 
-    let response: QuestionResponse = question.into();
-    Ok(Json(json!(response)))
+    let mut usage = generated.usage;
+    usage.knowledge_base_id = Some(question.knowledge_base_id.clone());
+    usage.question_id = Some(question.id.clone());
+    record_usage(state, &usage).await;
+    if let Some(mut usage) = reference_usage {
+        usage.knowledge_base_id = Some(question.knowledge_base_id.clone());
+        usage.question_id = Some(question.id.clone());
+        record_usage(state, &usage).await;
+    }
+
+    Ok(question)
 }
 
-/// Submit and evaluate an answer
-pub async fn submit_answer(
+// Have the model write the reference answer of an open question, returning the usage to record
+// once the question is saved. Structured questions take theirs from the answer key; if the call
+// fails the question is kept without one and graded against the material alone.
+pub(crate) async fn add_reference_answer(
+    state: &AppState,
+    providers: &ProviderChain,
+    question: Question,
+    source: &DocumentChunk,
+) -> (Question, Option<AIUsage>) {
+    if question.reference_answer.is_some() {
+        return (question, None);
+    }
+
+    let language = output_language(state, &question.knowledge_base_id, &source.content)
+        .await
+        .unwrap_or("English");
+    let prompt = reference_answers::reference_prompt(&source.content, &question.question_text, language);
+
+    match providers.generate_reference_answer(&prompt).await {
+        Ok(Served { value, usage, .. }) => (question.with_reference(&value), Some(usage)),
+        Err(e) => {
+            tracing::warn!("Failed to generate a reference answer for question {}: {}", question.id, e);
+            (question, None)
+        }
+    }
+}
+
+/// Get the source passage a question was generated from
+pub async fn get_question_source(
     Path(question_id): Path<String>,
     State(state): State<AppState>,
-    Json(payload): Json<AnswerRequest>,
 ) -> Result<Json<Value>, (StatusCode, Json<Value>)> {
-    // Validate the request
-    if let Err(validation_errors) = payload.validate() {
-        return Err((
-            StatusCode::BAD_REQUEST,
-            Json(json!({
-                "error": "Validation failed",
-                "details": validation_errors.to_string()
-            })),
-        ));
-    }
-
-    // Get the question
     let question = match state.db.get_question_by_id(&question_id).await {
         Ok(Some(q)) => q,
         Ok(None) => {
@@ -272,121 +667,229 @@ pub async fn submit_answer(
         }
     };
 
-    // Get knowledge base documents for context
-    let documents = match state.db.get_documents_by_knowledge_base(&question.knowledge_base_id).await {
-        Ok(docs) => docs,
+    let chunk = match &question.chunk_id {
+        Some(chunk_id) => state.db.get_chunk_by_id(chunk_id).await.unwrap_or_else(|e| {
+            tracing::error!("Failed to get source chunk: {}", e);
+            None
+        }),
+        None => None,
+    };
+
+    let document = match &question.document_id {
+        Some(document_id) => state.db.get_document_by_id(document_id).await.unwrap_or_else(|e| {
+            tracing::error!("Failed to get source document: {}", e);
+            None
+        }),
+        None => None,
+    };
+
+    // Questions generated before source tracking only have the snippet
+    Ok(Json(json!({
+        "question_id": question.id,
+        "document_id": question.document_id,
+        "filename": document.map(|doc| doc.filename),
+        "chunk_id": question.chunk_id,
+        "chunk_index": chunk.as_ref().map(|c| c.chunk_index),
+        "page_number": question.page_number,
+        "start_offset": chunk.as_ref().map(|c| c.start_offset),
+        "end_offset": chunk.as_ref().map(|c| c.end_offset),
+        "content": chunk.map(|c| c.content).or(question.context_snippet),
+    })))
+}
+
+/// Submit and evaluate an answer
+///
+/// Answers to multiple choice, true/false and cloze questions, and short answers matching
+/// the expected wording, are graded against the answer key without calling the AI.
+pub async fn submit_answer(
+    Path(question_id): Path<String>,
+    State(state): State<AppState>,
+    Json(payload): Json<AnswerRequest>,
+) -> Result<Json<Value>, (StatusCode, Json<Value>)> {
+    let (question, evidence) = prepare_evaluation(&state, &question_id, &payload).await?;
+
+    if let Some(evaluation) = question_types::grade(&question, &payload.user_answer) {
+        let answer = store_answer(&state, &question, payload.user_answer, evaluation, LOCAL_GRADER, &evidence).await?;
+        let response = AnswerResponse::from(answer).with_reference(&question);
+        return Ok(Json(json!(response)));
+    }
+
+    let prompt = evaluation_prompt(&state, &question, &payload.user_answer, &evidence).await?;
+
+    // Get the providers selected for grading
+    let providers = state.provider_chain(&question.knowledge_base_id, PromptKind::Evaluation).await.map_err(provider_error_response)?;
+
+    // Evaluate answer using AI
+    let evaluation = match providers.evaluate_answer(&prompt).await {
+        Ok(eval) => eval,
         Err(e) => {
-            tracing::error!("Failed to get documents: {}", e);
+            tracing::error!("Failed to evaluate answer: {}", e);
             return Err((
-                StatusCode::INTERNAL_SERVER_ERROR,
-                Json(json!({"error": "Failed to retrieve documents"})),
+                ai_error_status(&e),
+                Json(json!({"error": format!("Failed to evaluate answer: {}", e)})),
             ));
         }
     };
 
-    // Combine document content for context
-    let mut context = String::new();
-    for doc in &documents {
-        if let Some(content) = &doc.content_text {
-            context.push_str(content);
-            context.push_str("\n\n");
-        }
+    let answer = save_evaluated_answer(&state, &question, payload.user_answer, evaluation, &evidence).await?;
+    let response = AnswerResponse::from(answer).with_reference(&question);
+    Ok(Json(json!(response)))
+}
+
+/// Evaluate an answer, streaming the model output over Server-Sent Events
+///
+/// Emits `token` events with partial output, then a single `done` event carrying the
+/// saved answer, or an `error` event if evaluation fails.
+pub async fn submit_answer_stream(
+    Path(question_id): Path<String>,
+    State(state): State<AppState>,
+    Json(payload): Json<AnswerRequest>,
+) -> Result<Sse<EventStream>, (StatusCode, Json<Value>)> {
+    let (question, evidence) = prepare_evaluation(&state, &question_id, &payload).await?;
+    let (events, stream) = event_channel();
+
+    // Locally graded answers are complete at once
+    if let Some(evaluation) = question_types::grade(&question, &payload.user_answer) {
+        let answer = store_answer(&state, &question, payload.user_answer, evaluation, LOCAL_GRADER, &evidence).await?;
+        let _ = events.send(Ok(json_event("done", &AnswerResponse::from(answer).with_reference(&question)))).await;
+        return Ok(Sse::new(stream).keep_alive(KeepAlive::default()));
     }
 
-    // Get AI configuration
-    let ai_config = match state.db.get_ai_config().await {
-        Ok(Some(config)) => config,
+    let prompt = evaluation_prompt(&state, &question, &payload.user_answer, &evidence).await?;
+    let providers = state.provider_chain(&question.knowledge_base_id, PromptKind::Evaluation).await.map_err(provider_error_response)?;
+
+    tokio::spawn(async move {
+        let (tokens, token_rx) = mpsc::channel(STREAM_BUFFER);
+        let (result, _) = tokio::join!(
+            providers.stream_evaluation(&prompt, tokens),
+            forward_tokens(token_rx, &events),
+        );
+
+        let event = match result {
+            Ok(evaluation) => match save_evaluated_answer(&state, &question, payload.user_answer, evaluation, &evidence).await {
+                Ok(answer) => json_event("done", &AnswerResponse::from(answer).with_reference(&question)),
+                Err((_, Json(body))) => json_event("error", &body),
+            },
+            Err(e) => {
+                tracing::error!("Failed to evaluate answer: {}", e);
+                if let Some(usage) = e.usage() {
+                    let mut usage = usage.clone();
+                    usage.knowledge_base_id = Some(question.knowledge_base_id.clone());
+                    usage.question_id = Some(question.id.clone());
+                    record_usage(&state, &usage).await;
+                }
+                json_event("error", &json!({"error": format!("Failed to evaluate answer: {}", e)}))
+            }
+        };
+        let _ = events.send(Ok(event)).await;
+    });
+
+    Ok(Sse::new(stream).keep_alive(KeepAlive::default()))
+}
+
+// Validate an answer and load its question with the passages it will be judged against
+async fn prepare_evaluation(
+    state: &AppState,
+    question_id: &str,
+    payload: &AnswerRequest,
+) -> Result<(Question, Vec<EvidencePassage>), (StatusCode, Json<Value>)> {
+    // Validate the request
+    if let Err(validation_errors) = payload.validate() {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            Json(json!({
+                "error": "Validation failed",
+                "details": validation_errors.to_string()
+            })),
+        ));
+    }
+
+    // Get the question
+    let question = match state.db.get_question_by_id(question_id).await {
+        Ok(Some(q)) => q,
         Ok(None) => {
             return Err((
-                StatusCode::BAD_REQUEST,
-                Json(json!({"error": "AI not configured. Please configure AI settings first."})),
+                StatusCode::NOT_FOUND,
+                Json(json!({"error": "Question not found"})),
             ));
         }
         Err(e) => {
-            tracing::error!("Failed to get AI config: {}", e);
+            tracing::error!("Failed to get question: {}", e);
             return Err((
                 StatusCode::INTERNAL_SERVER_ERROR,
-                Json(json!({"error": "Failed to retrieve AI configuration"})),
-            ));
-        }
-    };
-
-    // Create AI provider
-    let provider_type = match ai_config.provider {
-        AIProvider::DeepSeek => AIProviderType::DeepSeek,
-        AIProvider::Local => AIProviderType::Local,
-        AIProvider::OpenAI => {
-            return Err((
-                StatusCode::NOT_IMPLEMENTED,
-                Json(json!({"error": "OpenAI provider not yet implemented"})),
+                Json(json!({"error": "Failed to retrieve question"})),
             ));
         }
     };
 
-    let mut provider_config = HashMap::new();
-    
-    match ai_config.provider {
-        AIProvider::DeepSeek => {
-            if let Some(api_key) = ai_config.api_key {
-                provider_config.insert("api_key".to_string(), api_key);
-            } else {
-                return Err((
-                    StatusCode::BAD_REQUEST,
-                    Json(json!({"error": "API key not configured for DeepSeek"})),
-                ));
-            }
-        }
-        AIProvider::Local => {
-            if let Some(api_url) = ai_config.api_url {
-                provider_config.insert("api_url".to_string(), api_url);
-            } else {
-                return Err((
-                    StatusCode::BAD_REQUEST,
-                    Json(json!({"error": "API URL not configured for Local AI"})),
-                ));
-            }
-        }
-        _ => {}
-    }
-
-    if let Some(model_name) = ai_config.model_name {
-        provider_config.insert("model".to_string(), model_name);
+    if question.status == QuestionStatus::Draft {
+        return Err((
+            StatusCode::CONFLICT,
+            Json(json!({"error": "Question is a draft in the question bank; approve it before answering"})),
+        ));
     }
-    provider_config.insert("max_tokens".to_string(), ai_config.max_tokens.to_string());
-    provider_config.insert("temperature".to_string(), ai_config.temperature.to_string());
 
-    let ai_provider = match AIServiceFactory::create_provider(provider_type, provider_config) {
-        Ok(provider) => provider,
+    // Evaluate against the question's source passage plus the most related passages; any key
+    // points are added to the prompt on top of them
+    let evidence = match Retriever::gather_evidence(&state.db, &question, DEFAULT_EVIDENCE_PASSAGES).await {
+        Ok(evidence) => evidence,
         Err(e) => {
-            tracing::error!("Failed to create AI provider: {}", e);
+            tracing::error!("Failed to gather evidence: {}", e);
             return Err((
                 StatusCode::INTERNAL_SERVER_ERROR,
-                Json(json!({"error": format!("Failed to create AI provider: {}", e)})),
+                Json(json!({"error": "Failed to retrieve reference passages"})),
             ));
         }
     };
 
-    // Evaluate answer using AI
-    let evaluation = match ai_provider.evaluate_answer(
-        &question.question_text,
-        &payload.user_answer,
-        &context,
-    ).await {
-        Ok(eval) => eval,
-        Err(e) => {
-            tracing::error!("Failed to evaluate answer: {}", e);
-            return Err((
-                StatusCode::SERVICE_UNAVAILABLE,
-                Json(json!({"error": format!("Failed to evaluate answer: {}", e)})),
-            ));
-        }
-    };
+    Ok((question, evidence))
+}
+
+// Save an AI-evaluated answer and account for the tokens the evaluation used
+async fn save_evaluated_answer(
+    state: &AppState,
+    question: &Question,
+    user_answer: String,
+    evaluation: Served<AIEvaluation>,
+    evidence: &[EvidencePassage],
+) -> Result<Answer, (StatusCode, Json<Value>)> {
+    let Served { value: evaluation, served_by, mut usage } = evaluation;
+    let answer = store_answer(state, question, user_answer, evaluation, &served_by, evidence).await?;
+
+    usage.knowledge_base_id = Some(question.knowledge_base_id.clone());
+    usage.question_id = Some(question.id.clone());
+    usage.answer_id = Some(answer.id.clone());
+    record_usage(state, &usage).await;
 
-    // Create and save answer
-    let mut answer = Answer::new(question_id, payload.user_answer);
-    answer.ai_score = Some(evaluation.score as i32);
+    Ok(answer)
+}
+
+// Save an evaluated answer along with the evidence it was scored against
+async fn store_answer(
+    state: &AppState,
+    question: &Question,
+    user_answer: String,
+    evaluation: AIEvaluation,
+    served_by: &str,
+    evidence: &[EvidencePassage],
+) -> Result<Answer, (StatusCode, Json<Value>)> {
+    // Unscored answers keep a NULL score so they don't skew progress statistics
+    let mut answer = Answer::new(question.id.clone(), user_answer);
+    answer.ai_score = evaluation.score.map(i32::from);
+    if let Some(criteria) = &evaluation.criteria {
+        // The knowledge base's weights decide the overall score, not the model's own total
+        let weights = rubric_weights(state, &question.knowledge_base_id).await;
+        answer = answer.with_criteria(criteria);
+        answer.ai_score = Some(i32::from(criteria.weighted_score(&weights)));
+    }
+    if evaluation.criteria.is_some() || !evaluation.matched_key_points.is_empty() || !evaluation.missed_key_points.is_empty() {
+        answer.matched_key_points = Some(serde_json::to_string(&evaluation.matched_key_points).unwrap_or_default());
+        answer.missed_key_points = Some(serde_json::to_string(&evaluation.missed_key_points).unwrap_or_default());
+    }
     answer.ai_feedback = Some(evaluation.feedback);
     answer.ai_suggestions = Some(serde_json::to_string(&evaluation.suggestions).unwrap_or_default());
+    answer.evidence = Some(serde_json::to_string(evidence).unwrap_or_default());
+    answer.served_by = Some(served_by.to_string());
 
     if let Err(e) = state.db.save_answer(&answer).await {
         tracing::error!("Failed to save answer: {}", e);
@@ -396,8 +899,62 @@ pub async fn submit_answer(
         ));
     }
 
-    let response: AnswerResponse = answer.into();
-    Ok(Json(json!(response)))
+    Ok(answer)
+}
+
+// A knowledge base that can't be read is scored with the default rubric rather than failing the answer
+async fn rubric_weights(state: &AppState, knowledge_base_id: &str) -> RubricWeights {
+    match state.db.get_knowledge_base_by_id(knowledge_base_id).await {
+        Ok(kb) => kb.map(|kb| kb.rubric()).unwrap_or_default(),
+        Err(e) => {
+            tracing::warn!("Failed to load rubric weights, using the defaults: {}", e);
+            RubricWeights::default()
+        }
+    }
+}
+
+// Knowledge bases write reference answers unless turned off; one that can't be read still does
+pub(crate) async fn writes_reference_answers(state: &AppState, knowledge_base_id: &str) -> bool {
+    match state.db.get_knowledge_base_by_id(knowledge_base_id).await {
+        Ok(kb) => kb.is_none_or(|kb| kb.reference_answers),
+        Err(e) => {
+            tracing::warn!("Failed to load the reference answer setting, writing one: {}", e);
+            true
+        }
+    }
+}
+
+// Usage accounting never fails the request it belongs to
+pub(crate) async fn record_usage(state: &AppState, usage: &AIUsage) {
+    if let Err(e) = state.db.record_ai_usage(usage).await {
+        tracing::error!("Failed to record AI usage: {}", e);
+    }
+}
+
+// Server-Sent Events plumbing shared by the streaming endpoints
+
+type EventStream = ReceiverStream<Result<Event, Infallible>>;
+
+const STREAM_BUFFER: usize = 64;
+
+fn event_channel() -> (mpsc::Sender<Result<Event, Infallible>>, EventStream) {
+    let (tx, rx) = mpsc::channel(STREAM_BUFFER);
+    (tx, ReceiverStream::new(rx))
+}
+
+fn json_event<T: Serialize>(name: &str, data: &T) -> Event {
+    Event::default()
+        .event(name)
+        .json_data(data)
+        .unwrap_or_else(|_| Event::default().event("error").data("Failed to encode event"))
+}
+
+// Relay model output as `token` events until the provider finishes
+async fn forward_tokens(mut tokens: mpsc::Receiver<String>, events: &mpsc::Sender<Result<Event, Infallible>>) {
+    while let Some(text) = tokens.recv().await {
+        // A closed connection just drops the tokens; the result is still saved
+        let _ = events.send(Ok(json_event("token", &json!({"text": text})))).await;
+    }
 }
 
 #[cfg(test)]
@@ -405,14 +962,12 @@ mod tests {
     use super::*;
     use crate::database::create_connection_pool;
     use crate::services::AppState;
-    use crate::models::{KnowledgeBase, Document, DocumentType, AIConfig, AIProvider};
+    use crate::models::{Document, DocumentType, AIConfig, AIModelPrice, AIProvider, Criterion, UsageGrouping};
     use axum::extract::{Path, State};
-    use tempfile::NamedTempFile;
 
     async fn create_test_app_state() -> AppState {
-        let temp_file = NamedTempFile::new().unwrap();
-        let database_url = format!("sqlite:{}", temp_file.path().to_str().unwrap());
-        let pool = create_connection_pool(&database_url).await.unwrap();
+        // Use in-memory database for tests
+        let pool = create_connection_pool("sqlite::memory:").await.unwrap();
         AppState::new(pool)
     }
 
@@ -431,20 +986,359 @@ mod tests {
         );
         state.db.save_document(&document).await.unwrap();
         
-        // Create AI config
-        let ai_config = AIConfig::new(
-            AIProvider::DeepSeek,
-            Some("test-api-key".to_string()),
-            None,
-            Some("deepseek-chat".to_string()),
-            1000,
-            0.7,
+        // Use the offline mock provider with scripted replies
+        let mut ai_config = AIConfig::new(AIProvider::Mock, None, None, None, 1000, 0.7);
+        ai_config.provider_options = Some(
+            json!({
+                "questions": ["What separates AI from machine learning?"],
+                "evaluations": [{"score": 78, "feedback": "Mostly right", "suggestions": ["Give an example"]}],
+            })
+            .to_string(),
         );
         state.db.save_ai_config(&ai_config).await.unwrap();
         
         (kb.id, document.id)
     }
 
+    #[tokio::test]
+    async fn test_generate_and_answer_with_mock_provider() {
+        let state = create_test_app_state().await;
+        let (kb_id, document_id) = setup_test_data(&state).await;
+
+        let Json(question) = generate_question(Path(kb_id.clone()), State(state.clone()), Ok(Json(GenerateQuestionRequest::default()))).await.unwrap();
+        assert_eq!(question["question_text"], "What separates AI from machine learning?");
+        assert_eq!(question["document_id"], document_id.as_str());
+
+        let question_id = question["id"].as_str().unwrap().to_string();
+        let Json(answer) = submit_answer(
+            Path(question_id.clone()),
+            State(state.clone()),
+            Json(AnswerRequest { user_answer: "Machine learning is a subset of AI".to_string() }),
+        ).await.unwrap();
+        assert_eq!(answer["ai_score"], 78);
+        assert_eq!(answer["ai_feedback"], "Mostly right");
+        // The reference answer is kept back until the question is answered
+        assert!(question.get("reference_answer").is_none());
+        assert!(answer["reference_answer"].as_str().unwrap().contains("This is test content"));
+        assert_eq!(answer["key_points"][0], "This is test content for generating questions.");
+
+        let saved = state.db.get_answers_by_question(&question_id).await.unwrap();
+        assert_eq!(saved[0].ai_score, Some(78));
+
+        // Question, reference answer and evaluation calls are accounted to the knowledge base
+        let usage = state.db.get_ai_usage_summary(UsageGrouping::KnowledgeBase, None, None).await.unwrap();
+        assert_eq!(usage.len(), 1);
+        assert_eq!(usage[0].key, kb_id);
+        assert_eq!(usage[0].calls, 3);
+        assert!(usage[0].prompt_tokens > 0);
+    }
+
+    #[tokio::test]
+    async fn test_reference_answers_can_be_turned_off() {
+        let state = create_test_app_state().await;
+        let (kb_id, _) = setup_test_data(&state).await;
+        state.db.set_knowledge_base_reference_answers(&kb_id, false).await.unwrap();
+
+        // Only the question call is made, and the answer is graded against the material alone
+        let Json(question) = generate_question(Path(kb_id), State(state.clone()), Ok(Json(GenerateQuestionRequest::default()))).await.unwrap();
+        let question = state.db.get_question_by_id(question["id"].as_str().unwrap()).await.unwrap().unwrap();
+        assert!(question.reference().is_none());
+
+        let usage = state.db.get_ai_usage_summary(UsageGrouping::KnowledgeBase, None, None).await.unwrap();
+        assert_eq!(usage[0].calls, 1);
+    }
+
+    // A knowledge base whose question comes with a reference answer of two key points
+    async fn setup_question_with_key_points(state: &AppState) -> Question {
+        let (kb_id, _) = setup_test_data(state).await;
+
+        let mut ai_config = state.db.get_ai_config().await.unwrap().unwrap();
+        ai_config.provider_options = Some(
+            json!({
+                "questions": ["How does AI relate to machine learning?"],
+                "references": [{"answer": "Machine learning is a branch of AI.", "key_points": ["ML is a subset of AI", "ML learns from data"]}],
+            })
+            .to_string(),
+        );
+        state.db.save_ai_config(&ai_config).await.unwrap();
+
+        let Json(question) = generate_question(Path(kb_id), State(state.clone()), Ok(Json(GenerateQuestionRequest::default()))).await.unwrap();
+        state.db.get_question_by_id(question["id"].as_str().unwrap()).await.unwrap().unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_answers_are_evaluated_against_key_points() {
+        let state = create_test_app_state().await;
+        let question = setup_question_with_key_points(&state).await;
+        assert_eq!(question.reference().unwrap().key_points.len(), 2);
+
+        let evidence = Retriever::gather_evidence(&state.db, &question, 0).await.unwrap();
+        let prompt = evaluation_prompt(&state, &question, "It is part of AI", &evidence).await.unwrap();
+        assert!(prompt.user.contains("Reference answer: Machine learning is a branch of AI.\nKey points:\n- ML is a subset of AI\n- ML learns from data\n\nLearner's answer: It is part of AI"));
+        assert!(prompt.system.unwrap().contains("score mainly by how many key points"));
+    }
+
+    #[tokio::test]
+    async fn test_key_points_are_labelled_in_the_knowledge_base_language() {
+        let state = create_test_app_state().await;
+        let question = setup_question_with_key_points(&state).await;
+
+        // A knowledge base set to Chinese gets the reference labelled in Chinese, whatever the material
+        state.db.set_knowledge_base_language(&question.knowledge_base_id, Some("zh")).await.unwrap();
+        let evidence = Retriever::gather_evidence(&state.db, &question, 0).await.unwrap();
+        let prompt = evaluation_prompt(&state, &question, "It is part of AI", &evidence).await.unwrap();
+        assert!(prompt.user.contains("参考答案：Machine learning is a branch of AI.\n要点：\n- ML is a subset of AI"));
+        assert!(!prompt.user.contains("Reference answer:"));
+    }
+
+    #[tokio::test]
+    async fn test_key_points_add_to_related_evidence() {
+        let state = create_test_app_state().await;
+        let question = setup_question_with_key_points(&state).await;
+
+        // Key points come on top of the related passages, not instead of them
+        let related = Document::new(
+            question.knowledge_base_id.clone(),
+            "more.txt".to_string(),
+            DocumentType::Txt,
+            "/tmp/more.txt".to_string(),
+            100,
+            Some("Machine learning systems learn patterns from data.".to_string()),
+        );
+        state.db.save_document(&related).await.unwrap();
+        let _ = submit_answer(
+            Path(question.id.clone()),
+            State(state.clone()),
+            Json(AnswerRequest { user_answer: "It is part of AI".to_string() }),
+        ).await.unwrap();
+        let answer = &state.db.get_answers_by_question(&question.id).await.unwrap()[0];
+        let evidence: Vec<EvidencePassage> = serde_json::from_str(answer.evidence.as_deref().unwrap()).unwrap();
+        assert_eq!(evidence.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_failed_reference_call_keeps_question_answerable() {
+        let state = create_test_app_state().await;
+        let (kb_id, _) = setup_test_data(&state).await;
+        let mut ai_config = state.db.get_ai_config().await.unwrap().unwrap();
+        ai_config.provider_options = Some(json!({"questions": ["What is AI?"], "references": ["no reference"]}).to_string());
+        state.db.save_ai_config(&ai_config).await.unwrap();
+
+        let Json(question) = generate_question(Path(kb_id), State(state.clone()), Ok(Json(GenerateQuestionRequest::default()))).await.unwrap();
+        let question = state.db.get_question_by_id(question["id"].as_str().unwrap()).await.unwrap().unwrap();
+        assert!(question.reference().is_none());
+        let prompt = evaluation_prompt(&state, &question, "Intelligence", &[]).await.unwrap();
+        assert!(prompt.user.contains("Question: What is AI?\n\nLearner's answer: Intelligence"));
+    }
+
+    #[tokio::test]
+    async fn test_rubric_scores_are_weighted_per_knowledge_base() {
+        let state = create_test_app_state().await;
+        let (kb_id, _) = setup_test_data(&state).await;
+
+        let mut ai_config = state.db.get_ai_config().await.unwrap().unwrap();
+        ai_config.provider_options = Some(
+            json!({
+                "questions": ["How does AI relate to machine learning?"],
+                "evaluations": [{
+                    "score": 95, "feedback": "Accurate but thin", "suggestions": [],
+                    "criteria": {"accuracy": 100, "completeness": 60, "depth": 40, "clarity": 80},
+                    "matched_key_points": ["ML is a subset of AI"], "missed_key_points": ["ML learns from data"],
+                }],
+            })
+            .to_string(),
+        );
+        state.db.save_ai_config(&ai_config).await.unwrap();
+        let weights = RubricWeights { accuracy: 1.0, completeness: 1.0, depth: 2.0, clarity: 0.0 };
+        state.db.set_knowledge_base_rubric(&kb_id, Some(&serde_json::to_string(&weights).unwrap())).await.unwrap();
+
+        let Json(question) = generate_question(Path(kb_id.clone()), State(state.clone()), Ok(Json(GenerateQuestionRequest::default()))).await.unwrap();
+        let question_id = question["id"].as_str().unwrap().to_string();
+        let Json(answer) = submit_answer(
+            Path(question_id.clone()),
+            State(state.clone()),
+            Json(AnswerRequest { user_answer: "It is part of AI".to_string() }),
+        ).await.unwrap();
+
+        // (100 + 60 + 2 * 40) / 4, not the model's own 95
+        assert_eq!(answer["ai_score"], 60);
+        assert_eq!(answer["criteria"]["depth"], 40);
+        assert_eq!(answer["missed_key_points"], json!(["ML learns from data"]));
+
+        let stored = &state.db.get_answers_by_question(&question_id).await.unwrap()[0];
+        assert_eq!(stored.ai_score, Some(60));
+        assert_eq!(stored.criteria().map(|criteria| criteria.accuracy), Some(100));
+
+        let progress = state.db.get_learning_progress(&kb_id).await.unwrap();
+        assert_eq!(progress.criterion_averages.map(|averages| averages.completeness), Some(60.0));
+        assert_eq!(progress.weakest_criterion, Some(Criterion::Depth));
+    }
+
+    #[tokio::test]
+    async fn test_objective_questions_are_graded_locally() {
+        let state = create_test_app_state().await;
+        let (kb_id, _) = setup_test_data(&state).await;
+
+        let mut ai_config = state.db.get_ai_config().await.unwrap().unwrap();
+        ai_config.provider_options = Some(
+            json!({"questions": [r#"Sure: {"question": "What is ML part of?", "options": ["Biology", "AI"], "correct": [1]}"#]}).to_string(),
+        );
+        state.db.save_ai_config(&ai_config).await.unwrap();
+
+        let request = GenerateQuestionRequest { question_type: QuestionType::MultipleChoice, ..Default::default() };
+        let Json(question) = generate_question(Path(kb_id.clone()), State(state.clone()), Ok(Json(request))).await.unwrap();
+        assert_eq!(question["question_type"], "multiple_choice");
+        assert_eq!(question["options"], json!(["Biology", "AI"]));
+        assert!(question.get("payload").is_none());
+
+        let question_id = question["id"].as_str().unwrap().to_string();
+        let Json(answer) = submit_answer(
+            Path(question_id.clone()),
+            State(state.clone()),
+            Json(AnswerRequest { user_answer: "b".to_string() }),
+        ).await.unwrap();
+        assert_eq!(answer["ai_score"], 100);
+        assert_eq!(answer["served_by"], LOCAL_GRADER);
+
+        let stored = state.db.get_question_by_id(&question_id).await.unwrap().unwrap();
+        assert_eq!(stored.question_type, QuestionType::MultipleChoice);
+        assert_eq!(stored.answer_key(), Some(QuestionPayload::MultipleChoice {
+            options: vec!["Biology".to_string(), "AI".to_string()],
+            correct: vec![1],
+        }));
+
+        // Only the generation called the AI
+        let usage = state.db.get_ai_usage_summary(UsageGrouping::KnowledgeBase, None, None).await.unwrap();
+        assert_eq!(usage[0].calls, 1);
+    }
+
+    #[tokio::test]
+    async fn test_mock_generates_every_question_type() {
+        let state = create_test_app_state().await;
+        let (kb_id, _) = setup_test_data(&state).await;
+        let mut ai_config = state.db.get_ai_config().await.unwrap().unwrap();
+        ai_config.provider_options = None;
+        state.db.save_ai_config(&ai_config).await.unwrap();
+
+        for question_type in [QuestionType::MultipleChoice, QuestionType::TrueFalse, QuestionType::Cloze, QuestionType::ShortAnswer] {
+            let request = GenerateQuestionRequest { question_type, ..Default::default() };
+            let Json(question) = generate_question(Path(kb_id.clone()), State(state.clone()), Ok(Json(request))).await.unwrap();
+            assert_eq!(question["question_type"], question_type.to_string());
+        }
+
+        // The structured reply must follow the format instructions
+        ai_config.provider_options = Some(json!({"questions": ["What is AI?"]}).to_string());
+        state.db.save_ai_config(&ai_config).await.unwrap();
+        state.invalidate_ai_provider().await;
+        let request = GenerateQuestionRequest { question_type: QuestionType::TrueFalse, ..Default::default() };
+        let result = generate_question(Path(kb_id), State(state), Ok(Json(request))).await;
+        assert!(matches!(result, Err((StatusCode::SERVICE_UNAVAILABLE, _))));
+    }
+
+    #[tokio::test]
+    async fn test_adaptive_difficulty_follows_recent_scores() {
+        let state = create_test_app_state().await;
+        let (kb_id, _) = setup_test_data(&state).await;
+        let mut ai_config = state.db.get_ai_config().await.unwrap().unwrap();
+        ai_config.provider_options = Some(json!({"evaluations": [{"score": 95, "feedback": "Excellent", "suggestions": []}]}).to_string());
+        state.db.save_ai_config(&ai_config).await.unwrap();
+
+        let request = GenerateQuestionRequest { difficulty: Some(2), cognitive_level: Some(CognitiveLevel::Recall), ..Default::default() };
+        let Json(question) = generate_question(Path(kb_id.clone()), State(state.clone()), Ok(Json(request))).await.unwrap();
+        assert_eq!(question["difficulty"], 2);
+        assert_eq!(question["cognitive_level"], "recall");
+
+        let question_id = question["id"].as_str().unwrap().to_string();
+        let _ = submit_answer(Path(question_id), State(state.clone()), Json(AnswerRequest { user_answer: "An answer".to_string() })).await.unwrap();
+
+        // A strong recent average moves one step up from the last answered question
+        let request = GenerateQuestionRequest { adaptive: true, cognitive_level: Some(CognitiveLevel::Apply), ..Default::default() };
+        let Json(question) = generate_question(Path(kb_id.clone()), State(state.clone()), Ok(Json(request))).await.unwrap();
+        assert_eq!(question["difficulty"], 3);
+        assert_eq!(question["cognitive_level"], "apply");
+
+        let request = GenerateQuestionRequest { difficulty: Some(6), ..Default::default() };
+        let result = generate_question(Path(kb_id), State(state), Ok(Json(request))).await;
+        assert!(matches!(result, Err((StatusCode::BAD_REQUEST, _))));
+    }
+
+    #[tokio::test]
+    async fn test_exhausted_budget_blocks_calls() {
+        let state = create_test_app_state().await;
+        let (kb_id, _) = setup_test_data(&state).await;
+
+        let mut ai_config = state.db.get_ai_config().await.unwrap().unwrap();
+        ai_config.monthly_budget = Some(0.01);
+        let ai_config = state.db.save_ai_config(&ai_config).await.unwrap();
+        state.db.set_ai_model_prices(&[AIModelPrice {
+            provider: "mock".to_string(),
+            model: String::new(),
+            prompt_price: 1000.0,
+            completion_price: 1000.0,
+        }]).await.unwrap();
+
+        // The first call is within budget and spends it
+        assert!(generate_question(Path(kb_id.clone()), State(state.clone()), Ok(Json(GenerateQuestionRequest::default()))).await.is_ok());
+        assert!(state.over_budget(&ai_config).await.unwrap());
+
+        let result = generate_question(Path(kb_id), State(state), Ok(Json(GenerateQuestionRequest::default()))).await;
+        assert!(matches!(result, Err((StatusCode::TOO_MANY_REQUESTS, _))));
+    }
+
+    #[tokio::test]
+    async fn test_generate_question_reports_injected_failure() {
+        let state = create_test_app_state().await;
+        let (kb_id, _) = setup_test_data(&state).await;
+
+        let mut ai_config = state.db.get_ai_config().await.unwrap().unwrap();
+        ai_config.provider_options = Some(json!({"fail_every": 1, "fail_with": "timeout"}).to_string());
+        state.db.save_ai_config(&ai_config).await.unwrap();
+
+        let result = generate_question(Path(kb_id), State(state), Ok(Json(GenerateQuestionRequest::default()))).await;
+        assert!(matches!(result, Err((StatusCode::GATEWAY_TIMEOUT, _))));
+    }
+
+    #[tokio::test]
+    async fn test_unavailable_provider_falls_back_and_records_server() {
+        let state = create_test_app_state().await;
+        let (kb_id, _) = setup_test_data(&state).await;
+
+        let mut backup = AIConfig::new(AIProvider::Mock, None, None, Some("backup".to_string()), 1000, 0.7);
+        backup.name = "Backup".to_string();
+        backup.provider_options = Some(
+            json!({
+                "questions": ["Where does machine learning fit within AI?"],
+                "evaluations": [{"score": 64, "feedback": "Partly right", "suggestions": []}],
+            })
+            .to_string(),
+        );
+        let backup_id = state.db.create_ai_profile(&backup).await.unwrap();
+
+        let mut primary = state.db.get_ai_config().await.unwrap().unwrap();
+        primary.provider_options = Some(json!({"fail_every": 1}).to_string());
+        primary.fallback_profile_ids = vec![backup_id];
+        state.db.save_ai_config(&primary).await.unwrap();
+        state.invalidate_ai_provider().await;
+
+        let Json(question) = generate_question(Path(kb_id), State(state.clone()), Ok(Json(GenerateQuestionRequest::default()))).await.unwrap();
+        assert_eq!(question["question_text"], "Where does machine learning fit within AI?");
+        assert_eq!(question["served_by"], "mock/backup");
+
+        let question_id = question["id"].as_str().unwrap().to_string();
+        let Json(answer) = submit_answer(
+            Path(question_id.clone()),
+            State(state.clone()),
+            Json(AnswerRequest { user_answer: "It is a subset".to_string() }),
+        ).await.unwrap();
+        assert_eq!(answer["ai_score"], 64);
+        assert_eq!(answer["served_by"], "mock/backup");
+
+        let stored = state.db.get_question_by_id(&question_id).await.unwrap().unwrap();
+        assert_eq!(stored.served_by.as_deref(), Some("mock/backup"));
+        let saved = state.db.get_answers_by_question(&question_id).await.unwrap();
+        assert_eq!(saved[0].served_by.as_deref(), Some("mock/backup"));
+    }
+
     #[tokio::test]
     async fn test_generate_question_no_knowledge_base() {
         let state = create_test_app_state().await;
@@ -452,6 +1346,7 @@ mod tests {
         let result = generate_question(
             Path("non-existent-kb".to_string()),
             State(state),
+            Ok(Json(GenerateQuestionRequest::default())),
         ).await;
         
         assert!(result.is_err());
@@ -469,6 +1364,7 @@ mod tests {
         let result = generate_question(
             Path(kb.id),
             State(state),
+            Ok(Json(GenerateQuestionRequest::default())),
         ).await;
         
         assert!(result.is_err());
@@ -495,6 +1391,7 @@ mod tests {
         let result = generate_question(
             Path(kb.id),
             State(state),
+            Ok(Json(GenerateQuestionRequest::default())),
         ).await;
         
         assert!(result.is_err());
@@ -550,6 +1447,14 @@ mod tests {
             ai_feedback: Some("Good answer".to_string()),
             ai_suggestions: Some(r#"["Suggestion 1", "Suggestion 2"]"#.to_string()),
             answered_at: chrono::Utc::now(),
+            evidence: Some(r#"[{"chunk_id":"chunk-1","document_id":"doc-1","page_number":3,"content":"Passage","score":null,"is_source":true}]"#.to_string()),
+            served_by: Some("ollama/llama3.1".to_string()),
+            accuracy_score: Some(90),
+            completeness_score: Some(80),
+            depth_score: Some(85),
+            clarity_score: Some(75),
+            matched_key_points: Some(r#"["Each value has one owner"]"#.to_string()),
+            missed_key_points: None,
         };
         
         let response: AnswerResponse = answer.into();
@@ -560,6 +1465,13 @@ mod tests {
         assert_eq!(response.ai_score, Some(85));
         assert_eq!(response.ai_feedback, Some("Good answer".to_string()));
         assert_eq!(response.ai_suggestions, vec!["Suggestion 1", "Suggestion 2"]);
+        assert_eq!(response.evidence.len(), 1);
+        assert_eq!(response.evidence[0].page_number, Some(3));
+        assert_eq!(response.criteria.map(|criteria| criteria.depth), Some(85));
+        assert_eq!(response.matched_key_points, vec!["Each value has one owner"]);
+        assert!(response.missed_key_points.is_empty());
+        assert!(response.evidence[0].is_source);
+        assert_eq!(response.served_by, Some("ollama/llama3.1".to_string()));
     }
 
     #[tokio::test]
@@ -570,6 +1482,18 @@ mod tests {
             question_text: "What is AI?".to_string(),
             context_snippet: Some("AI context".to_string()),
             generated_at: chrono::Utc::now(),
+            document_id: Some("doc-id".to_string()),
+            chunk_id: Some("chunk-id".to_string()),
+            page_number: Some(3),
+            served_by: None,
+            difficulty: Some(2),
+            cognitive_level: Some(CognitiveLevel::Recall),
+            status: QuestionStatus::Approved,
+            batch_id: None,
+            reference_answer: Some("A. Yes".to_string()),
+            key_points: None,
+            question_type: QuestionType::MultipleChoice,
+            payload: Some(r#"{"type":"multiple_choice","options":["Yes","No"],"correct":[0]}"#.to_string()),
         };
         
         let response: QuestionResponse = question.into();
@@ -577,5 +1501,145 @@ mod tests {
         assert_eq!(response.id, "test-id");
         assert_eq!(response.question_text, "What is AI?");
         assert_eq!(response.context_snippet, Some("AI context".to_string()));
+        assert_eq!(response.chunk_id, Some("chunk-id".to_string()));
+        assert_eq!(response.page_number, Some(3));
+        assert_eq!(response.options, Some(vec!["Yes".to_string(), "No".to_string()]));
+        assert_eq!(response.correct_count, Some(1));
+        // The answer key itself is never sent
+        let json = serde_json::to_value(&response).unwrap();
+        assert!(json.get("payload").is_none() && json.get("correct").is_none());
+    }
+
+    #[test]
+    fn test_ai_error_status() {
+        use std::time::Duration;
+
+        assert_eq!(ai_error_status(&AIError::Timeout("slow".to_string())), StatusCode::GATEWAY_TIMEOUT);
+        assert_eq!(ai_error_status(&AIError::RateLimited { retry_after: None }), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(
+            ai_error_status(&AIError::RetriesExhausted {
+                attempts: 3,
+                last_error: Box::new(AIError::RateLimited { retry_after: Some(Duration::from_secs(1)) }),
+            }),
+            StatusCode::TOO_MANY_REQUESTS
+        );
+        assert_eq!(
+            ai_error_status(&AIError::CircuitOpen { retry_in: Duration::from_secs(5) }),
+            StatusCode::SERVICE_UNAVAILABLE
+        );
+    }
+
+    // Serve an OpenAI-style streamed evaluation split into several deltas
+    async fn spawn_streaming_evaluator() -> String {
+        use axum::{http::header, routing::post, Router};
+
+        let deltas = [r#"{"score": 82, "#, r#""feedback": "Clear", "#, r#""suggestions": ["Cite the text"]}"#];
+        let mut events: String = deltas
+            .iter()
+            .map(|delta| format!("data: {}\n\n", json!({"choices": [{"index": 0, "delta": {"content": delta}}]})))
+            .collect();
+        events.push_str("data: [DONE]\n\n");
+
+        let app = Router::new().route(
+            "/v1/chat/completions",
+            post(move || async move { ([(header::CONTENT_TYPE, "text/event-stream")], events) }),
+        );
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            axum::serve(listener, app).await.unwrap();
+        });
+
+        format!("http://{}/v1", addr)
+    }
+
+    #[tokio::test]
+    async fn test_submit_answer_stream_persists_result() {
+        use axum::response::IntoResponse;
+
+        let state = create_test_app_state().await;
+        let server = spawn_streaming_evaluator().await;
+        let ai_config = AIConfig::new(AIProvider::OpenAI, Some("sk-test".to_string()), Some(server), None, 1000, 0.7);
+        state.db.save_ai_config(&ai_config).await.unwrap();
+
+        let kb = state.db.create_knowledge_base("Stream KB", None).await.unwrap();
+        let question = Question::new(kb.id, "What is ownership?".to_string(), Some("Ownership rules".to_string()));
+        state.db.save_question(&question).await.unwrap();
+
+        let response = submit_answer_stream(
+            Path(question.id.clone()),
+            State(state.clone()),
+            Json(AnswerRequest { user_answer: "Each value has one owner".to_string() }),
+        ).await.unwrap().into_response();
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let body = String::from_utf8(body.to_vec()).unwrap();
+
+        assert_eq!(body.matches("event: token").count(), 3);
+        let done = body
+            .split("\n\n")
+            .find(|event| event.starts_with("event: done"))
+            .and_then(|event| event.lines().find_map(|line| line.strip_prefix("data: ")))
+            .expect("done event");
+        let done: Value = serde_json::from_str(done).unwrap();
+        assert_eq!(done["ai_score"], 82);
+        assert_eq!(done["ai_suggestions"], json!(["Cite the text"]));
+
+        let saved = state.db.get_answers_by_question(&question.id).await.unwrap();
+        assert_eq!(saved.len(), 1);
+        assert_eq!(saved[0].ai_feedback.as_deref(), Some("Clear"));
+    }
+
+    #[tokio::test]
+    async fn test_submit_answer_stream_question_not_found() {
+        let state = create_test_app_state().await;
+
+        let result = submit_answer_stream(
+            Path("non-existent-question".to_string()),
+            State(state),
+            Json(AnswerRequest { user_answer: "Test answer".to_string() }),
+        ).await;
+
+        assert!(matches!(result, Err((StatusCode::NOT_FOUND, _))));
+    }
+
+    #[tokio::test]
+    async fn test_prompts_follow_knowledge_base_language() {
+        let state = create_test_app_state().await;
+        let kb = state.db.create_knowledge_base("Languages", None).await.unwrap();
+        let chunk = DocumentChunk::new(
+            "doc".to_string(),
+            kb.id.clone(),
+            0,
+            "所有权是Rust最独特的功能，它让Rust无需垃圾回收即可保障内存安全。".to_string(),
+            0,
+            40,
+        );
+
+        // Detected from the passage by default
+        let prompt = question_prompt(&state, &chunk, QuestionType::Open, &QuestionTarget::default()).await.unwrap();
+        assert!(prompt.system.unwrap().contains("Write the question in Chinese."));
+
+        // An explicit setting wins over detection, for both questions and feedback
+        state.db.set_knowledge_base_language(&kb.id, Some("fr")).await.unwrap();
+        let prompt = question_prompt(&state, &chunk, QuestionType::Open, &QuestionTarget::default()).await.unwrap();
+        assert!(prompt.system.unwrap().contains("Write the question in French."));
+
+        // The built-in templates ask for the target difficulty, or the old fixed one
+        let prompt = question_prompt(&state, &chunk, QuestionType::Open, &QuestionTarget::default()).await.unwrap();
+        assert!(prompt.system.unwrap().contains("Require comprehensive thinking"));
+        let target = QuestionTarget { difficulty: Some(4), cognitive_level: Some(CognitiveLevel::Analyze) };
+        let prompt = question_prompt(&state, &chunk, QuestionType::Open, &target).await.unwrap().system.unwrap();
+        assert!(prompt.contains("Target difficulty 4 of 5"));
+        assert!(!prompt.contains("comprehensive thinking"));
+
+        // Structured types add their output format after the template's instructions
+        let prompt = question_prompt(&state, &chunk, QuestionType::Cloze, &QuestionTarget::default()).await.unwrap().system.unwrap();
+        assert!(prompt.contains("Write the question in French."));
+        assert!(prompt.ends_with(question_types::format_instructions(QuestionType::Cloze).unwrap()));
+
+        let question = Question::new(kb.id, "什么是所有权？".to_string(), Some(chunk.content.clone()));
+        let prompt = evaluation_prompt(&state, &question, "Une réponse", &[]).await.unwrap();
+        assert!(prompt.system.unwrap().contains("feedback and suggestions in French"));
     }
-}
\ No newline at end of file
+}
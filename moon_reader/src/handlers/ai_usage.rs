@@ -0,0 +1,197 @@
+use axum::{
+    extract::{Query, State},
+    response::Json,
+};
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use validator::Validate;
+
+use crate::error::{AppError, AppResult, validation_error_to_app_error};
+use crate::models::{AIModelPrice, AIUsageSummary, UsageGrouping};
+use crate::services::{month_start, AppState};
+
+// Request DTOs
+#[derive(Debug, Default, Deserialize)]
+pub struct AIUsageQuery {
+    #[serde(default)]
+    pub group_by: UsageGrouping,
+    pub start_date: Option<chrono::DateTime<Utc>>,
+    /// Exclusive
+    pub end_date: Option<chrono::DateTime<Utc>>,
+}
+
+// Response DTOs
+#[derive(Debug, Serialize)]
+pub struct AIUsageResponse {
+    pub group_by: UsageGrouping,
+    pub entries: Vec<AIUsageSummary>,
+    pub total_prompt_tokens: i64,
+    pub total_completion_tokens: i64,
+    pub total_cost: f64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct AIBudgetStatus {
+    pub profile_id: i32,
+    pub name: String,
+    pub monthly_budget: Option<f64>,
+    /// Cost of this calendar month's calls at the current prices
+    pub spent_this_month: f64,
+    pub exceeded: bool,
+}
+
+// Handler functions
+
+/// Token usage and cost, grouped by day, knowledge base or provider
+pub async fn get_ai_usage(
+    Query(query): Query<AIUsageQuery>,
+    State(state): State<AppState>,
+) -> AppResult<Json<AIUsageResponse>> {
+    if let (Some(start), Some(end)) = (query.start_date, query.end_date)
+        && start >= end
+    {
+        return Err(AppError::BadRequest("start_date must be before end_date".to_string()));
+    }
+
+    let entries = state
+        .db
+        .get_ai_usage_summary(query.group_by, query.start_date, query.end_date)
+        .await?;
+
+    Ok(Json(AIUsageResponse {
+        group_by: query.group_by,
+        total_prompt_tokens: entries.iter().map(|entry| entry.prompt_tokens).sum(),
+        total_completion_tokens: entries.iter().map(|entry| entry.completion_tokens).sum(),
+        total_cost: entries.iter().map(|entry| entry.cost).sum(),
+        entries,
+    }))
+}
+
+/// This month's spending of every AI profile against its budget
+pub async fn get_ai_budgets(State(state): State<AppState>) -> AppResult<Json<Vec<AIBudgetStatus>>> {
+    let since = month_start(Utc::now());
+    let mut budgets = Vec::new();
+
+    for profile in state.db.get_ai_profiles().await? {
+        let Some(profile_id) = profile.id else {
+            continue;
+        };
+        let spent_this_month = state.db.get_ai_profile_cost(profile_id, since).await?;
+        budgets.push(AIBudgetStatus {
+            profile_id,
+            exceeded: profile.monthly_budget.is_some_and(|budget| spent_this_month >= budget),
+            name: profile.name,
+            monthly_budget: profile.monthly_budget,
+            spent_this_month,
+        });
+    }
+
+    Ok(Json(budgets))
+}
+
+pub async fn list_ai_model_prices(State(state): State<AppState>) -> AppResult<Json<Vec<AIModelPrice>>> {
+    Ok(Json(state.db.get_ai_model_prices().await?))
+}
+
+/// Replace the price list; costs are recomputed from it, past usage included
+pub async fn update_ai_model_prices(
+    State(state): State<AppState>,
+    Json(prices): Json<Vec<AIModelPrice>>,
+) -> AppResult<Json<Vec<AIModelPrice>>> {
+    for price in &prices {
+        if let Err(validation_errors) = price.validate() {
+            return Err(validation_error_to_app_error(validation_errors));
+        }
+    }
+
+    let mut seen = std::collections::HashSet::new();
+    if let Some(duplicate) = prices.iter().find(|price| !seen.insert((&price.provider, &price.model))) {
+        return Err(AppError::BadRequest(format!(
+            "Duplicate price for provider '{}' and model '{}'",
+            duplicate.provider, duplicate.model
+        )));
+    }
+
+    state.db.set_ai_model_prices(&prices).await?;
+
+    tracing::info!("Updated {} AI model prices", prices.len());
+    Ok(Json(state.db.get_ai_model_prices().await?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::create_connection_pool;
+    use crate::models::{AIConfig, AIProvider, AIUsage, PromptKind};
+
+    async fn create_test_app_state() -> AppState {
+        let pool = create_connection_pool("sqlite::memory:").await.unwrap();
+        AppState::new(pool)
+    }
+
+    fn price(provider: &str, model: &str, prompt_price: f64, completion_price: f64) -> AIModelPrice {
+        AIModelPrice {
+            provider: provider.to_string(),
+            model: model.to_string(),
+            prompt_price,
+            completion_price,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_usage_costs_and_budgets() {
+        let state = create_test_app_state().await;
+
+        let mut profile = AIConfig::new(AIProvider::OpenAI, Some("sk-test".to_string()), None, Some("gpt-4o".to_string()), 1000, 0.7);
+        profile.monthly_budget = Some(1.0);
+        let profile = state.db.save_ai_config(&profile).await.unwrap();
+        let kb = state.db.create_knowledge_base("Work", None).await.unwrap();
+
+        let mut usage = AIUsage::new(&profile, PromptKind::Question, 100_000, 50_000);
+        usage.knowledge_base_id = Some(kb.id.clone());
+        state.db.record_ai_usage(&usage).await.unwrap();
+
+        // Unpriced usage is free
+        let Json(report) = get_ai_usage(Query(AIUsageQuery::default()), State(state.clone())).await.unwrap();
+        assert_eq!(report.entries.len(), 1);
+        assert_eq!(report.total_prompt_tokens, 100_000);
+        assert_eq!(report.total_cost, 0.0);
+
+        // The model's own price wins over the provider-wide one
+        let Json(prices) = update_ai_model_prices(
+            State(state.clone()),
+            Json(vec![price("openai", "gpt-4o", 2.5, 10.0), price("openai", "", 100.0, 100.0)]),
+        )
+        .await
+        .unwrap();
+        assert_eq!(prices.len(), 2);
+
+        let query = AIUsageQuery { group_by: UsageGrouping::KnowledgeBase, ..AIUsageQuery::default() };
+        let Json(report) = get_ai_usage(Query(query), State(state.clone())).await.unwrap();
+        assert_eq!(report.entries[0].key, kb.id);
+        assert!((report.total_cost - 0.75).abs() < 1e-9);
+
+        let Json(budgets) = get_ai_budgets(State(state.clone())).await.unwrap();
+        assert!((budgets[0].spent_this_month - 0.75).abs() < 1e-9);
+        assert!(!budgets[0].exceeded);
+
+        state.db.record_ai_usage(&AIUsage::new(&profile, PromptKind::Evaluation, 200_000, 0)).await.unwrap();
+        let Json(budgets) = get_ai_budgets(State(state)).await.unwrap();
+        assert!(budgets[0].exceeded);
+    }
+
+    #[tokio::test]
+    async fn test_update_prices_rejects_duplicates() {
+        let state = create_test_app_state().await;
+
+        let result = update_ai_model_prices(
+            State(state.clone()),
+            Json(vec![price("openai", "gpt-4o", 1.0, 1.0), price("openai", "gpt-4o", 2.0, 2.0)]),
+        )
+        .await;
+        assert!(matches!(result, Err(AppError::BadRequest(_))));
+
+        let result = update_ai_model_prices(State(state), Json(vec![price("openai", "gpt-4o", -1.0, 1.0)])).await;
+        assert!(matches!(result, Err(AppError::Validation(_))));
+    }
+}
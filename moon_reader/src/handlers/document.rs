@@ -8,6 +8,7 @@ use tokio::fs;
 use tokio::io::AsyncWriteExt;
 
 use crate::services::AppState;
+use crate::services::retrieval::Retriever;
 use crate::models::{Document, DocumentType};
 use crate::parsers::{DocumentParserFactory, ParseError};
 use crate::error::AppError;
@@ -73,7 +74,7 @@ pub async fn upload_document(
             .to_string();
 
         // Validate file extension
-        let extension = filename.split('.').last()
+        let extension = filename.split('.').next_back()
             .ok_or_else(|| AppError::FileUpload("No file extension found".to_string()))?
             .to_lowercase();
 
@@ -147,6 +148,11 @@ pub async fn upload_document(
         state.db.save_document(&document).await
             .map_err(AppError::Database)?;
 
+        // Build the retrieval index; a failure here only delays indexing until the next search
+        if let Err(e) = Retriever::index_document(&state.db, &document).await {
+            tracing::warn!("Failed to index document {}: {}", document.id, e);
+        }
+
         return Ok(Json(json!({
             "message": "Document uploaded successfully",
             "document": {
@@ -203,4 +209,40 @@ pub async fn get_document_content(
         "filename": document.filename,
         "content": document.content_text
     })))
-}
\ No newline at end of file
+}
+
+// Per-chunk quiz coverage for a document: how often each section was quizzed and how well it went
+pub async fn get_document_coverage(
+    Path(id): Path<String>,
+    State(state): State<AppState>,
+) -> Result<Json<Value>, AppError> {
+    let document = state.db.get_document_by_id(&id).await
+        .map_err(AppError::Database)?
+        .ok_or_else(|| AppError::NotFound("Document not found".to_string()))?;
+
+    Retriever::ensure_indexed(&state.db, &document.knowledge_base_id).await
+        .map_err(AppError::Database)?;
+    let chunks = state.db.get_chunk_coverage(&document.knowledge_base_id, Some(&document.id)).await
+        .map_err(AppError::Database)?;
+
+    let covered = chunks.iter().filter(|c| c.question_count > 0).count();
+    let answered = chunks.iter().filter(|c| c.answer_count > 0).count();
+    let coverage_ratio = if chunks.is_empty() { 0.0 } else { covered as f64 / chunks.len() as f64 };
+
+    // Weight each chunk's average by its number of scored answers
+    let (score_sum, score_count) = chunks.iter()
+        .filter_map(|c| c.average_score.map(|avg| (avg * c.answer_count as f64, c.answer_count)))
+        .fold((0.0, 0), |(sum, count), (s, n)| (sum + s, count + n));
+    let average_score = (score_count > 0).then(|| score_sum / score_count as f64);
+
+    Ok(Json(json!({
+        "document_id": document.id,
+        "filename": document.filename,
+        "total_chunks": chunks.len(),
+        "covered_chunks": covered,
+        "answered_chunks": answered,
+        "coverage_ratio": coverage_ratio,
+        "average_score": average_score,
+        "chunks": chunks
+    })))
+}
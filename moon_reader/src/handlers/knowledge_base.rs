@@ -4,12 +4,25 @@ use axum::{
 };
 use serde::{Deserialize, Serialize};
 use serde_json::{json, Value};
-use validator::Validate;
+use validator::{Validate, ValidationError};
 
-use crate::models::KnowledgeBase;
-use crate::services::AppState;
+use crate::models::{KnowledgeBase, RubricWeights};
+use crate::services::{AppState, language};
 use crate::error::{AppError, AppResult, validation_error_to_app_error};
 
+// Accept "auto" or a supported language code
+fn validate_language(language: &str) -> Result<(), ValidationError> {
+    if language == AUTO_LANGUAGE || language::language_name(language).is_some() {
+        Ok(())
+    } else {
+        let mut error = ValidationError::new("language");
+        error.message = Some("Unsupported language".into());
+        Err(error)
+    }
+}
+
+const AUTO_LANGUAGE: &str = "auto";
+
 // Request DTOs
 #[derive(Debug, Deserialize, Validate)]
 pub struct CreateKnowledgeBaseRequest {
@@ -17,6 +30,14 @@ pub struct CreateKnowledgeBaseRequest {
     pub name: String,
     #[validate(length(max = 1000, message = "Description must be less than 1000 characters"))]
     pub description: Option<String>,
+    /// Language code, or "auto" (the default) to detect it from the documents
+    #[validate(custom = "validate_language")]
+    pub language: Option<String>,
+    /// Criterion weights answers are scored with; the default rubric when unset
+    #[validate]
+    pub rubric_weights: Option<RubricWeights>,
+    /// Write a reference answer for each generated open question; on when unset
+    pub reference_answers: Option<bool>,
 }
 
 #[derive(Debug, Deserialize, Validate)]
@@ -25,6 +46,14 @@ pub struct UpdateKnowledgeBaseRequest {
     pub name: String,
     #[validate(length(max = 1000, message = "Description must be less than 1000 characters"))]
     pub description: Option<String>,
+    /// Language code or "auto"; leave unset to keep the current setting
+    #[validate(custom = "validate_language")]
+    pub language: Option<String>,
+    /// Criterion weights; leave unset to keep the current ones
+    #[validate]
+    pub rubric_weights: Option<RubricWeights>,
+    /// Leave unset to keep the current setting
+    pub reference_answers: Option<bool>,
 }
 
 // Response DTOs
@@ -33,6 +62,12 @@ pub struct KnowledgeBaseResponse {
     pub id: String,
     pub name: String,
     pub description: Option<String>,
+    /// Language code, or `None` when detected automatically
+    pub language: Option<String>,
+    /// Criterion weights answers are scored with
+    pub rubric_weights: RubricWeights,
+    /// Whether generated open questions get a reference answer, one more provider call each
+    pub reference_answers: bool,
     pub created_at: String,
     pub updated_at: String,
     pub document_count: i64,
@@ -40,10 +75,14 @@ pub struct KnowledgeBaseResponse {
 
 impl From<KnowledgeBase> for KnowledgeBaseResponse {
     fn from(kb: KnowledgeBase) -> Self {
+        let rubric_weights = kb.rubric();
         Self {
             id: kb.id,
             name: kb.name,
             description: kb.description,
+            language: kb.language,
+            rubric_weights,
+            reference_answers: kb.reference_answers,
             created_at: kb.created_at.to_rfc3339(),
             updated_at: kb.updated_at.to_rfc3339(),
             document_count: 0, // Will be populated separately if needed
@@ -90,7 +129,23 @@ pub async fn create_knowledge_base(
         return Err(validation_error_to_app_error(validation_errors));
     }
     
-    let knowledge_base = state.db.create_knowledge_base(&payload.name, payload.description.as_deref()).await?;
+    let mut knowledge_base = state.db.create_knowledge_base(&payload.name, payload.description.as_deref()).await?;
+    
+    if let Some(language) = payload.language.as_deref().filter(|l| *l != AUTO_LANGUAGE) {
+        state.db.set_knowledge_base_language(&knowledge_base.id, Some(language)).await?;
+        knowledge_base.language = Some(language.to_string());
+    }
+    
+    if let Some(weights) = &payload.rubric_weights {
+        let weights = serde_json::to_string(weights).map_err(|e| AppError::Internal(e.to_string()))?;
+        state.db.set_knowledge_base_rubric(&knowledge_base.id, Some(&weights)).await?;
+        knowledge_base.rubric_weights = Some(weights);
+    }
+    
+    if let Some(enabled) = payload.reference_answers {
+        state.db.set_knowledge_base_reference_answers(&knowledge_base.id, enabled).await?;
+        knowledge_base.reference_answers = enabled;
+    }
     
     tracing::info!("Created knowledge base: {}", knowledge_base.id);
     Ok(Json(KnowledgeBaseResponse::from(knowledge_base)))
@@ -118,6 +173,20 @@ pub async fn update_knowledge_base(
         return Err(AppError::NotFound("Knowledge base not found".to_string()));
     }
     
+    if let Some(language) = payload.language.as_deref() {
+        let language = (language != AUTO_LANGUAGE).then_some(language);
+        state.db.set_knowledge_base_language(&id, language).await?;
+    }
+    
+    if let Some(weights) = &payload.rubric_weights {
+        let weights = serde_json::to_string(weights).map_err(|e| AppError::Internal(e.to_string()))?;
+        state.db.set_knowledge_base_rubric(&id, Some(&weights)).await?;
+    }
+    
+    if let Some(enabled) = payload.reference_answers {
+        state.db.set_knowledge_base_reference_answers(&id, enabled).await?;
+    }
+    
     // Fetch the updated knowledge base
     let updated_kb = state.db.get_knowledge_base_by_id(&id).await?
         .ok_or_else(|| AppError::Internal("Failed to retrieve updated knowledge base".to_string()))?;
@@ -2,12 +2,24 @@
 pub mod knowledge_base;
 pub mod document;
 pub mod ai_quiz;
+pub mod question_bank;
+pub mod tutoring;
 pub mod review;
 pub mod ai_config;
+pub mod ai_profile;
+pub mod ai_usage;
+pub mod search;
+pub mod prompt_template;
 
 // Re-export handler functions for easy access
 pub use knowledge_base::*;
 pub use document::*;
 pub use ai_quiz::*;
+pub use question_bank::*;
+pub use tutoring::*;
 pub use review::*;
-pub use ai_config::*;
\ No newline at end of file
+pub use ai_config::*;
+pub use ai_profile::*;
+pub use ai_usage::*;
+pub use search::*;
+pub use prompt_template::*;
\ No newline at end of file
@@ -0,0 +1,296 @@
+use axum::{
+    extract::{Path, Query, State},
+    response::Json,
+};
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use validator::Validate;
+
+use crate::error::{AppError, AppResult, validation_error_to_app_error};
+use crate::models::{PromptKind, PromptTemplate};
+use crate::services::AppState;
+use crate::services::prompts::{self, Prompt, PromptVariables};
+
+// Request DTOs
+#[derive(Debug, Deserialize, Validate)]
+pub struct CreatePromptTemplateRequest {
+    #[validate(length(min = 1, max = 100, message = "Name must be between 1 and 100 characters"))]
+    pub name: String,
+    pub kind: PromptKind,
+    #[validate(length(max = 10000, message = "System prompt must be less than 10000 characters"))]
+    pub system_prompt: Option<String>,
+    #[validate(length(min = 1, max = 20000, message = "User prompt must be between 1 and 20000 characters"))]
+    pub user_prompt: String,
+    #[serde(default)]
+    pub is_default: bool,
+}
+
+#[derive(Debug, Deserialize, Validate)]
+pub struct UpdatePromptTemplateRequest {
+    #[validate(length(min = 1, max = 100, message = "Name must be between 1 and 100 characters"))]
+    pub name: String,
+    #[validate(length(max = 10000, message = "System prompt must be less than 10000 characters"))]
+    pub system_prompt: Option<String>,
+    #[validate(length(min = 1, max = 20000, message = "User prompt must be between 1 and 20000 characters"))]
+    pub user_prompt: String,
+    /// Leave unset to keep the current default flag
+    pub is_default: Option<bool>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ListPromptTemplatesQuery {
+    pub kind: Option<PromptKind>,
+}
+
+// Response DTOs
+#[derive(Debug, Serialize)]
+pub struct ListPromptTemplatesResponse {
+    pub templates: Vec<PromptTemplate>,
+    /// Placeholders templates may use
+    pub variables: Vec<&'static str>,
+}
+
+// Handler functions
+pub async fn list_prompt_templates(
+    Query(query): Query<ListPromptTemplatesQuery>,
+    State(state): State<AppState>,
+) -> AppResult<Json<ListPromptTemplatesResponse>> {
+    let templates = state.db.get_prompt_templates(query.kind).await?;
+
+    Ok(Json(ListPromptTemplatesResponse {
+        templates,
+        variables: prompts::PROMPT_VARIABLES.to_vec(),
+    }))
+}
+
+pub async fn get_prompt_template(
+    Path(id): Path<String>,
+    State(state): State<AppState>,
+) -> AppResult<Json<PromptTemplate>> {
+    let template = state.db.get_prompt_template_by_id(&id).await?
+        .ok_or_else(|| AppError::NotFound("Prompt template not found".to_string()))?;
+
+    Ok(Json(template))
+}
+
+pub async fn create_prompt_template(
+    State(state): State<AppState>,
+    Json(payload): Json<CreatePromptTemplateRequest>,
+) -> AppResult<Json<PromptTemplate>> {
+    // Validate input
+    if let Err(validation_errors) = payload.validate() {
+        return Err(validation_error_to_app_error(validation_errors));
+    }
+    prompts::validate_template(payload.kind, payload.system_prompt.as_deref(), &payload.user_prompt)
+        .map_err(AppError::Validation)?;
+
+    let mut template = PromptTemplate::new(payload.name, payload.kind, payload.system_prompt, payload.user_prompt);
+    template.is_default = payload.is_default;
+
+    state.db.save_prompt_template(&template).await.map_err(|e| duplicate_name_error(e, &template.name))?;
+
+    tracing::info!("Created prompt template: {}", template.id);
+    Ok(Json(template))
+}
+
+pub async fn update_prompt_template(
+    Path(id): Path<String>,
+    State(state): State<AppState>,
+    Json(payload): Json<UpdatePromptTemplateRequest>,
+) -> AppResult<Json<PromptTemplate>> {
+    // Validate input
+    if let Err(validation_errors) = payload.validate() {
+        return Err(validation_error_to_app_error(validation_errors));
+    }
+
+    let mut template = state.db.get_prompt_template_by_id(&id).await?
+        .ok_or_else(|| AppError::NotFound("Prompt template not found".to_string()))?;
+
+    prompts::validate_template(template.kind, payload.system_prompt.as_deref(), &payload.user_prompt)
+        .map_err(AppError::Validation)?;
+
+    // Every kind keeps a default; it changes by promoting another template
+    if template.is_default && payload.is_default == Some(false) {
+        return Err(AppError::BadRequest(
+            "Make another template the default instead of unsetting this one".to_string(),
+        ));
+    }
+
+    template.name = payload.name;
+    template.system_prompt = payload.system_prompt;
+    template.user_prompt = payload.user_prompt;
+    template.is_default = payload.is_default.unwrap_or(template.is_default);
+    template.updated_at = Utc::now();
+
+    state.db.save_prompt_template(&template).await.map_err(|e| duplicate_name_error(e, &template.name))?;
+
+    tracing::info!("Updated prompt template: {}", id);
+    Ok(Json(template))
+}
+
+pub async fn delete_prompt_template(
+    Path(id): Path<String>,
+    State(state): State<AppState>,
+) -> AppResult<Json<Value>> {
+    let template = state.db.get_prompt_template_by_id(&id).await?
+        .ok_or_else(|| AppError::NotFound("Prompt template not found".to_string()))?;
+
+    if template.is_default {
+        return Err(AppError::BadRequest(
+            "The default template cannot be deleted; make another template the default first".to_string(),
+        ));
+    }
+
+    state.db.delete_prompt_template(&id).await?;
+
+    tracing::info!("Deleted prompt template: {}", id);
+    Ok(Json(json!({"message": "Prompt template deleted successfully"})))
+}
+
+/// Preview a template filled in with sample variables
+pub async fn render_prompt_template(
+    Path(id): Path<String>,
+    State(state): State<AppState>,
+    payload: Option<Json<PromptVariables>>,
+) -> AppResult<Json<Prompt>> {
+    let template = state.db.get_prompt_template_by_id(&id).await?
+        .ok_or_else(|| AppError::NotFound("Prompt template not found".to_string()))?;
+
+    let variables = payload.map(|Json(variables)| variables).unwrap_or_default();
+    Ok(Json(prompts::render(&template, &variables)))
+}
+
+// Report a clash with an existing template name as a client error
+fn duplicate_name_error(error: sqlx::Error, name: &str) -> AppError {
+    match error {
+        sqlx::Error::Database(ref db_error) if db_error.is_unique_violation() => {
+            AppError::BadRequest(format!("A prompt template named '{}' already exists", name))
+        }
+        other => AppError::Database(other),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::create_connection_pool;
+
+    async fn create_test_app_state() -> AppState {
+        let pool = create_connection_pool("sqlite::memory:").await.unwrap();
+        AppState::new(pool)
+    }
+
+    fn create_request(name: &str, is_default: bool) -> CreatePromptTemplateRequest {
+        CreatePromptTemplateRequest {
+            name: name.to_string(),
+            kind: PromptKind::Question,
+            system_prompt: Some("Ask in {{language}}.".to_string()),
+            user_prompt: "Material:\n{{context}}".to_string(),
+            is_default,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_seeded_defaults() {
+        let state = create_test_app_state().await;
+
+        let Json(list) = list_prompt_templates(Query(ListPromptTemplatesQuery { kind: None }), State(state.clone()))
+            .await
+            .unwrap();
+        assert_eq!(list.templates.len(), 4);
+        assert!(list.variables.contains(&"difficulty"));
+
+        let question = state.db.get_default_prompt_template(PromptKind::Question).await.unwrap().unwrap();
+        assert_eq!(question.id, "builtin-question-en");
+        let evaluation = state.db.get_default_prompt_template(PromptKind::Evaluation).await.unwrap().unwrap();
+        assert!(evaluation.system_prompt.unwrap().contains("JSON"));
+    }
+
+    #[tokio::test]
+    async fn test_create_default_replaces_previous() {
+        let state = create_test_app_state().await;
+
+        let Json(created) = create_prompt_template(State(state.clone()), Json(create_request("Custom", true)))
+            .await
+            .unwrap();
+
+        let default = state.db.get_default_prompt_template(PromptKind::Question).await.unwrap().unwrap();
+        assert_eq!(default.id, created.id);
+        let previous = state.db.get_prompt_template_by_id("builtin-question-en").await.unwrap().unwrap();
+        assert!(!previous.is_default);
+
+        // Names are unique
+        let duplicate = create_prompt_template(State(state.clone()), Json(create_request("Custom", false))).await;
+        assert!(matches!(duplicate, Err(AppError::BadRequest(_))));
+    }
+
+    #[tokio::test]
+    async fn test_create_rejects_invalid_variables() {
+        let state = create_test_app_state().await;
+
+        let mut request = create_request("Broken", false);
+        request.user_prompt = "Ask about {{topic}}".to_string();
+
+        let result = create_prompt_template(State(state), Json(request)).await;
+        assert!(matches!(result, Err(AppError::Validation(_))));
+    }
+
+    #[tokio::test]
+    async fn test_update_and_delete_rules() {
+        let state = create_test_app_state().await;
+
+        let unset_default = update_prompt_template(
+            Path("builtin-question-en".to_string()),
+            State(state.clone()),
+            Json(UpdatePromptTemplateRequest {
+                name: "English question".to_string(),
+                system_prompt: None,
+                user_prompt: "{{context}}".to_string(),
+                is_default: Some(false),
+            }),
+        ).await;
+        assert!(matches!(unset_default, Err(AppError::BadRequest(_))));
+
+        let delete_default = delete_prompt_template(Path("builtin-question-en".to_string()), State(state.clone())).await;
+        assert!(matches!(delete_default, Err(AppError::BadRequest(_))));
+
+        let Json(updated) = update_prompt_template(
+            Path("builtin-question-zh".to_string()),
+            State(state.clone()),
+            Json(UpdatePromptTemplateRequest {
+                name: "Chinese question".to_string(),
+                system_prompt: None,
+                user_prompt: "材料：{{context}}".to_string(),
+                is_default: None,
+            }),
+        ).await.unwrap();
+        assert_eq!(updated.system_prompt, None);
+        assert!(!updated.is_default);
+
+        let _ = delete_prompt_template(Path("builtin-question-zh".to_string()), State(state.clone())).await.unwrap();
+        assert!(state.db.get_prompt_template_by_id("builtin-question-zh").await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_render_prompt_template() {
+        let state = create_test_app_state().await;
+
+        let Json(prompt) = render_prompt_template(
+            Path("builtin-evaluation-en".to_string()),
+            State(state),
+            Some(Json(PromptVariables {
+                context: Some("Borrowing rules".to_string()),
+                question: Some("What is a borrow?".to_string()),
+                answer: Some("A reference".to_string()),
+                ..Default::default()
+            })),
+        ).await.unwrap();
+
+        assert!(prompt.system.is_some());
+        assert_eq!(
+            prompt.user,
+            "Reference material:\nBorrowing rules\n\nQuestion: What is a borrow?\n\nLearner's answer: A reference\n\nPlease evaluate this answer and return a JSON-formatted evaluation result."
+        );
+    }
+}
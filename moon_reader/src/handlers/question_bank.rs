@@ -0,0 +1,599 @@
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    response::Json,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use validator::Validate;
+
+use crate::error::{AppError, AppResult, validation_error_to_app_error};
+use crate::handlers::ai_quiz::{self, GenerateQuestionRequest, QuestionResponse};
+use crate::models::{CognitiveLevel, PromptKind, Question, QuestionBatch, QuestionPayload, QuestionStatus, QuestionType, ReferenceAnswer};
+use crate::services::{AppState, difficulty::QuestionTarget, question_types, retrieval::{HashingEmbedder, SourceStrategy}};
+
+/// Questions at least this similar to one already in the knowledge base are dropped as duplicates
+const DUPLICATE_SIMILARITY: f32 = 0.9;
+// Generation attempts allowed per requested question, duplicates and failures included
+const ATTEMPTS_PER_QUESTION: i32 = 2;
+// A batch gives up once this many generations in a row have failed
+const MAX_CONSECUTIVE_FAILURES: u32 = 3;
+
+// Request DTOs
+#[derive(Debug, Default, Deserialize, Validate)]
+pub struct StartQuestionBatchRequest {
+    #[validate(range(min = 1, max = 50, message = "Count must be between 1 and 50"))]
+    pub count: u32,
+    #[serde(default)]
+    pub question_type: QuestionType,
+    #[validate(range(min = 1, max = 5, message = "Difficulty must be between 1 and 5"))]
+    pub difficulty: Option<u8>,
+    pub cognitive_level: Option<CognitiveLevel>,
+    /// How to pick the passage of each question; the default spreads them over the least covered chunks
+    pub strategy: Option<SourceStrategy>,
+    /// Restrict generation to a single document
+    pub document_id: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct QuestionBankQuery {
+    pub status: Option<QuestionStatus>,
+    pub batch_id: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Validate)]
+pub struct UpdateBankQuestionRequest {
+    #[validate(length(min = 1, max = 2000, message = "Question text must be between 1 and 2000 characters"))]
+    pub question_text: String,
+    /// New answer key of a structured question; leave unset to keep the current one
+    pub answer_key: Option<QuestionPayload>,
+    /// New reference answer and key points; leave unset to keep the current ones
+    pub reference: Option<ReferenceAnswer>,
+    /// Leave unset to keep the current difficulty
+    #[validate(range(min = 1, max = 5, message = "Difficulty must be between 1 and 5"))]
+    pub difficulty: Option<u8>,
+    /// Leave unset to keep the current cognitive level
+    pub cognitive_level: Option<CognitiveLevel>,
+    /// Leave unset to keep the current status
+    pub status: Option<QuestionStatus>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ApproveQuestionsRequest {
+    pub question_ids: Vec<String>,
+}
+
+// Response DTOs
+
+/// A question as shown to whoever curates the bank, answer key and reference answer included
+#[derive(Debug, Serialize)]
+pub struct BankQuestion {
+    #[serde(flatten)]
+    pub question: Question,
+    pub answer_key: Option<QuestionPayload>,
+    pub reference: Option<ReferenceAnswer>,
+    pub answer_count: i64,
+}
+
+impl BankQuestion {
+    fn new(question: Question, answer_count: i64) -> Self {
+        Self {
+            answer_key: question.answer_key(),
+            reference: question.reference(),
+            question,
+            answer_count,
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct ApproveQuestionsResponse {
+    /// Drafts that were approved; already approved or unknown ids are not counted
+    pub approved: u64,
+}
+
+// Handler functions
+
+/// Start generating questions into the question bank as drafts; poll the returned batch for progress
+pub async fn start_question_batch(
+    Path(kb_id): Path<String>,
+    State(state): State<AppState>,
+    Json(payload): Json<StartQuestionBatchRequest>,
+) -> AppResult<(StatusCode, Json<QuestionBatch>)> {
+    // Validate input
+    if let Err(validation_errors) = payload.validate() {
+        return Err(validation_error_to_app_error(validation_errors));
+    }
+
+    state.db.get_knowledge_base_by_id(&kb_id).await?
+        .ok_or_else(|| AppError::NotFound("Knowledge base not found".to_string()))?;
+
+    let documents = state.db.get_documents_by_knowledge_base(&kb_id).await?;
+    if documents.is_empty() {
+        return Err(AppError::BadRequest(
+            "No documents found in knowledge base. Please add some learning materials first.".to_string(),
+        ));
+    }
+    if let Some(document_id) = &payload.document_id
+        && !documents.iter().any(|doc| &doc.id == document_id)
+    {
+        return Err(AppError::NotFound("Document not found in this knowledge base".to_string()));
+    }
+
+    // Fail now rather than in the background when no provider can be used
+    state.provider_chain(&kb_id, PromptKind::Question).await?;
+
+    let mut batch = QuestionBatch::new(kb_id, payload.count as i32);
+    batch.question_type = payload.question_type;
+    batch.difficulty = payload.difficulty.map(i32::from);
+    batch.cognitive_level = payload.cognitive_level;
+    batch.document_id = payload.document_id.clone();
+    state.db.create_question_batch(&batch).await?;
+
+    let request = GenerateQuestionRequest {
+        strategy: payload.strategy,
+        document_id: payload.document_id,
+        question_type: payload.question_type,
+        difficulty: payload.difficulty,
+        cognitive_level: payload.cognitive_level,
+        ..GenerateQuestionRequest::default()
+    };
+    tokio::spawn(run_question_batch(state, batch.clone(), request));
+
+    tracing::info!("Started question batch {} for {} questions", batch.id, batch.requested);
+    Ok((StatusCode::ACCEPTED, Json(batch)))
+}
+
+pub async fn list_question_batches(
+    Path(kb_id): Path<String>,
+    State(state): State<AppState>,
+) -> AppResult<Json<Vec<QuestionBatch>>> {
+    state.db.get_knowledge_base_by_id(&kb_id).await?
+        .ok_or_else(|| AppError::NotFound("Knowledge base not found".to_string()))?;
+
+    Ok(Json(state.db.get_question_batches(&kb_id).await?))
+}
+
+pub async fn get_question_batch(
+    Path(id): Path<String>,
+    State(state): State<AppState>,
+) -> AppResult<Json<QuestionBatch>> {
+    let batch = state.db.get_question_batch(&id).await?
+        .ok_or_else(|| AppError::NotFound("Question batch not found".to_string()))?;
+
+    Ok(Json(batch))
+}
+
+/// Browse a knowledge base's questions with their answer keys, optionally only drafts or one batch
+pub async fn list_question_bank(
+    Path(kb_id): Path<String>,
+    Query(query): Query<QuestionBankQuery>,
+    State(state): State<AppState>,
+) -> AppResult<Json<Vec<BankQuestion>>> {
+    state.db.get_knowledge_base_by_id(&kb_id).await?
+        .ok_or_else(|| AppError::NotFound("Knowledge base not found".to_string()))?;
+
+    let questions = state.db.get_question_bank(&kb_id, query.status, query.batch_id.as_deref()).await?;
+
+    Ok(Json(
+        questions
+            .into_iter()
+            .map(|(question, answer_count)| BankQuestion::new(question, answer_count))
+            .collect(),
+    ))
+}
+
+/// Edit a question's text, answer key, reference answer or targeting, or move it between draft and approved
+pub async fn update_bank_question(
+    Path(id): Path<String>,
+    State(state): State<AppState>,
+    Json(payload): Json<UpdateBankQuestionRequest>,
+) -> AppResult<Json<BankQuestion>> {
+    // Validate input
+    if let Err(validation_errors) = payload.validate() {
+        return Err(validation_error_to_app_error(validation_errors));
+    }
+
+    let mut question = state.db.get_question_by_id(&id).await?
+        .ok_or_else(|| AppError::NotFound("Question not found".to_string()))?;
+    let answer_count = state.db.get_answers_by_question(&id).await?.len() as i64;
+
+    question.question_text = payload.question_text.trim().to_string();
+    if let Some(difficulty) = payload.difficulty {
+        question.difficulty = Some(i32::from(difficulty));
+    }
+    if let Some(cognitive_level) = payload.cognitive_level {
+        question.cognitive_level = Some(cognitive_level);
+    }
+    if let Some(status) = payload.status {
+        question.status = status;
+    }
+    if let Some(answer_key) = &payload.answer_key {
+        // Past answers were graded against the current key
+        if answer_count > 0 && question.answer_key().as_ref() != Some(answer_key) {
+            return Err(AppError::Conflict(
+                "The answer key of an answered question cannot be changed; delete it or add a new one".to_string(),
+            ));
+        }
+        question = question.with_payload(answer_key);
+    }
+    if let Some(reference) = &payload.reference {
+        if reference.answer.trim().is_empty() {
+            return Err(AppError::Validation("reference: answer must not be empty".to_string()));
+        }
+        // Past answers were graded against the current reference answer too
+        if answer_count > 0 && question.reference().as_ref() != Some(reference) {
+            return Err(AppError::Conflict(
+                "The reference answer of an answered question cannot be changed; delete it or add a new one".to_string(),
+            ));
+        }
+        question = question.with_reference(reference);
+    }
+    if let Some(answer_key) = question.answer_key() {
+        question_types::check_answer_key(&question.question_text, &answer_key)
+            .map_err(|e| AppError::Validation(format!("answer_key: {}", e)))?;
+    }
+
+    state.db.update_question(&question).await?;
+
+    tracing::info!("Updated question: {}", id);
+    Ok(Json(BankQuestion::new(question, answer_count)))
+}
+
+/// Approve draft questions so they can be quizzed
+pub async fn approve_bank_questions(
+    Path(kb_id): Path<String>,
+    State(state): State<AppState>,
+    Json(payload): Json<ApproveQuestionsRequest>,
+) -> AppResult<Json<ApproveQuestionsResponse>> {
+    if payload.question_ids.is_empty() {
+        return Err(AppError::Validation("question_ids must not be empty".to_string()));
+    }
+
+    state.db.get_knowledge_base_by_id(&kb_id).await?
+        .ok_or_else(|| AppError::NotFound("Knowledge base not found".to_string()))?;
+
+    let approved = state.db.approve_questions(&kb_id, &payload.question_ids).await?;
+
+    tracing::info!("Approved {} questions in knowledge base {}", approved, kb_id);
+    Ok(Json(ApproveQuestionsResponse { approved }))
+}
+
+/// Delete a question from the bank, along with any answers to it
+pub async fn delete_bank_question(
+    Path(id): Path<String>,
+    State(state): State<AppState>,
+) -> AppResult<Json<Value>> {
+    let deleted = state.db.delete_question(&id).await?;
+    if !deleted {
+        return Err(AppError::NotFound("Question not found".to_string()));
+    }
+
+    tracing::info!("Deleted question: {}", id);
+    Ok(Json(json!({"message": "Question deleted successfully"})))
+}
+
+/// An approved question nobody has answered yet, for quizzing from the bank
+pub async fn next_bank_question(
+    Path(kb_id): Path<String>,
+    State(state): State<AppState>,
+) -> AppResult<Json<QuestionResponse>> {
+    state.db.get_knowledge_base_by_id(&kb_id).await?
+        .ok_or_else(|| AppError::NotFound("Knowledge base not found".to_string()))?;
+
+    let question = state.db.get_unanswered_question(&kb_id).await?
+        .ok_or_else(|| AppError::NotFound("No unanswered approved questions in the question bank".to_string()))?;
+
+    Ok(Json(question.into()))
+}
+
+// Background job: generate the batch, then record how it ended
+async fn run_question_batch(state: AppState, mut batch: QuestionBatch, request: GenerateQuestionRequest) {
+    let error = fill_question_batch(&state, &mut batch, &request).await.err();
+    if let Some(error) = &error {
+        tracing::error!("Question batch {} failed: {}", batch.id, error);
+    }
+
+    batch.finish(error);
+    if let Err(e) = state.db.update_question_batch(&batch).await {
+        tracing::error!("Failed to save question batch {}: {}", batch.id, e);
+    }
+}
+
+// Generate drafts until the batch is full or out of attempts, saving progress as it goes
+async fn fill_question_batch(
+    state: &AppState,
+    batch: &mut QuestionBatch,
+    request: &GenerateQuestionRequest,
+) -> Result<(), String> {
+    let kb_id = batch.knowledge_base_id.clone();
+    let target = QuestionTarget {
+        difficulty: request.difficulty,
+        cognitive_level: request.cognitive_level,
+    };
+    let reference_answers = ai_quiz::writes_reference_answers(state, &kb_id).await;
+
+    // Compared against every question of the knowledge base, not just this batch's
+    let mut known: Vec<Vec<f32>> = state
+        .db
+        .get_question_bank(&kb_id, None, None)
+        .await
+        .map_err(|e| format!("Failed to load existing questions: {}", e))?
+        .iter()
+        .map(|(question, _)| HashingEmbedder::embed(&comparison_text(&question.question_text, question.answer_key().as_ref())))
+        .collect();
+
+    let mut consecutive_failures = 0;
+    for _ in 0..batch.requested * ATTEMPTS_PER_QUESTION {
+        if batch.generated >= batch.requested {
+            break;
+        }
+
+        let source = ai_quiz::select_question_source(state, &kb_id, request).await.map_err(quiz_error)?;
+        let prompt = ai_quiz::question_prompt(state, &source, request.question_type, &target).await.map_err(quiz_error)?;
+        // Loaded per question, so a budget used up partway stops the batch
+        let providers = state
+            .provider_chain(&kb_id, PromptKind::Question)
+            .await
+            .map_err(|e| e.to_string())?;
+
+        match ai_quiz::generate_with(&providers, &prompt, request.question_type).await {
+            Ok(generated) => {
+                consecutive_failures = 0;
+                let mut usage = generated.usage;
+                usage.knowledge_base_id = Some(kb_id.clone());
+
+                let embedding = HashingEmbedder::embed(&comparison_text(
+                    &generated.value.question_text,
+                    generated.value.payload.as_ref(),
+                ));
+                if known.iter().any(|other| HashingEmbedder::cosine(&embedding, other) >= DUPLICATE_SIMILARITY) {
+                    batch.duplicates += 1;
+                } else {
+                    let mut question = ai_quiz::generated_question_record(
+                        kb_id.clone(),
+                        generated.value,
+                        generated.served_by,
+                        &source,
+                        &target,
+                    );
+                    question.status = QuestionStatus::Draft;
+                    question.batch_id = Some(batch.id.clone());
+                    let (question, reference_usage) = if reference_answers {
+                        ai_quiz::add_reference_answer(state, &providers, question, &source).await
+                    } else {
+                        (question, None)
+                    };
+                    state
+                        .db
+                        .save_question(&question)
+                        .await
+                        .map_err(|e| format!("Failed to save question: {}", e))?;
+
+                    usage.question_id = Some(question.id.clone());
+                    if let Some(mut reference_usage) = reference_usage {
+                        reference_usage.knowledge_base_id = Some(kb_id.clone());
+                        reference_usage.question_id = Some(question.id.clone());
+                        ai_quiz::record_usage(state, &reference_usage).await;
+                    }
+                    known.push(embedding);
+                    batch.generated += 1;
+                }
+                ai_quiz::record_usage(state, &usage).await;
+            }
+            Err(e) => {
+                tracing::warn!("Question batch {} failed to generate a question: {}", batch.id, e);
+                batch.failures += 1;
+                consecutive_failures += 1;
+                if consecutive_failures >= MAX_CONSECUTIVE_FAILURES {
+                    return Err(format!("Stopped after {} failed generations in a row: {}", consecutive_failures, e));
+                }
+            }
+        }
+
+        if let Err(e) = state.db.update_question_batch(batch).await {
+            tracing::error!("Failed to save progress of question batch {}: {}", batch.id, e);
+        }
+    }
+
+    Ok(())
+}
+
+// What two questions are compared on: the text plus the choices or expected answers
+fn comparison_text(question_text: &str, answer_key: Option<&QuestionPayload>) -> String {
+    let mut text = question_text.to_string();
+    match answer_key {
+        Some(QuestionPayload::MultipleChoice { options, .. }) => text.extend(options.iter().map(|option| format!("\n{}", option))),
+        Some(QuestionPayload::Cloze { answers }) => text.extend(answers.iter().map(|answer| format!("\n{}", answer))),
+        Some(QuestionPayload::ShortAnswer { answer, .. }) => text.push_str(&format!("\n{}", answer)),
+        Some(QuestionPayload::TrueFalse { .. }) | None => {}
+    }
+    text
+}
+
+// The message of an error from the quiz helpers, which answer in their own JSON shape
+fn quiz_error((_, Json(body)): (StatusCode, Json<Value>)) -> String {
+    body["error"].as_str().unwrap_or("Failed to prepare question generation").to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::create_connection_pool;
+    use crate::handlers::ai_quiz::{submit_answer, AnswerRequest};
+    use crate::models::{AIConfig, AIProvider, BatchStatus, Document, DocumentType};
+
+    async fn create_test_app_state() -> AppState {
+        let pool = create_connection_pool("sqlite::memory:").await.unwrap();
+        AppState::new(pool)
+    }
+
+    // A knowledge base with one document and a mock provider replying with `questions` in turn
+    async fn setup_bank(state: &AppState, questions: Value) -> String {
+        let kb = state.db.create_knowledge_base("Bank", None).await.unwrap();
+        let document = Document::new(
+            kb.id.clone(),
+            "notes.txt".to_string(),
+            DocumentType::Txt,
+            "/tmp/notes.txt".to_string(),
+            100,
+            Some("Rust manages memory through ownership. Borrowing lets code use a value without taking ownership of it.".to_string()),
+        );
+        state.db.save_document(&document).await.unwrap();
+
+        let mut ai_config = AIConfig::new(AIProvider::Mock, None, None, None, 1000, 0.7);
+        ai_config.provider_options = Some(json!({"questions": questions}).to_string());
+        state.db.save_ai_config(&ai_config).await.unwrap();
+
+        kb.id
+    }
+
+    async fn wait_for_batch(state: &AppState, id: &str) -> QuestionBatch {
+        for _ in 0..200 {
+            let batch = state.db.get_question_batch(id).await.unwrap().unwrap();
+            if batch.status != BatchStatus::Running {
+                return batch;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        }
+        panic!("question batch {} did not finish", id);
+    }
+
+    #[tokio::test]
+    async fn test_batch_fills_bank_with_unique_drafts() {
+        let state = create_test_app_state().await;
+        let kb_id = setup_bank(
+            &state,
+            json!([
+                "How does Rust manage memory?",
+                "How does Rust manage memory?",
+                "What does borrowing let code do with a value?",
+            ]),
+        )
+        .await;
+
+        let request = StartQuestionBatchRequest { count: 2, ..StartQuestionBatchRequest::default() };
+        let (status, Json(batch)) = start_question_batch(Path(kb_id.clone()), State(state.clone()), Json(request)).await.unwrap();
+        assert_eq!(status, StatusCode::ACCEPTED);
+
+        let batch = wait_for_batch(&state, &batch.id).await;
+        assert_eq!(batch.status, BatchStatus::Completed);
+        assert_eq!(batch.generated, 2);
+        assert_eq!(batch.duplicates, 1);
+        assert!(batch.finished_at.is_some());
+
+        let query = QuestionBankQuery { status: Some(QuestionStatus::Draft), batch_id: Some(batch.id.clone()) };
+        let Json(drafts) = list_question_bank(Path(kb_id.clone()), Query(query), State(state.clone())).await.unwrap();
+        assert_eq!(drafts.len(), 2);
+        assert!(drafts.iter().all(|draft| draft.answer_count == 0));
+
+        // Drafts can't be quizzed until approved
+        let draft_id = drafts[0].question.id.clone();
+        let answer = || Json(AnswerRequest { user_answer: "Through ownership".to_string() });
+        let (status, _) = submit_answer(Path(draft_id.clone()), State(state.clone()), answer()).await.unwrap_err();
+        assert_eq!(status, StatusCode::CONFLICT);
+        assert!(matches!(
+            next_bank_question(Path(kb_id.clone()), State(state.clone())).await,
+            Err(AppError::NotFound(_))
+        ));
+
+        let request = ApproveQuestionsRequest { question_ids: vec![draft_id.clone(), "missing".to_string()] };
+        let Json(approved) = approve_bank_questions(Path(kb_id.clone()), State(state.clone()), Json(request)).await.unwrap();
+        assert_eq!(approved.approved, 1);
+
+        let Json(next) = next_bank_question(Path(kb_id.clone()), State(state.clone())).await.unwrap();
+        assert_eq!(next.id, draft_id);
+        assert!(submit_answer(Path(draft_id), State(state.clone()), answer()).await.is_ok());
+
+        let _ = delete_bank_question(Path(drafts[1].question.id.clone()), State(state.clone())).await.unwrap();
+        let Json(bank) = list_question_bank(Path(kb_id), Query(QuestionBankQuery::default()), State(state.clone())).await.unwrap();
+        assert_eq!(bank.len(), 1);
+        assert_eq!(bank[0].answer_count, 1);
+
+        // The answered question's reference answer is fixed like its answer key
+        let edit = UpdateBankQuestionRequest {
+            question_text: bank[0].question.question_text.clone(),
+            answer_key: None,
+            reference: Some(ReferenceAnswer { answer: "Through ownership.".to_string(), key_points: Vec::new() }),
+            difficulty: None,
+            cognitive_level: None,
+            status: None,
+        };
+        let result = update_bank_question(Path(bank[0].question.id.clone()), State(state), Json(edit)).await;
+        assert!(matches!(result, Err(AppError::Conflict(_))));
+    }
+
+    #[tokio::test]
+    async fn test_start_batch_rejects_bad_requests() {
+        let state = create_test_app_state().await;
+        let kb_id = setup_bank(&state, json!([])).await;
+
+        let request = StartQuestionBatchRequest { count: 0, ..StartQuestionBatchRequest::default() };
+        let result = start_question_batch(Path(kb_id.clone()), State(state.clone()), Json(request)).await;
+        assert!(matches!(result, Err(AppError::Validation(_))));
+
+        let request = StartQuestionBatchRequest {
+            count: 3,
+            document_id: Some("elsewhere".to_string()),
+            ..StartQuestionBatchRequest::default()
+        };
+        let result = start_question_batch(Path(kb_id), State(state), Json(request)).await;
+        assert!(matches!(result, Err(AppError::NotFound(_))));
+    }
+
+    #[tokio::test]
+    async fn test_edited_answer_keys_must_match_the_text() {
+        let state = create_test_app_state().await;
+        let kb_id = setup_bank(&state, json!([r#"{"text": "Rust manages memory through [[ownership]]."}"#])).await;
+
+        let request = StartQuestionBatchRequest { count: 1, question_type: QuestionType::Cloze, ..StartQuestionBatchRequest::default() };
+        let (_, Json(batch)) = start_question_batch(Path(kb_id.clone()), State(state.clone()), Json(request)).await.unwrap();
+        wait_for_batch(&state, &batch.id).await;
+        let Json(bank) = list_question_bank(Path(kb_id), Query(QuestionBankQuery::default()), State(state.clone())).await.unwrap();
+        let id = bank[0].question.id.clone();
+
+        let edit = |question_text: &str, answers: &[&str]| UpdateBankQuestionRequest {
+            question_text: question_text.to_string(),
+            answer_key: Some(QuestionPayload::Cloze { answers: answers.iter().map(|a| a.to_string()).collect() }),
+            reference: None,
+            difficulty: Some(2),
+            cognitive_level: None,
+            status: Some(QuestionStatus::Approved),
+        };
+
+        let result = update_bank_question(Path(id.clone()), State(state.clone()), Json(edit("Rust manages memory.", &["ownership"]))).await;
+        assert!(matches!(result, Err(AppError::Validation(_))));
+
+        let Json(updated) = update_bank_question(
+            Path(id.clone()),
+            State(state.clone()),
+            Json(edit("Rust manages ____ through ____.", &["memory", "ownership"])),
+        )
+        .await
+        .unwrap();
+        assert_eq!(updated.question.status, QuestionStatus::Approved);
+        assert_eq!(updated.question.difficulty, Some(2));
+
+        let saved = state.db.get_question_by_id(&id).await.unwrap().unwrap();
+        assert_eq!(saved.question_text, "Rust manages ____ through ____.");
+        assert_eq!(saved.answer_key(), Some(QuestionPayload::Cloze { answers: vec!["memory".to_string(), "ownership".to_string()] }));
+
+        // Fields left unset keep their current values
+        let Json(updated) = update_bank_question(
+            Path(id.clone()),
+            State(state.clone()),
+            Json(UpdateBankQuestionRequest {
+                question_text: "Rust manages ____ through ____!".to_string(),
+                answer_key: None,
+                reference: None,
+                difficulty: None,
+                cognitive_level: None,
+                status: None,
+            }),
+        )
+        .await
+        .unwrap();
+        assert_eq!(updated.question.difficulty, Some(2));
+        assert_eq!(updated.question.status, QuestionStatus::Approved);
+    }
+}
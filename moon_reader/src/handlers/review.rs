@@ -6,10 +6,12 @@ use serde::{Deserialize, Serialize};
 use serde_json::{json, Value};
 use chrono::{DateTime, Utc};
 use rand::seq::SliceRandom;
+use std::collections::HashMap;
 
-use crate::services::AppState;
-use crate::models::{ReviewSession, Question, Answer, LearningProgress};
+use crate::services::{AppState, question_types::{self, LOCAL_GRADER}};
+use crate::models::{ReviewSession, Question, QuestionStatus, Answer, AnswerMessage, LearningProgress};
 use crate::error::AppError;
+use crate::handlers::ai_quiz::QuestionResponse;
 
 #[derive(Debug, Deserialize)]
 pub struct HistoryQueryParams {
@@ -31,6 +33,8 @@ pub struct CreateReviewSessionRequest {
 pub struct HistoryItem {
     pub question: Question,
     pub answer: Answer,
+    /// Follow-up conversation about the answer, oldest message first
+    pub messages: Vec<AnswerMessage>,
 }
 
 #[derive(Debug, Serialize)]
@@ -46,7 +50,7 @@ pub async fn get_random_review_question(
 ) -> Result<Json<Value>, AppError> {
     // Get all question-answer history for this knowledge base
     let history = state.db.get_question_answer_history(&kb_id, None, None).await
-        .map_err(|e| AppError::Database(e))?;
+        .map_err(AppError::Database)?;
     
     if history.is_empty() {
         return Ok(Json(json!({
@@ -60,7 +64,7 @@ pub async fn get_random_review_question(
     let (question, _) = history.choose(&mut rng).unwrap();
     
     Ok(Json(json!({
-        "question": question,
+        "question": QuestionResponse::from(question.clone()),
         "message": "Review this question from your history"
     })))
 }
@@ -73,7 +77,7 @@ pub async fn get_history(
 ) -> Result<Json<HistoryResponse>, AppError> {
     // Check if knowledge base exists
     let kb = state.db.get_knowledge_base_by_id(&kb_id).await
-        .map_err(|e| AppError::Database(e))?;
+        .map_err(AppError::Database)?;
     
     if kb.is_none() {
         return Err(AppError::Validation("Knowledge base not found".to_string()));
@@ -88,17 +92,23 @@ pub async fn get_history(
             params.max_score,
             params.start_date,
             params.end_date,
-        ).await.map_err(|e| AppError::Database(e))?
+        ).await.map_err(AppError::Database)?
     } else {
         state.db.get_question_answer_history(
             &kb_id,
             params.limit,
             params.offset,
-        ).await.map_err(|e| AppError::Database(e))?
+        ).await.map_err(AppError::Database)?
     };
     
+    let mut threads: HashMap<String, Vec<AnswerMessage>> = HashMap::new();
+    for message in state.db.get_answer_messages_by_knowledge_base(&kb_id).await.map_err(AppError::Database)? {
+        threads.entry(message.answer_id.clone()).or_default().push(message);
+    }
+    
     let items: Vec<HistoryItem> = history.into_iter().map(|(question, answer)| {
-        HistoryItem { question, answer }
+        let messages = threads.remove(&answer.id).unwrap_or_default();
+        HistoryItem { question, answer, messages }
     }).collect();
     
     let total_count = items.len();
@@ -121,7 +131,7 @@ pub async fn create_review_session(
     
     // Check if knowledge base exists
     let kb = state.db.get_knowledge_base_by_id(&payload.knowledge_base_id).await
-        .map_err(|e| AppError::Database(e))?;
+        .map_err(AppError::Database)?;
     
     if kb.is_none() {
         return Err(AppError::Validation("Knowledge base not found".to_string()));
@@ -129,7 +139,7 @@ pub async fn create_review_session(
     
     // Check if there's enough history for the requested number of questions
     let history = state.db.get_question_answer_history(&payload.knowledge_base_id, None, None).await
-        .map_err(|e| AppError::Database(e))?;
+        .map_err(AppError::Database)?;
     
     if history.len() < payload.questions_count as usize {
         return Err(AppError::Validation(format!(
@@ -143,7 +153,7 @@ pub async fn create_review_session(
     let session = ReviewSession::new(payload.knowledge_base_id, payload.questions_count);
     
     state.db.save_review_session(&session).await
-        .map_err(|e| AppError::Database(e))?;
+        .map_err(AppError::Database)?;
     
     Ok(Json(json!({
         "session_id": session.id,
@@ -161,14 +171,14 @@ pub async fn get_review_sessions(
 ) -> Result<Json<Value>, AppError> {
     // Check if knowledge base exists
     let kb = state.db.get_knowledge_base_by_id(&kb_id).await
-        .map_err(|e| AppError::Database(e))?;
+        .map_err(AppError::Database)?;
     
     if kb.is_none() {
         return Err(AppError::Validation("Knowledge base not found".to_string()));
     }
     
     let sessions = state.db.get_review_sessions_by_knowledge_base(&kb_id).await
-        .map_err(|e| AppError::Database(e))?;
+        .map_err(AppError::Database)?;
     
     Ok(Json(json!({
         "sessions": sessions,
@@ -186,12 +196,12 @@ pub async fn update_review_session_score(
         .and_then(|v| v.as_f64())
         .ok_or_else(|| AppError::Validation("Missing or invalid average_score".to_string()))?;
     
-    if average_score < 0.0 || average_score > 100.0 {
+    if !(0.0..=100.0).contains(&average_score) {
         return Err(AppError::Validation("Average score must be between 0 and 100".to_string()));
     }
     
     let updated = state.db.update_review_session_score(&session_id, average_score).await
-        .map_err(|e| AppError::Database(e))?;
+        .map_err(AppError::Database)?;
     
     if !updated {
         return Err(AppError::Validation("Review session not found".to_string()));
@@ -223,7 +233,7 @@ pub async fn get_review_questions(
     
     // Check if knowledge base exists
     let kb = state.db.get_knowledge_base_by_id(&kb_id).await
-        .map_err(|e| AppError::Database(e))?;
+        .map_err(AppError::Database)?;
     
     if kb.is_none() {
         return Err(AppError::Validation("Knowledge base not found".to_string()));
@@ -231,7 +241,7 @@ pub async fn get_review_questions(
     
     // Get random questions from history
     let questions = state.db.get_random_review_questions(&kb_id, count).await
-        .map_err(|e| AppError::Database(e))?;
+        .map_err(AppError::Database)?;
     
     if questions.is_empty() {
         return Ok(Json(json!({
@@ -240,8 +250,8 @@ pub async fn get_review_questions(
         })));
     }
     
-    // Return only the questions (without previous answers for review)
-    let review_questions: Vec<_> = questions.into_iter().map(|(question, _)| question).collect();
+    // Return only the questions (without previous answers or answer keys for review)
+    let review_questions: Vec<QuestionResponse> = questions.into_iter().map(|(question, _)| question.into()).collect();
     
     Ok(Json(json!({
         "questions": review_questions,
@@ -257,14 +267,14 @@ pub async fn get_learning_progress(
 ) -> Result<Json<LearningProgress>, AppError> {
     // Check if knowledge base exists
     let kb = state.db.get_knowledge_base_by_id(&kb_id).await
-        .map_err(|e| AppError::Database(e))?;
+        .map_err(AppError::Database)?;
     
     if kb.is_none() {
         return Err(AppError::Validation("Knowledge base not found".to_string()));
     }
     
     let progress = state.db.get_learning_progress(&kb_id).await
-        .map_err(|e| AppError::Database(e))?;
+        .map_err(AppError::Database)?;
     
     Ok(Json(progress))
 }
@@ -288,16 +298,48 @@ pub async fn submit_review_answer(
     
     // Get the original question
     let question = state.db.get_question_by_id(&payload.question_id).await
-        .map_err(|e| AppError::Database(e))?;
+        .map_err(AppError::Database)?;
     
-    let _question = question.ok_or_else(|| AppError::Validation("Question not found".to_string()))?;
+    let question = question.ok_or_else(|| AppError::Validation("Question not found".to_string()))?;
+    
+    if question.status == QuestionStatus::Draft {
+        return Err(AppError::Conflict("Question is a draft in the question bank; approve it before answering".to_string()));
+    }
     
     // Create new answer for the review
-    let answer = Answer::new(payload.question_id.clone(), payload.user_answer.clone());
+    let mut answer = Answer::new(payload.question_id.clone(), payload.user_answer.clone());
+    
+    // Objective questions are graded against their answer key
+    let evaluation = question_types::grade(&question, &payload.user_answer);
+    if let Some(evaluation) = &evaluation {
+        answer.ai_score = evaluation.score.map(i32::from);
+        answer.ai_feedback = Some(evaluation.feedback.clone());
+        answer.ai_suggestions = Some(serde_json::to_string(&evaluation.suggestions).unwrap_or_default());
+        answer.served_by = Some(LOCAL_GRADER.to_string());
+    }
     
     // Save the review answer
     state.db.save_answer(&answer).await
-        .map_err(|e| AppError::Database(e))?;
+        .map_err(AppError::Database)?;
+    
+    // Revealed now that the question is answered
+    let reference = question.reference();
+    let reference_answer = reference.as_ref().map(|reference| reference.answer.clone());
+    let key_points = reference.map(|reference| reference.key_points).unwrap_or_default();
+    
+    if evaluation.is_some() {
+        return Ok(Json(json!({
+            "answer_id": answer.id,
+            "question_id": payload.question_id,
+            "user_answer": payload.user_answer,
+            "submitted_at": answer.answered_at,
+            "ai_score": answer.ai_score,
+            "ai_feedback": answer.ai_feedback,
+            "reference_answer": reference_answer,
+            "key_points": key_points,
+            "message": "Review answer submitted successfully"
+        })));
+    }
     
     // Get the knowledge base content for AI evaluation (if AI service is available)
     // For now, we'll return a simple response without AI evaluation
@@ -308,6 +350,8 @@ pub async fn submit_review_answer(
         "question_id": payload.question_id,
         "user_answer": payload.user_answer,
         "submitted_at": answer.answered_at,
+        "reference_answer": reference_answer,
+        "key_points": key_points,
         "message": "Review answer submitted successfully",
         "note": "AI evaluation will be added in future updates"
     })))
@@ -0,0 +1,72 @@
+use axum::{
+    extract::{Path, Query, State},
+    response::Json,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::error::{AppError, AppResult};
+use crate::services::AppState;
+use crate::services::retrieval::Retriever;
+
+const DEFAULT_TOP_K: usize = 5;
+const MAX_TOP_K: usize = 50;
+
+#[derive(Debug, Deserialize)]
+pub struct SearchQueryParams {
+    pub q: String,
+    pub top_k: Option<usize>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SearchResultItem {
+    pub chunk_id: String,
+    pub document_id: String,
+    pub chunk_index: i32,
+    pub start_offset: i64,
+    pub end_offset: i64,
+    pub content: String,
+    pub score: f32,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SearchResponse {
+    pub query: String,
+    pub results: Vec<SearchResultItem>,
+}
+
+// Semantic search over the chunks of a knowledge base
+pub async fn search_knowledge_base(
+    Path(kb_id): Path<String>,
+    Query(params): Query<SearchQueryParams>,
+    State(state): State<AppState>,
+) -> AppResult<Json<SearchResponse>> {
+    if params.q.trim().is_empty() {
+        return Err(AppError::Validation("Search query cannot be empty".to_string()));
+    }
+
+    let top_k = params.top_k.unwrap_or(DEFAULT_TOP_K);
+    if top_k == 0 || top_k > MAX_TOP_K {
+        return Err(AppError::Validation(format!("top_k must be between 1 and {}", MAX_TOP_K)));
+    }
+
+    if state.db.get_knowledge_base_by_id(&kb_id).await?.is_none() {
+        return Err(AppError::NotFound("Knowledge base not found".to_string()));
+    }
+
+    let hits = Retriever::search(&state.db, &kb_id, &params.q, top_k).await?;
+
+    let results = hits.into_iter().map(|hit| SearchResultItem {
+        chunk_id: hit.chunk.id,
+        document_id: hit.chunk.document_id,
+        chunk_index: hit.chunk.chunk_index,
+        start_offset: hit.chunk.start_offset,
+        end_offset: hit.chunk.end_offset,
+        content: hit.chunk.content,
+        score: hit.score,
+    }).collect();
+
+    Ok(Json(SearchResponse {
+        query: params.q,
+        results,
+    }))
+}
@@ -0,0 +1,298 @@
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::Json,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use validator::Validate;
+
+use crate::handlers::ai_quiz::{ai_error_status, output_language, provider_error_response, record_usage};
+use crate::models::{Answer, AnswerMessage, PromptKind, Question, ThreadRole};
+use crate::services::{AppState, retrieval::{EvidencePassage, Retriever, DEFAULT_EVIDENCE_PASSAGES}, tutoring::{self, TutoringContext}};
+
+#[derive(Debug, Serialize, Deserialize, Validate)]
+pub struct AnswerMessageRequest {
+    #[validate(length(min = 1, max = 2000, message = "Message must be between 1 and 2000 characters"))]
+    pub message: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct AnswerThreadResponse {
+    pub answer_id: String,
+    pub messages: Vec<AnswerMessage>,
+}
+
+/// The learner's message and the tutor's reply, as stored in the thread
+#[derive(Debug, Serialize)]
+pub struct AnswerMessageResponse {
+    pub message: AnswerMessage,
+    pub reply: AnswerMessage,
+}
+
+/// The follow-up conversation about an answer, oldest message first
+pub async fn get_answer_thread(
+    Path(answer_id): Path<String>,
+    State(state): State<AppState>,
+) -> Result<Json<AnswerThreadResponse>, (StatusCode, Json<Value>)> {
+    let answer = load_answer(&state, &answer_id).await?;
+    let messages = load_thread(&state, &answer.id).await?;
+
+    Ok(Json(AnswerThreadResponse { answer_id: answer.id, messages }))
+}
+
+/// Ask a follow-up question about an answer's feedback; the tutor replies from the question's source material
+pub async fn send_answer_message(
+    Path(answer_id): Path<String>,
+    State(state): State<AppState>,
+    Json(payload): Json<AnswerMessageRequest>,
+) -> Result<Json<AnswerMessageResponse>, (StatusCode, Json<Value>)> {
+    if let Err(validation_errors) = payload.validate() {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            Json(json!({
+                "error": "Validation failed",
+                "details": validation_errors.to_string()
+            })),
+        ));
+    }
+    let text = payload.message.trim();
+    if text.is_empty() {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            Json(json!({"error": "Message cannot be empty"})),
+        ));
+    }
+
+    let answer = load_answer(&state, &answer_id).await?;
+    let question = match state.db.get_question_by_id(&answer.question_id).await {
+        Ok(Some(question)) => question,
+        Ok(None) => {
+            return Err((
+                StatusCode::NOT_FOUND,
+                Json(json!({"error": "Question not found"})),
+            ));
+        }
+        Err(e) => {
+            tracing::error!("Failed to get question: {}", e);
+            return Err((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({"error": "Failed to retrieve question"})),
+            ));
+        }
+    };
+    let thread = load_thread(&state, &answer.id).await?;
+    let evidence = answer_evidence(&state, &question, &answer).await?;
+
+    let material = EvidencePassage::format_context(&evidence);
+    let sample = evidence.first().map(|passage| passage.content.as_str()).unwrap_or(&question.question_text);
+    let language = output_language(&state, &question.knowledge_base_id, sample).await?;
+    let reference = question.reference();
+    let context = TutoringContext {
+        material: &material,
+        question: &question.question_text,
+        reference: reference.as_ref(),
+        answer: &answer,
+        language,
+    };
+    let prompt = tutoring::tutoring_prompt(&context, &thread, text);
+
+    let providers = state
+        .provider_chain(&question.knowledge_base_id, PromptKind::Evaluation)
+        .await
+        .map_err(provider_error_response)?;
+    let served = match providers.tutor_reply(&prompt).await {
+        Ok(served) => served,
+        Err(e) => {
+            tracing::error!("Failed to get tutor reply: {}", e);
+            return Err((
+                ai_error_status(&e),
+                Json(json!({"error": format!("Failed to get tutor reply: {}", e)})),
+            ));
+        }
+    };
+
+    // The learner's message is only kept with a reply, so a failed call can simply be retried
+    let message = AnswerMessage::new(answer.id.clone(), ThreadRole::Learner, text.to_string());
+    let mut reply = AnswerMessage::new(answer.id.clone(), ThreadRole::Tutor, served.value);
+    reply.served_by = Some(served.served_by);
+    for message in [&message, &reply] {
+        if let Err(e) = state.db.save_answer_message(message).await {
+            tracing::error!("Failed to save thread message: {}", e);
+            return Err((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({"error": "Failed to save message"})),
+            ));
+        }
+    }
+
+    let mut usage = served.usage;
+    usage.knowledge_base_id = Some(question.knowledge_base_id.clone());
+    usage.question_id = Some(question.id.clone());
+    usage.answer_id = Some(answer.id.clone());
+    record_usage(&state, &usage).await;
+
+    Ok(Json(AnswerMessageResponse { message, reply }))
+}
+
+async fn load_answer(state: &AppState, answer_id: &str) -> Result<Answer, (StatusCode, Json<Value>)> {
+    match state.db.get_answer_by_id(answer_id).await {
+        Ok(Some(answer)) => Ok(answer),
+        Ok(None) => Err((
+            StatusCode::NOT_FOUND,
+            Json(json!({"error": "Answer not found"})),
+        )),
+        Err(e) => {
+            tracing::error!("Failed to get answer: {}", e);
+            Err((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(json!({"error": "Failed to retrieve answer"})),
+            ))
+        }
+    }
+}
+
+async fn load_thread(state: &AppState, answer_id: &str) -> Result<Vec<AnswerMessage>, (StatusCode, Json<Value>)> {
+    state.db.get_answer_messages(answer_id).await.map_err(|e| {
+        tracing::error!("Failed to get thread messages: {}", e);
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({"error": "Failed to retrieve messages"})),
+        )
+    })
+}
+
+// The passages the answer was scored against; answers graded without them get the question's evidence
+async fn answer_evidence(
+    state: &AppState,
+    question: &Question,
+    answer: &Answer,
+) -> Result<Vec<EvidencePassage>, (StatusCode, Json<Value>)> {
+    let stored = answer
+        .evidence
+        .as_deref()
+        .and_then(|evidence| serde_json::from_str::<Vec<EvidencePassage>>(evidence).ok())
+        .filter(|evidence| !evidence.is_empty());
+    if let Some(evidence) = stored {
+        return Ok(evidence);
+    }
+
+    Retriever::gather_evidence(&state.db, question, DEFAULT_EVIDENCE_PASSAGES).await.map_err(|e| {
+        tracing::error!("Failed to gather evidence: {}", e);
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({"error": "Failed to retrieve reference passages"})),
+        )
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::create_connection_pool;
+    use crate::handlers::ai_quiz::{generate_question, submit_answer, AnswerRequest, GenerateQuestionRequest};
+    use crate::models::{AIConfig, AIProvider, Document, DocumentType, UsageGrouping};
+
+    async fn create_test_app_state() -> AppState {
+        let pool = create_connection_pool("sqlite::memory:").await.unwrap();
+        AppState::new(pool)
+    }
+
+    // An answered question in a knowledge base whose mock provider replies with `options`
+    async fn setup_answer(state: &AppState, options: Value) -> String {
+        let kb = state.db.create_knowledge_base("Tutoring", None).await.unwrap();
+        let document = Document::new(
+            kb.id.clone(),
+            "notes.txt".to_string(),
+            DocumentType::Txt,
+            "/tmp/notes.txt".to_string(),
+            100,
+            Some("Rust manages memory through ownership. Borrowing lets code use a value without taking ownership of it.".to_string()),
+        );
+        state.db.save_document(&document).await.unwrap();
+
+        let mut ai_config = AIConfig::new(AIProvider::Mock, None, None, None, 1000, 0.7);
+        ai_config.provider_options = Some(options.to_string());
+        state.db.save_ai_config(&ai_config).await.unwrap();
+
+        let Json(question) = generate_question(Path(kb.id.clone()), State(state.clone()), Ok(Json(GenerateQuestionRequest::default()))).await.unwrap();
+        let Json(answer) = submit_answer(
+            Path(question["id"].as_str().unwrap().to_string()),
+            State(state.clone()),
+            Json(AnswerRequest { user_answer: "A garbage collector".to_string() }),
+        ).await.unwrap();
+        answer["id"].as_str().unwrap().to_string()
+    }
+
+    fn message(text: &str) -> Json<AnswerMessageRequest> {
+        Json(AnswerMessageRequest { message: text.to_string() })
+    }
+
+    #[tokio::test]
+    async fn test_follow_up_thread() {
+        let state = create_test_app_state().await;
+        let answer_id = setup_answer(&state, json!({
+            "questions": ["How does Rust free memory?"],
+            "evaluations": [{"score": 20, "feedback": "Rust has no garbage collector", "suggestions": []}],
+            "tutor_replies": ["Passage [1] says Rust uses ownership instead.", "Borrowing lends a value without moving it."],
+        })).await;
+
+        let Json(first) = send_answer_message(Path(answer_id.clone()), State(state.clone()), message(" Why is that wrong? ")).await.unwrap();
+        assert_eq!(first.message.role, ThreadRole::Learner);
+        assert_eq!(first.message.content, "Why is that wrong?");
+        assert_eq!(first.reply.role, ThreadRole::Tutor);
+        assert_eq!(first.reply.content, "Passage [1] says Rust uses ownership instead.");
+        assert_eq!(first.reply.served_by.as_deref(), Some("mock"));
+
+        let _ = send_answer_message(Path(answer_id.clone()), State(state.clone()), message("And borrowing?")).await.unwrap();
+
+        let Json(thread) = get_answer_thread(Path(answer_id.clone()), State(state.clone())).await.unwrap();
+        let contents: Vec<&str> = thread.messages.iter().map(|m| m.content.as_str()).collect();
+        assert_eq!(contents, vec![
+            "Why is that wrong?",
+            "Passage [1] says Rust uses ownership instead.",
+            "And borrowing?",
+            "Borrowing lends a value without moving it.",
+        ]);
+
+        // Question, reference answer, evaluation and two tutor replies
+        let usage = state.db.get_ai_usage_summary(UsageGrouping::KnowledgeBase, None, None).await.unwrap();
+        assert_eq!(usage[0].calls, 5);
+    }
+
+    #[tokio::test]
+    async fn test_tutor_prompt_is_grounded_in_the_answer() {
+        let state = create_test_app_state().await;
+        let answer_id = setup_answer(&state, json!({
+            "questions": ["How does Rust free memory?"],
+            "evaluations": [{"score": 20, "feedback": "Rust has no garbage collector", "suggestions": []}],
+        })).await;
+
+        // The generated reply quotes the material the answer was scored against
+        let Json(exchange) = send_answer_message(Path(answer_id.clone()), State(state.clone()), message("Why?")).await.unwrap();
+        assert!(exchange.reply.content.contains("Rust manages memory through ownership"));
+        assert!(exchange.reply.content.contains("\"Why?\""));
+    }
+
+    #[tokio::test]
+    async fn test_thread_errors() {
+        let state = create_test_app_state().await;
+        let answer_id = setup_answer(&state, json!({
+            "questions": ["How does Rust free memory?"],
+            "fail_every": 4,
+        })).await;
+
+        let (status, _) = send_answer_message(Path(answer_id.clone()), State(state.clone()), message("   ")).await.unwrap_err();
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+
+        let (status, _) = send_answer_message(Path("missing".to_string()), State(state.clone()), message("Why?")).await.unwrap_err();
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        let (status, _) = get_answer_thread(Path("missing".to_string()), State(state.clone())).await.unwrap_err();
+        assert_eq!(status, StatusCode::NOT_FOUND);
+
+        // A failed reply keeps nothing, so the learner can ask again
+        let (status, _) = send_answer_message(Path(answer_id.clone()), State(state.clone()), message("Why?")).await.unwrap_err();
+        assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+        assert!(state.db.get_answer_messages(&answer_id).await.unwrap().is_empty());
+    }
+}
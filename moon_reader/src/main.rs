@@ -1,21 +1,14 @@
-mod handlers;
-mod models;
-mod services;
-mod database;
-mod parsers;
-mod error;
-
 use axum::{
     routing::{get, post, put, delete},
     Router,
 };
 use tower_http::cors::CorsLayer;
-use tracing_subscriber;
 use std::env;
 
-use crate::handlers::*;
-use crate::services::AppState;
-use crate::database::create_connection_pool;
+use moon_reader::handlers::*;
+use moon_reader::services::AppState;
+use moon_reader::services::secrets::{self, SecretCipher, SecretKeySource};
+use moon_reader::database::{create_connection_pool, DatabaseManager};
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
@@ -29,8 +22,25 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     // Create database connection pool
     let pool = create_connection_pool(&database_url).await?;
     
-    // Create application state
-    let app_state = AppState::new(pool);
+    // API keys are encrypted at rest with this key
+    let (cipher, key_source) = SecretCipher::load()?;
+    
+    if env::args().nth(1).as_deref() == Some("rotate-secret-key") {
+        return rotate_secret_key(DatabaseManager::with_secrets(pool, cipher), key_source).await;
+    }
+    
+    // Create application state, encrypting keys left in plaintext by earlier versions
+    let app_state = AppState::with_secrets(pool, cipher);
+    let encrypted = app_state.db.encrypt_plaintext_secrets().await?;
+    if encrypted > 0 {
+        tracing::info!("Encrypted {} stored API keys", encrypted);
+    }
+    
+    // Batch jobs run in this process, so any still marked running were cut short
+    let interrupted = app_state.db.fail_interrupted_question_batches().await?;
+    if interrupted > 0 {
+        tracing::warn!("Marked {} interrupted question batches as failed", interrupted);
+    }
     
     // Build our application with routes
     let app = create_app().with_state(app_state);
@@ -44,6 +54,36 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     Ok(())
 }
 
+/// `moon_reader rotate-secret-key`: re-encrypt every stored API key under a new random key
+async fn rotate_secret_key(db: DatabaseManager, key_source: SecretKeySource) -> Result<(), Box<dyn std::error::Error>> {
+    let new_key = SecretCipher::generate_key();
+    let new_cipher = SecretCipher::from_base64(&new_key)?;
+    
+    match key_source {
+        SecretKeySource::File(path) => {
+            // The new key is on disk before any row depends on it
+            let mut pending = path.clone().into_os_string();
+            pending.push(".new");
+            secrets::write_key_file(pending.as_ref(), &new_key)?;
+            
+            let rotated = db.rotate_secret_key(&new_cipher).await?;
+            std::fs::rename(&pending, &path)?;
+            println!("Rotated {} API keys; the new key is in {}", rotated, path.display());
+        }
+        SecretKeySource::Env => {
+            let rotated = db.rotate_secret_key(&new_cipher).await?;
+            println!(
+                "Rotated {} API keys. Set {} to the new key before restarting:\n{}",
+                rotated,
+                secrets::SECRET_KEY_ENV,
+                new_key
+            );
+        }
+    }
+    
+    Ok(())
+}
+
 fn create_app() -> Router<AppState> {
     Router::new()
         // Knowledge base routes
@@ -59,12 +99,42 @@ fn create_app() -> Router<AppState> {
                delete(delete_document))
         .route("/api/documents/:id/content", 
                get(get_document_content))
+        .route("/api/documents/:id/coverage", 
+               get(get_document_coverage))
+        
+        // Retrieval routes
+        .route("/api/knowledge-bases/:id/search", 
+               get(search_knowledge_base))
         
         // AI quiz routes
         .route("/api/knowledge-bases/:id/generate-question", 
                post(generate_question))
+        .route("/api/knowledge-bases/:id/generate-question/stream", 
+               post(generate_question_stream))
         .route("/api/questions/:id/answer", 
                post(submit_answer))
+        .route("/api/questions/:id/answer/stream", 
+               post(submit_answer_stream))
+        .route("/api/questions/:id/source", 
+               get(get_question_source))
+        
+        // Question bank routes
+        .route("/api/knowledge-bases/:id/question-bank", 
+               get(list_question_bank))
+        .route("/api/knowledge-bases/:id/question-bank/batches", 
+               get(list_question_batches).post(start_question_batch))
+        .route("/api/knowledge-bases/:id/question-bank/approve", 
+               post(approve_bank_questions))
+        .route("/api/knowledge-bases/:id/question-bank/next", 
+               get(next_bank_question))
+        .route("/api/question-batches/:id", 
+               get(get_question_batch))
+        .route("/api/question-bank/:id", 
+               put(update_bank_question).delete(delete_bank_question))
+        
+        // Tutoring routes
+        .route("/api/answers/:id/messages", 
+               get(get_answer_thread).post(send_answer_message))
         
         // Review routes
         .route("/api/knowledge-bases/:id/review/random", 
@@ -89,6 +159,36 @@ fn create_app() -> Router<AppState> {
                get(get_ai_config).post(save_ai_config))
         .route("/api/ai-config/test", 
                post(test_ai_connection))
+        .route("/api/ai-config/models", 
+               get(list_ai_models))
+        
+        // AI profile routes
+        .route("/api/ai-profiles", 
+               get(list_ai_profiles).post(create_ai_profile))
+        .route("/api/ai-profiles/:id", 
+               get(get_ai_profile).put(update_ai_profile).delete(delete_ai_profile))
+        .route("/api/ai-profiles/:id/test", 
+               post(test_ai_profile))
+        .route("/api/ai-profile-selection", 
+               get(get_ai_profile_selection).put(update_ai_profile_selection))
+        .route("/api/knowledge-bases/:id/ai-profiles", 
+               get(get_knowledge_base_ai_profiles).put(update_knowledge_base_ai_profiles))
+        
+        // AI usage and cost routes
+        .route("/api/ai-usage", 
+               get(get_ai_usage))
+        .route("/api/ai-usage/budgets", 
+               get(get_ai_budgets))
+        .route("/api/ai-prices", 
+               get(list_ai_model_prices).put(update_ai_model_prices))
+        
+        // Prompt template routes
+        .route("/api/prompt-templates", 
+               get(list_prompt_templates).post(create_prompt_template))
+        .route("/api/prompt-templates/:id", 
+               get(get_prompt_template).put(update_prompt_template).delete(delete_prompt_template))
+        .route("/api/prompt-templates/:id/render", 
+               post(render_prompt_template))
         
         // Add CORS layer
         .layer(CorsLayer::permissive())
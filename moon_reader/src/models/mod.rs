@@ -34,6 +34,12 @@ pub struct KnowledgeBase {
     pub name: String,
     #[validate(length(max = 1000, message = "Description too long"))]
     pub description: Option<String>,
+    /// Language code for questions and feedback; `None` detects it from the documents
+    pub language: Option<String>,
+    /// Criterion weights as `RubricWeights` JSON; `None` uses the default rubric
+    pub rubric_weights: Option<String>,
+    /// Have the model write a reference answer and key points for each generated open question
+    pub reference_answers: bool,
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
 }
@@ -45,10 +51,21 @@ impl KnowledgeBase {
             id: Uuid::new_v4().to_string(),
             name,
             description,
+            language: None,
+            rubric_weights: None,
+            reference_answers: true,
             created_at: now,
             updated_at: now,
         }
     }
+    
+    /// Weights answers in this knowledge base are scored with
+    pub fn rubric(&self) -> RubricWeights {
+        self.rubric_weights
+            .as_deref()
+            .and_then(|weights| serde_json::from_str(weights).ok())
+            .unwrap_or_default()
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, FromRow, Validate)]
@@ -108,6 +125,60 @@ impl std::fmt::Display for DocumentType {
     }
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct DocumentChunk {
+    pub id: String,
+    pub document_id: String,
+    pub knowledge_base_id: String,
+    pub chunk_index: i32,
+    pub content: String,
+    // Character offsets into the parent document's content_text
+    pub start_offset: i64,
+    pub end_offset: i64,
+    // 1-based page the chunk starts on, for paginated formats such as PDF
+    pub page_number: Option<i32>,
+    pub created_at: DateTime<Utc>,
+}
+
+impl DocumentChunk {
+    pub fn new(
+        document_id: String,
+        knowledge_base_id: String,
+        chunk_index: i32,
+        content: String,
+        start_offset: i64,
+        end_offset: i64,
+    ) -> Self {
+        Self {
+            id: Uuid::new_v4().to_string(),
+            document_id,
+            knowledge_base_id,
+            chunk_index,
+            content,
+            start_offset,
+            end_offset,
+            page_number: None,
+            created_at: Utc::now(),
+        }
+    }
+}
+
+/// Quiz activity aggregated over a single chunk
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct ChunkCoverage {
+    pub chunk_id: String,
+    pub document_id: String,
+    pub chunk_index: i32,
+    pub page_number: Option<i32>,
+    pub start_offset: i64,
+    pub end_offset: i64,
+    // Leading characters of the chunk, to identify the section
+    pub preview: String,
+    pub question_count: i64,
+    pub answer_count: i64,
+    pub average_score: Option<f64>,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize, FromRow, Validate)]
 pub struct Question {
     pub id: String,
@@ -117,6 +188,31 @@ pub struct Question {
     #[validate(length(max = 1000, message = "Context snippet too long"))]
     pub context_snippet: Option<String>,
     pub generated_at: DateTime<Utc>,
+    // Source passage the question was generated from
+    pub document_id: Option<String>,
+    pub chunk_id: Option<String>,
+    pub page_number: Option<i32>,
+    /// Provider that generated the question, e.g. `ollama/llama3.1`
+    pub served_by: Option<String>,
+    pub question_type: QuestionType,
+    /// Answer key of a structured question as `QuestionPayload` JSON; never serialized,
+    /// so listing a question does not give its answer away
+    #[serde(skip_serializing)]
+    pub payload: Option<String>,
+    /// Difficulty the question was generated for, 1 (easiest) to 5; `None` when untargeted
+    #[validate(range(min = 1, max = 5, message = "Difficulty must be between 1 and 5"))]
+    pub difficulty: Option<i32>,
+    pub cognitive_level: Option<CognitiveLevel>,
+    /// Drafts from the question bank can't be answered until approved
+    pub status: QuestionStatus,
+    /// Batch job that generated the question into the question bank
+    pub batch_id: Option<String>,
+    /// Model answer revealed after answering; never serialized, like the answer key
+    #[serde(skip_serializing)]
+    pub reference_answer: Option<String>,
+    /// Points a good answer covers, as a JSON array; answers are graded against them
+    #[serde(skip_serializing)]
+    pub key_points: Option<String>,
 }
 
 impl Question {
@@ -131,6 +227,223 @@ impl Question {
             question_text,
             context_snippet,
             generated_at: Utc::now(),
+            document_id: None,
+            chunk_id: None,
+            page_number: None,
+            served_by: None,
+            question_type: QuestionType::Open,
+            payload: None,
+            difficulty: None,
+            cognitive_level: None,
+            status: QuestionStatus::Approved,
+            batch_id: None,
+            reference_answer: None,
+            key_points: None,
+        }
+    }
+    
+    /// Make this a structured question with the given answer key
+    pub fn with_payload(mut self, payload: &QuestionPayload) -> Self {
+        self.question_type = payload.question_type();
+        self.payload = serde_json::to_string(payload).ok();
+        self
+    }
+    
+    /// The answer key of a structured question
+    pub fn answer_key(&self) -> Option<QuestionPayload> {
+        self.payload.as_deref().and_then(|payload| serde_json::from_str(payload).ok())
+    }
+    
+    /// Store the reference answer and key points answers are graded against
+    pub fn with_reference(mut self, reference: &ReferenceAnswer) -> Self {
+        self.reference_answer = Some(reference.answer.clone());
+        self.key_points = if reference.key_points.is_empty() {
+            None
+        } else {
+            serde_json::to_string(&reference.key_points).ok()
+        };
+        self
+    }
+    
+    /// The stored reference answer and key points, if the question has one
+    pub fn reference(&self) -> Option<ReferenceAnswer> {
+        Some(ReferenceAnswer {
+            answer: self.reference_answer.clone()?,
+            key_points: self
+                .key_points
+                .as_deref()
+                .and_then(|points| serde_json::from_str(points).ok())
+                .unwrap_or_default(),
+        })
+    }
+    
+    /// Record the chunk the question was generated from
+    pub fn with_source(mut self, chunk: &DocumentChunk) -> Self {
+        self.document_id = Some(chunk.document_id.clone());
+        self.chunk_id = Some(chunk.id.clone());
+        self.page_number = chunk.page_number;
+        self
+    }
+}
+
+/// Form of a question, which decides how it is generated and graded
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, Type)]
+#[sqlx(type_name = "TEXT", rename_all = "snake_case")]
+#[serde(rename_all = "snake_case")]
+pub enum QuestionType {
+    /// Free-text question graded by the AI
+    #[default]
+    Open,
+    MultipleChoice,
+    TrueFalse,
+    Cloze,
+    ShortAnswer,
+}
+
+impl std::fmt::Display for QuestionType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            QuestionType::Open => write!(f, "open"),
+            QuestionType::MultipleChoice => write!(f, "multiple_choice"),
+            QuestionType::TrueFalse => write!(f, "true_false"),
+            QuestionType::Cloze => write!(f, "cloze"),
+            QuestionType::ShortAnswer => write!(f, "short_answer"),
+        }
+    }
+}
+
+/// Review state of a question in the question bank
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, Type)]
+#[sqlx(type_name = "TEXT", rename_all = "snake_case")]
+#[serde(rename_all = "snake_case")]
+pub enum QuestionStatus {
+    Draft,
+    #[default]
+    Approved,
+}
+
+/// Progress of a batch job generating questions into the question bank
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Type)]
+#[sqlx(type_name = "TEXT", rename_all = "snake_case")]
+#[serde(rename_all = "snake_case")]
+pub enum BatchStatus {
+    Running,
+    Completed,
+    Failed,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct QuestionBatch {
+    pub id: String,
+    pub knowledge_base_id: String,
+    pub status: BatchStatus,
+    /// Number of questions asked for
+    pub requested: i32,
+    /// Questions saved to the bank so far
+    pub generated: i32,
+    /// Generated questions dropped as near-identical to one already in the knowledge base
+    pub duplicates: i32,
+    /// Generation attempts that failed
+    pub failures: i32,
+    pub question_type: QuestionType,
+    pub difficulty: Option<i32>,
+    pub cognitive_level: Option<CognitiveLevel>,
+    /// Restricts generation to a single document
+    pub document_id: Option<String>,
+    pub error: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub finished_at: Option<DateTime<Utc>>,
+}
+
+impl QuestionBatch {
+    pub fn new(knowledge_base_id: String, requested: i32) -> Self {
+        Self {
+            id: Uuid::new_v4().to_string(),
+            knowledge_base_id,
+            status: BatchStatus::Running,
+            requested,
+            generated: 0,
+            duplicates: 0,
+            failures: 0,
+            question_type: QuestionType::Open,
+            difficulty: None,
+            cognitive_level: None,
+            document_id: None,
+            error: None,
+            created_at: Utc::now(),
+            finished_at: None,
+        }
+    }
+
+    /// Record how the job ended
+    pub fn finish(&mut self, error: Option<String>) {
+        self.status = if error.is_some() { BatchStatus::Failed } else { BatchStatus::Completed };
+        self.error = error;
+        self.finished_at = Some(Utc::now());
+    }
+}
+
+/// Level of Bloom's taxonomy a question exercises, from remembering to analysing
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Type)]
+#[sqlx(type_name = "TEXT", rename_all = "snake_case")]
+#[serde(rename_all = "snake_case")]
+pub enum CognitiveLevel {
+    Recall,
+    Understand,
+    Apply,
+    Analyze,
+}
+
+impl std::fmt::Display for CognitiveLevel {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CognitiveLevel::Recall => write!(f, "recall"),
+            CognitiveLevel::Understand => write!(f, "understand"),
+            CognitiveLevel::Apply => write!(f, "apply"),
+            CognitiveLevel::Analyze => write!(f, "analyze"),
+        }
+    }
+}
+
+/// Model answer to a question and the points a good answer covers
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ReferenceAnswer {
+    pub answer: String,
+    #[serde(default)]
+    pub key_points: Vec<String>,
+}
+
+/// Options and correct answers of a structured question
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum QuestionPayload {
+    MultipleChoice {
+        options: Vec<String>,
+        /// 0-based indexes into `options`; more than one makes it a multiple-select question
+        correct: Vec<usize>,
+    },
+    TrueFalse {
+        answer: bool,
+    },
+    /// The question text shows each deletion as `____`, in the order of `answers`
+    Cloze {
+        answers: Vec<String>,
+    },
+    ShortAnswer {
+        answer: String,
+        /// Other wordings that are equally correct
+        #[serde(default)]
+        accepted: Vec<String>,
+    },
+}
+
+impl QuestionPayload {
+    pub fn question_type(&self) -> QuestionType {
+        match self {
+            QuestionPayload::MultipleChoice { .. } => QuestionType::MultipleChoice,
+            QuestionPayload::TrueFalse { .. } => QuestionType::TrueFalse,
+            QuestionPayload::Cloze { .. } => QuestionType::Cloze,
+            QuestionPayload::ShortAnswer { .. } => QuestionType::ShortAnswer,
         }
     }
 }
@@ -148,6 +461,18 @@ pub struct Answer {
     #[validate(length(max = 2000, message = "Suggestions too long"))]
     pub ai_suggestions: Option<String>,
     pub answered_at: DateTime<Utc>,
+    /// JSON array of the passages the answer was evaluated against
+    pub evidence: Option<String>,
+    /// Provider that evaluated the answer, e.g. `deepseek/deepseek-chat`
+    pub served_by: Option<String>,
+    // Per-criterion scores, 0-100; `None` for answers graded without a rubric
+    pub accuracy_score: Option<i32>,
+    pub completeness_score: Option<i32>,
+    pub depth_score: Option<i32>,
+    pub clarity_score: Option<i32>,
+    /// JSON arrays of the question's key points the answer covered and missed
+    pub matched_key_points: Option<String>,
+    pub missed_key_points: Option<String>,
 }
 
 impl Answer {
@@ -160,8 +485,172 @@ impl Answer {
             ai_feedback: None,
             ai_suggestions: None,
             answered_at: Utc::now(),
+            evidence: None,
+            served_by: None,
+            accuracy_score: None,
+            completeness_score: None,
+            depth_score: None,
+            clarity_score: None,
+            matched_key_points: None,
+            missed_key_points: None,
+        }
+    }
+    
+    /// Record the per-criterion scores of a rubric evaluation
+    pub fn with_criteria(mut self, criteria: &CriterionScores) -> Self {
+        self.accuracy_score = Some(i32::from(criteria.accuracy));
+        self.completeness_score = Some(i32::from(criteria.completeness));
+        self.depth_score = Some(i32::from(criteria.depth));
+        self.clarity_score = Some(i32::from(criteria.clarity));
+        self
+    }
+    
+    /// The per-criterion scores, if the answer was graded with a rubric
+    pub fn criteria(&self) -> Option<CriterionScores> {
+        let score = |value: Option<i32>| value.and_then(|value| u8::try_from(value).ok());
+        Some(CriterionScores {
+            accuracy: score(self.accuracy_score)?,
+            completeness: score(self.completeness_score)?,
+            depth: score(self.depth_score)?,
+            clarity: score(self.clarity_score)?,
+        })
+    }
+}
+
+/// Who wrote a message in an answer's tutoring thread
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Type)]
+#[sqlx(type_name = "TEXT", rename_all = "snake_case")]
+#[serde(rename_all = "snake_case")]
+pub enum ThreadRole {
+    Learner,
+    Tutor,
+}
+
+/// A message in the follow-up conversation about an answer and its feedback
+#[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
+pub struct AnswerMessage {
+    pub id: String,
+    pub answer_id: String,
+    pub role: ThreadRole,
+    pub content: String,
+    /// Provider that wrote a tutor reply, e.g. `deepseek/deepseek-chat`
+    pub served_by: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+impl AnswerMessage {
+    pub fn new(answer_id: String, role: ThreadRole, content: String) -> Self {
+        Self {
+            id: Uuid::new_v4().to_string(),
+            answer_id,
+            role,
+            content,
+            served_by: None,
+            created_at: Utc::now(),
+        }
+    }
+}
+
+/// Dimension of an answer that is scored on its own
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Criterion {
+    Accuracy,
+    Completeness,
+    Depth,
+    Clarity,
+}
+
+impl Criterion {
+    pub const ALL: [Criterion; 4] = [Criterion::Accuracy, Criterion::Completeness, Criterion::Depth, Criterion::Clarity];
+}
+
+impl std::fmt::Display for Criterion {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Criterion::Accuracy => write!(f, "accuracy"),
+            Criterion::Completeness => write!(f, "completeness"),
+            Criterion::Depth => write!(f, "depth"),
+            Criterion::Clarity => write!(f, "clarity"),
+        }
+    }
+}
+
+/// Scores of an answer on each criterion, 0-100
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CriterionScores {
+    pub accuracy: u8,
+    pub completeness: u8,
+    pub depth: u8,
+    pub clarity: u8,
+}
+
+impl CriterionScores {
+    pub fn get(&self, criterion: Criterion) -> u8 {
+        match criterion {
+            Criterion::Accuracy => self.accuracy,
+            Criterion::Completeness => self.completeness,
+            Criterion::Depth => self.depth,
+            Criterion::Clarity => self.clarity,
         }
     }
+    
+    /// Overall score: the average of the criteria under the given weights
+    pub fn weighted_score(&self, weights: &RubricWeights) -> u8 {
+        let total: f64 = Criterion::ALL.iter().map(|&c| weights.get(c)).sum();
+        if total <= 0.0 {
+            return 0;
+        }
+        let sum: f64 = Criterion::ALL.iter().map(|&c| f64::from(self.get(c)) * weights.get(c)).sum();
+        (sum / total).round().clamp(0.0, 100.0) as u8
+    }
+}
+
+/// Relative weight of each criterion in an answer's overall score; only the ratios matter
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, Validate)]
+#[validate(schema(function = "validate_rubric_weights"))]
+pub struct RubricWeights {
+    #[validate(range(min = 0.0, message = "Weights must not be negative"))]
+    pub accuracy: f64,
+    #[validate(range(min = 0.0, message = "Weights must not be negative"))]
+    pub completeness: f64,
+    #[validate(range(min = 0.0, message = "Weights must not be negative"))]
+    pub depth: f64,
+    #[validate(range(min = 0.0, message = "Weights must not be negative"))]
+    pub clarity: f64,
+}
+
+impl RubricWeights {
+    pub fn get(&self, criterion: Criterion) -> f64 {
+        match criterion {
+            Criterion::Accuracy => self.accuracy,
+            Criterion::Completeness => self.completeness,
+            Criterion::Depth => self.depth,
+            Criterion::Clarity => self.clarity,
+        }
+    }
+}
+
+impl Default for RubricWeights {
+    /// Accuracy counts most, clarity least
+    fn default() -> Self {
+        Self {
+            accuracy: 0.4,
+            completeness: 0.3,
+            depth: 0.2,
+            clarity: 0.1,
+        }
+    }
+}
+
+fn validate_rubric_weights(weights: &RubricWeights) -> Result<(), ValidationError> {
+    if Criterion::ALL.iter().map(|&c| weights.get(c)).sum::<f64>() > 0.0 {
+        Ok(())
+    } else {
+        let mut error = ValidationError::new("rubric_weights");
+        error.message = Some("At least one weight must be positive".into());
+        Err(error)
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, FromRow, Validate)]
@@ -190,6 +679,10 @@ impl ReviewSession {
 #[derive(Debug, Clone, Serialize, Deserialize, FromRow, Validate)]
 pub struct AIConfig {
     pub id: Option<i32>,
+    /// Unique profile name
+    pub name: String,
+    /// The profile used when no other is selected for a task
+    pub is_default: bool,
     pub provider: AIProvider,
     #[validate(length(max = 500, message = "API key too long"))]
     pub api_key: Option<String>,
@@ -201,6 +694,20 @@ pub struct AIConfig {
     pub max_tokens: i32,
     #[validate(range(min = 0.0, max = 2.0, message = "Temperature must be between 0.0 and 2.0"))]
     pub temperature: f64,
+    /// JSON object of provider-specific model options, e.g. `{"num_ctx": 8192}` for Ollama
+    pub provider_options: Option<String>,
+    // Request limits; `None` uses the built-in defaults
+    #[validate(range(min = 1, max = 300, message = "Connect timeout must be between 1 and 300 seconds"))]
+    pub connect_timeout_secs: Option<i32>,
+    #[validate(range(min = 1, max = 1800, message = "Request timeout must be between 1 and 1800 seconds"))]
+    pub request_timeout_secs: Option<i32>,
+    #[validate(range(min = 0, max = 10, message = "Max retries must be between 0 and 10"))]
+    pub max_retries: Option<i32>,
+    /// Profiles tried in order when this profile's service is unavailable
+    pub fallback_profile_ids: Vec<i32>,
+    /// Spending limit per calendar month, in the currency of the model prices; `None` is unlimited
+    #[validate(range(min = 0.0, message = "Monthly budget cannot be negative"))]
+    pub monthly_budget: Option<f64>,
     pub updated_at: DateTime<Utc>,
 }
 
@@ -215,15 +722,116 @@ impl AIConfig {
     ) -> Self {
         Self {
             id: None,
+            name: "Default".to_string(),
+            is_default: false,
             provider,
             api_key,
             api_url,
             model_name,
             max_tokens,
             temperature,
+            provider_options: None,
+            connect_timeout_secs: None,
+            request_timeout_secs: None,
+            max_retries: None,
+            fallback_profile_ids: Vec::new(),
+            monthly_budget: None,
             updated_at: Utc::now(),
         }
     }
+    
+    /// How answers and questions record this profile's provider: the provider, plus the model if set
+    pub fn served_by(&self) -> String {
+        match &self.model_name {
+            Some(model) if !model.trim().is_empty() => format!("{}/{}", self.provider, model),
+            _ => self.provider.to_string(),
+        }
+    }
+}
+
+/// Tokens used by one AI call, attributed to the profile and model that served it
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AIUsage {
+    pub id: Option<i64>,
+    pub profile_id: Option<i32>,
+    pub provider: String,
+    /// The profile's model name, empty for the provider's default model
+    pub model: String,
+    pub task: PromptKind,
+    pub knowledge_base_id: Option<String>,
+    pub question_id: Option<String>,
+    pub answer_id: Option<String>,
+    pub prompt_tokens: i64,
+    pub completion_tokens: i64,
+    pub created_at: DateTime<Utc>,
+}
+
+impl AIUsage {
+    pub fn new(profile: &AIConfig, task: PromptKind, prompt_tokens: u32, completion_tokens: u32) -> Self {
+        Self {
+            id: None,
+            profile_id: profile.id,
+            provider: profile.provider.to_string(),
+            model: profile.model_name.clone().unwrap_or_default(),
+            task,
+            knowledge_base_id: None,
+            question_id: None,
+            answer_id: None,
+            prompt_tokens: i64::from(prompt_tokens),
+            completion_tokens: i64::from(completion_tokens),
+            created_at: Utc::now(),
+        }
+    }
+}
+
+/// Price per million tokens of a provider's model; an empty model prices the provider's other models
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Validate)]
+pub struct AIModelPrice {
+    #[validate(length(min = 1, max = 50, message = "Provider must be between 1 and 50 characters"))]
+    pub provider: String,
+    #[serde(default)]
+    #[validate(length(max = 100, message = "Model name too long"))]
+    pub model: String,
+    #[validate(range(min = 0.0, message = "Prices cannot be negative"))]
+    pub prompt_price: f64,
+    #[validate(range(min = 0.0, message = "Prices cannot be negative"))]
+    pub completion_price: f64,
+}
+
+/// How AI usage is aggregated
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum UsageGrouping {
+    #[default]
+    Day,
+    KnowledgeBase,
+    Provider,
+}
+
+/// Usage and cost of the AI calls sharing one day, knowledge base or provider
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AIUsageSummary {
+    pub key: String,
+    pub calls: i64,
+    pub prompt_tokens: i64,
+    pub completion_tokens: i64,
+    pub cost: f64,
+}
+
+/// AI profiles chosen per task, globally or for one knowledge base; `None` falls back to the next level
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct AIProfileSelection {
+    pub question_profile_id: Option<i32>,
+    pub evaluation_profile_id: Option<i32>,
+}
+
+impl AIProfileSelection {
+    pub fn profile_for(&self, task: PromptKind) -> Option<i32> {
+        match task {
+            PromptKind::Question => self.question_profile_id,
+            PromptKind::Evaluation => self.evaluation_profile_id,
+        }
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, Type)]
@@ -235,6 +843,13 @@ pub enum AIProvider {
     Local,
     #[serde(rename = "openai")]
     OpenAI,
+    #[serde(rename = "anthropic")]
+    Anthropic,
+    #[serde(rename = "ollama")]
+    Ollama,
+    /// Built-in offline provider with scripted replies
+    #[serde(rename = "mock")]
+    Mock,
 }
 
 impl std::fmt::Display for AIProvider {
@@ -243,6 +858,55 @@ impl std::fmt::Display for AIProvider {
             AIProvider::DeepSeek => write!(f, "deepseek"),
             AIProvider::Local => write!(f, "local"),
             AIProvider::OpenAI => write!(f, "openai"),
+            AIProvider::Anthropic => write!(f, "anthropic"),
+            AIProvider::Ollama => write!(f, "ollama"),
+            AIProvider::Mock => write!(f, "mock"),
+        }
+    }
+}
+
+/// A named, editable prompt with `{{variable}}` placeholders
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PromptTemplate {
+    pub id: String,
+    pub name: String,
+    pub kind: PromptKind,
+    pub system_prompt: Option<String>,
+    pub user_prompt: String,
+    /// The template used for its kind when generating or evaluating
+    pub is_default: bool,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+impl PromptTemplate {
+    pub fn new(name: String, kind: PromptKind, system_prompt: Option<String>, user_prompt: String) -> Self {
+        let now = Utc::now();
+        Self {
+            id: Uuid::new_v4().to_string(),
+            name,
+            kind,
+            system_prompt,
+            user_prompt,
+            is_default: false,
+            created_at: now,
+            updated_at: now,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PromptKind {
+    Question,
+    Evaluation,
+}
+
+impl std::fmt::Display for PromptKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PromptKind::Question => write!(f, "question"),
+            PromptKind::Evaluation => write!(f, "evaluation"),
         }
     }
 }
@@ -254,4 +918,50 @@ pub struct LearningProgress {
     pub recent_average_score: Option<f64>,
     pub improvement_trend: Option<String>,
     pub total_review_sessions: i32,
+    /// Average score per criterion over the answers graded with a rubric
+    pub criterion_averages: Option<CriterionAverages>,
+    /// The criterion with the lowest average, where the learner should focus
+    pub weakest_criterion: Option<Criterion>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct CriterionAverages {
+    pub accuracy: f64,
+    pub completeness: f64,
+    pub depth: f64,
+    pub clarity: f64,
+}
+
+impl CriterionAverages {
+    /// Averages over `scores`, or `None` when there are none
+    pub fn of(scores: &[CriterionScores]) -> Option<Self> {
+        if scores.is_empty() {
+            return None;
+        }
+        let average = |criterion: Criterion| {
+            scores.iter().map(|s| f64::from(s.get(criterion))).sum::<f64>() / scores.len() as f64
+        };
+        Some(Self {
+            accuracy: average(Criterion::Accuracy),
+            completeness: average(Criterion::Completeness),
+            depth: average(Criterion::Depth),
+            clarity: average(Criterion::Clarity),
+        })
+    }
+    
+    pub fn get(&self, criterion: Criterion) -> f64 {
+        match criterion {
+            Criterion::Accuracy => self.accuracy,
+            Criterion::Completeness => self.completeness,
+            Criterion::Depth => self.depth,
+            Criterion::Clarity => self.clarity,
+        }
+    }
+    
+    /// The lowest-scoring criterion; ties go to the one listed first
+    pub fn weakest(&self) -> Criterion {
+        Criterion::ALL
+            .into_iter()
+            .fold(Criterion::Accuracy, |weakest, c| if self.get(c) < self.get(weakest) { c } else { weakest })
+    }
 }
\ No newline at end of file
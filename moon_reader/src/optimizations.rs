@@ -151,7 +151,7 @@ pub struct QueryOptimizer;
 impl QueryOptimizer {
     /// Generate optimized SQL for knowledge base listing with pagination
     pub fn optimized_knowledge_bases_query(limit: Option<i32>, offset: Option<i32>) -> String {
-        let base_query = "SELECT id, name, description, created_at, updated_at FROM knowledge_bases ORDER BY created_at DESC";
+        let base_query = "SELECT id, name, description, language, created_at, updated_at FROM knowledge_bases ORDER BY created_at DESC";
         
         match (limit, offset) {
             (Some(l), Some(o)) => format!("{} LIMIT {} OFFSET {}", base_query, l, o),
@@ -283,17 +283,17 @@ impl FileProcessor {
         let truncated = &content[..max_length];
         
         // Try to break at sentence end
-        if let Some(pos) = truncated.rfind('.') {
-            if pos > max_length / 2 {
-                return format!("{}.", &truncated[..pos]);
-            }
+        if let Some(pos) = truncated.rfind('.')
+            && pos > max_length / 2
+        {
+            return format!("{}.", &truncated[..pos]);
         }
         
         // Try to break at word boundary
-        if let Some(pos) = truncated.rfind(' ') {
-            if pos > max_length / 2 {
-                return format!("{}...", &truncated[..pos]);
-            }
+        if let Some(pos) = truncated.rfind(' ')
+            && pos > max_length / 2
+        {
+            return format!("{}...", &truncated[..pos]);
         }
         
         // Fallback to hard truncation
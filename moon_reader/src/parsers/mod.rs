@@ -1,6 +1,9 @@
 use std::path::Path;
 use thiserror::Error;
 
+/// Separator inserted between pages of paginated documents (form feed)
+pub const PAGE_BREAK: char = '\u{000C}';
+
 #[derive(Debug, Error)]
 pub enum ParseError {
     #[error("IO error: {0}")]
@@ -42,8 +45,10 @@ impl DocumentParser {
             let path = file_path.to_owned();
             move || -> Result<String, ParseError> {
                 let bytes = std::fs::read(&path)?;
-                pdf_extract::extract_text_from_mem(&bytes)
-                    .map_err(|e| ParseError::Pdf(e.to_string()))
+                // Keep page boundaries so passages can be cited by page
+                let pages = pdf_extract::extract_text_from_mem_by_pages(&bytes)
+                    .map_err(|e| ParseError::Pdf(e.to_string()))?;
+                Ok(pages.join(&PAGE_BREAK.to_string()))
             }
         })
         .await
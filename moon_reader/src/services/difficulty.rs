@@ -0,0 +1,97 @@
+use serde::{Deserialize, Serialize};
+
+use crate::models::CognitiveLevel;
+
+pub const MIN_DIFFICULTY: u8 = 1;
+pub const MAX_DIFFICULTY: u8 = 5;
+/// Where adaptive mode starts when no answered question has a difficulty yet
+pub const DEFAULT_DIFFICULTY: u8 = 3;
+
+// Recent average scores that move adaptive difficulty up or down a step
+const RAISE_AT: f64 = 80.0;
+const LOWER_BELOW: f64 = 50.0;
+
+// What the built-in templates asked for before questions could be targeted
+const UNTARGETED_GUIDANCE: &str = "The question should: 1) Test understanding of core concepts 2) Require comprehensive thinking 3) Avoid simple factual questions.";
+
+/// Difficulty and cognitive level a question is generated for
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct QuestionTarget {
+    pub difficulty: Option<u8>,
+    pub cognitive_level: Option<CognitiveLevel>,
+}
+
+impl QuestionTarget {
+    /// Instructions substituted for `{{difficulty}}` in question templates
+    pub fn guidance(&self) -> String {
+        let mut guidance = Vec::new();
+
+        if let Some(difficulty) = self.difficulty {
+            let (label, requirement) = match difficulty {
+                1 => ("very easy", "a single fact stated plainly in the material"),
+                2 => ("easy", "a main point stated directly in the material"),
+                3 => ("moderate", "connecting two or more points of the material"),
+                4 => ("hard", "reasoning beyond what the material states explicitly"),
+                _ => ("very hard", "combining several ideas, with subtle distinctions or edge cases"),
+            };
+            guidance.push(format!(
+                "Target difficulty {} of {} ({}): answering should require {}.",
+                difficulty, MAX_DIFFICULTY, label, requirement
+            ));
+        }
+
+        if let Some(level) = self.cognitive_level {
+            let task = match level {
+                CognitiveLevel::Recall => "remember facts, terms or definitions as the material states them",
+                CognitiveLevel::Understand => "explain or paraphrase an idea in their own words",
+                CognitiveLevel::Apply => "use an idea from the material in a new, concrete situation",
+                CognitiveLevel::Analyze => "break the material into parts and examine how they relate, compare or cause each other",
+            };
+            guidance.push(format!("Target cognitive level \"{}\": the learner must {}.", level, task));
+        }
+
+        if guidance.is_empty() {
+            UNTARGETED_GUIDANCE.to_string()
+        } else {
+            guidance.join(" ")
+        }
+    }
+}
+
+/// Move one step up after strong recent answers and one step down after weak ones
+pub fn adapt_difficulty(current: u8, recent_average: Option<f64>) -> u8 {
+    let current = current.clamp(MIN_DIFFICULTY, MAX_DIFFICULTY);
+    match recent_average {
+        Some(average) if average >= RAISE_AT => (current + 1).min(MAX_DIFFICULTY),
+        Some(average) if average < LOWER_BELOW => (current - 1).max(MIN_DIFFICULTY),
+        _ => current,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_adapt_difficulty() {
+        assert_eq!(adapt_difficulty(3, Some(92.0)), 4);
+        assert_eq!(adapt_difficulty(3, Some(35.0)), 2);
+        assert_eq!(adapt_difficulty(3, Some(65.0)), 3);
+        assert_eq!(adapt_difficulty(3, None), 3);
+        assert_eq!(adapt_difficulty(5, Some(100.0)), 5);
+        assert_eq!(adapt_difficulty(1, Some(0.0)), 1);
+    }
+
+    #[test]
+    fn test_guidance() {
+        assert_eq!(QuestionTarget::default().guidance(), UNTARGETED_GUIDANCE);
+
+        let guidance = QuestionTarget {
+            difficulty: Some(2),
+            cognitive_level: Some(CognitiveLevel::Apply),
+        }
+        .guidance();
+        assert!(guidance.starts_with("Target difficulty 2 of 5 (easy)"));
+        assert!(guidance.contains("Target cognitive level \"apply\""));
+    }
+}
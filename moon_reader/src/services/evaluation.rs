@@ -0,0 +1,230 @@
+use serde_json::Value;
+use thiserror::Error;
+
+use super::ai::AIEvaluation;
+use crate::models::CriterionScores;
+
+#[derive(Debug, Error)]
+pub enum EvaluationParseError {
+    #[error("no JSON object found in the reply")]
+    NoJson,
+    #[error("field `{field}` {reason}")]
+    InvalidField { field: &'static str, reason: String },
+}
+
+/// Parse an evaluation from a model reply, tolerating code fences and surrounding prose
+pub fn parse_evaluation(reply: &str) -> Result<AIEvaluation, EvaluationParseError> {
+    let mut last_error = EvaluationParseError::NoJson;
+
+    // Models sometimes echo an example before the real result, so try every object
+    for object in json_objects(reply) {
+        match validate(&object) {
+            Ok(evaluation) => return Ok(evaluation),
+            Err(e) => last_error = e,
+        }
+    }
+
+    Err(last_error)
+}
+
+/// Follow-up prompt asking the model to restate an unparseable evaluation as JSON
+pub fn repair_prompt(reply: &str, error: &EvaluationParseError) -> String {
+    format!(
+        "The following answer evaluation could not be read ({}). Rewrite it as a single JSON object with exactly these fields: \"score\" (integer 0-100), \"feedback\" (string) and \"suggestions\" (array of strings), plus \"criteria\" (object with integer 0-100 \"accuracy\", \"completeness\", \"depth\" and \"clarity\"), \"matched_key_points\" and \"missed_key_points\" (arrays of strings) if the evaluation gives them. Return only the JSON object.\n\n{}",
+        error, reply
+    )
+}
+
+// Check the fields of a candidate evaluation object against the expected schema
+fn validate(object: &Value) -> Result<AIEvaluation, EvaluationParseError> {
+    let score = match object.get("score") {
+        Some(Value::Number(n)) => n.as_f64(),
+        Some(Value::String(s)) => s.trim().trim_end_matches("/100").trim().parse::<f64>().ok(),
+        _ => None,
+    }
+    .ok_or(EvaluationParseError::InvalidField {
+        field: "score",
+        reason: "must be a number".to_string(),
+    })?;
+
+    if !(0.0..=100.0).contains(&score) {
+        return Err(EvaluationParseError::InvalidField {
+            field: "score",
+            reason: format!("must be between 0 and 100, got {}", score),
+        });
+    }
+
+    let feedback = object
+        .get("feedback")
+        .and_then(Value::as_str)
+        .ok_or(EvaluationParseError::InvalidField {
+            field: "feedback",
+            reason: "must be a string".to_string(),
+        })?;
+
+    let suggestions = object
+        .get("suggestions")
+        .and_then(Value::as_array)
+        .and_then(|items| items.iter().map(|item| item.as_str().map(str::to_string)).collect::<Option<Vec<_>>>())
+        .ok_or(EvaluationParseError::InvalidField {
+            field: "suggestions",
+            reason: "must be an array of strings".to_string(),
+        })?;
+
+    Ok(AIEvaluation {
+        score: Some(score.round() as u8),
+        feedback: feedback.to_string(),
+        suggestions,
+        criteria: object.get("criteria").and_then(criteria),
+        matched_key_points: strings(object.get("matched_key_points")),
+        missed_key_points: strings(object.get("missed_key_points")),
+    })
+}
+
+// The rubric is optional, so a missing or malformed breakdown drops it rather than the evaluation
+fn criteria(value: &Value) -> Option<CriterionScores> {
+    let score = |name: &str| {
+        let score = value.get(name)?.as_f64()?;
+        (0.0..=100.0).contains(&score).then(|| score.round() as u8)
+    };
+
+    Some(CriterionScores {
+        accuracy: score("accuracy")?,
+        completeness: score("completeness")?,
+        depth: score("depth")?,
+        clarity: score("clarity")?,
+    })
+}
+
+fn strings(value: Option<&Value>) -> Vec<String> {
+    value
+        .and_then(Value::as_array)
+        .map(|items| {
+            items
+                .iter()
+                .filter_map(Value::as_str)
+                .map(str::trim)
+                .filter(|item| !item.is_empty())
+                .map(str::to_string)
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+// Every top-level JSON object embedded in `text`, in order of appearance
+pub(crate) fn json_objects(text: &str) -> Vec<Value> {
+    let mut objects = Vec::new();
+    let mut offset = 0;
+
+    while let Some(start) = text[offset..].find('{').map(|i| offset + i) {
+        match balanced_object_end(&text[start..]) {
+            Some(len) => match serde_json::from_str::<Value>(&text[start..start + len]) {
+                Ok(value) if value.is_object() => {
+                    objects.push(value);
+                    offset = start + len;
+                }
+                _ => offset = start + 1,
+            },
+            None => offset = start + 1,
+        }
+    }
+
+    objects
+}
+
+// Byte length of the brace-balanced span starting at `text[0] == '{'`, ignoring braces in strings
+fn balanced_object_end(text: &str) -> Option<usize> {
+    let mut depth = 0usize;
+    let mut in_string = false;
+    let mut escaped = false;
+
+    for (i, c) in text.char_indices() {
+        if in_string {
+            match c {
+                _ if escaped => escaped = false,
+                '\\' => escaped = true,
+                '"' => in_string = false,
+                _ => {}
+            }
+            continue;
+        }
+
+        match c {
+            '"' => in_string = true,
+            '{' => depth += 1,
+            '}' => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(i + 1);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_plain_json() {
+        let evaluation = parse_evaluation(r#"{"score": 85, "feedback": "Good", "suggestions": ["More detail"]}"#).unwrap();
+        assert_eq!(evaluation.score, Some(85));
+        assert_eq!(evaluation.feedback, "Good");
+        assert_eq!(evaluation.suggestions, vec!["More detail"]);
+    }
+
+    #[test]
+    fn test_parse_fenced_json_with_prose() {
+        let reply = "Here is my evaluation:\n```json\n{\n  \"score\": 72.6,\n  \"feedback\": \"Covers {most} points\",\n  \"suggestions\": []\n}\n```\nHope this helps!";
+        let evaluation = parse_evaluation(reply).unwrap();
+        assert_eq!(evaluation.score, Some(73));
+        assert_eq!(evaluation.feedback, "Covers {most} points");
+        assert!(evaluation.suggestions.is_empty());
+    }
+
+    #[test]
+    fn test_parse_skips_invalid_candidates() {
+        let reply = r#"Format: {"score": "<0-100>"}. Result: {"score": "90/100", "feedback": "Great", "suggestions": ["None"]}"#;
+        assert_eq!(parse_evaluation(reply).unwrap().score, Some(90));
+    }
+
+    #[test]
+    fn test_parse_rubric_breakdown() {
+        let reply = r#"{"score": 70, "feedback": "Partly", "suggestions": [],
+            "criteria": {"accuracy": 90, "completeness": 50.4, "depth": 60, "clarity": 80},
+            "matched_key_points": ["Each value has one owner"], "missed_key_points": ["Values drop at scope end", 4]}"#;
+        let evaluation = parse_evaluation(reply).unwrap();
+        let criteria = evaluation.criteria.unwrap();
+        assert_eq!((criteria.accuracy, criteria.completeness, criteria.depth, criteria.clarity), (90, 50, 60, 80));
+        assert_eq!(evaluation.matched_key_points, vec!["Each value has one owner"]);
+        assert_eq!(evaluation.missed_key_points, vec!["Values drop at scope end"]);
+
+        // An incomplete breakdown keeps the evaluation but drops the criteria
+        let reply = r#"{"score": 70, "feedback": "Partly", "suggestions": [], "criteria": {"accuracy": 90, "depth": 120}}"#;
+        let evaluation = parse_evaluation(reply).unwrap();
+        assert_eq!(evaluation.score, Some(70));
+        assert!(evaluation.criteria.is_none());
+        assert!(evaluation.matched_key_points.is_empty());
+    }
+
+    #[test]
+    fn test_parse_rejects_schema_violations() {
+        assert!(matches!(parse_evaluation("The answer is decent."), Err(EvaluationParseError::NoJson)));
+        assert!(matches!(
+            parse_evaluation(r#"{"score": 140, "feedback": "x", "suggestions": []}"#),
+            Err(EvaluationParseError::InvalidField { field: "score", .. })
+        ));
+        assert!(matches!(
+            parse_evaluation(r#"{"score": 50, "feedback": "x", "suggestions": "do more"}"#),
+            Err(EvaluationParseError::InvalidField { field: "suggestions", .. })
+        ));
+        assert!(matches!(
+            parse_evaluation(r#"{"score": 50, "suggestions": []}"#),
+            Err(EvaluationParseError::InvalidField { field: "feedback", .. })
+        ));
+    }
+}
@@ -0,0 +1,329 @@
+use std::future::Future;
+use std::sync::Arc;
+
+use tokio::sync::mpsc;
+
+use crate::models::{AIConfig, AIUsage, PromptKind, QuestionType, ReferenceAnswer};
+use crate::services::ai::{AIError, AIEvaluation, AIProvider, Metered, TokenSender};
+use crate::services::prompts::Prompt;
+use crate::services::question_types::GeneratedQuestion;
+
+/// A provider in a chain, with the profile it was built from
+#[derive(Clone)]
+pub struct ChainedProvider {
+    pub profile: AIConfig,
+    pub provider: Arc<dyn AIProvider>,
+}
+
+/// The result of an AI call, the provider that produced it and the tokens it used
+#[derive(Debug, Clone, PartialEq)]
+pub struct Served<T> {
+    pub value: T,
+    pub served_by: String,
+    pub usage: AIUsage,
+}
+
+impl<T> Served<T> {
+    pub fn map<U>(self, f: impl FnOnce(T) -> U) -> Served<U> {
+        Served {
+            value: f(self.value),
+            served_by: self.served_by,
+            usage: self.usage,
+        }
+    }
+}
+
+/// A profile's provider followed by its fallbacks, tried in order while the service is unavailable
+#[derive(Clone)]
+pub struct ProviderChain {
+    providers: Vec<ChainedProvider>,
+}
+
+impl ProviderChain {
+    pub fn new(providers: Vec<ChainedProvider>) -> Self {
+        Self { providers }
+    }
+
+    pub async fn generate_question(&self, prompt: &Prompt) -> Result<Served<String>, AIError> {
+        self.call(PromptKind::Question, |provider| async move { provider.generate_question(prompt).await })
+            .await
+    }
+
+    pub async fn generate_structured_question(
+        &self,
+        prompt: &Prompt,
+        question_type: QuestionType,
+    ) -> Result<Served<GeneratedQuestion>, AIError> {
+        self.call(PromptKind::Question, |provider| async move {
+            provider.generate_structured_question(prompt, question_type).await
+        })
+        .await
+    }
+
+    /// Reference answers are part of generating a question, so they use the question task's providers
+    pub async fn generate_reference_answer(&self, prompt: &Prompt) -> Result<Served<ReferenceAnswer>, AIError> {
+        self.call(PromptKind::Question, |provider| async move { provider.generate_reference_answer(prompt).await })
+            .await
+    }
+
+    pub async fn evaluate_answer(&self, prompt: &Prompt) -> Result<Served<AIEvaluation>, AIError> {
+        self.call(PromptKind::Evaluation, |provider| async move { provider.evaluate_answer(prompt).await })
+            .await
+    }
+
+    /// Tutoring follows up on an evaluation, so it uses the evaluation task's providers
+    pub async fn tutor_reply(&self, prompt: &Prompt) -> Result<Served<String>, AIError> {
+        self.call(PromptKind::Evaluation, |provider| async move { provider.tutor_reply(prompt).await })
+            .await
+    }
+
+    pub async fn stream_question(&self, prompt: &Prompt, tokens: TokenSender) -> Result<Served<String>, AIError> {
+        self.stream(PromptKind::Question, prompt, tokens, |provider, tokens| async move {
+            provider.stream_question(prompt, tokens).await
+        })
+        .await
+    }
+
+    pub async fn stream_evaluation(
+        &self,
+        prompt: &Prompt,
+        tokens: TokenSender,
+    ) -> Result<Served<AIEvaluation>, AIError> {
+        self.stream(PromptKind::Evaluation, prompt, tokens, |provider, tokens| async move {
+            provider.stream_evaluation(prompt, tokens).await
+        })
+        .await
+    }
+
+    // Run `request` against each provider until one answers; errors the next provider
+    // cannot fix (bad key, bad request, rate limit) are returned straight away
+    async fn call<T, F, Fut>(&self, task: PromptKind, request: F) -> Result<Served<T>, AIError>
+    where
+        F: Fn(Arc<dyn AIProvider>) -> Fut,
+        Fut: Future<Output = Result<Metered<T>, AIError>>,
+    {
+        let mut last_error = None;
+
+        for link in &self.providers {
+            match request(link.provider.clone()).await {
+                Ok(Metered { value, usage }) => {
+                    return Ok(Served {
+                        value,
+                        served_by: link.profile.served_by(),
+                        usage: AIUsage::new(&link.profile, task, usage.prompt_tokens, usage.completion_tokens),
+                    });
+                }
+                Err(e) if e.is_unavailable() => {
+                    tracing::warn!("AI provider {} unavailable, trying the next one: {}", link.profile.served_by(), e);
+                    last_error = Some(e);
+                }
+                Err(e) => return Err(e),
+            }
+        }
+
+        Err(last_error.unwrap_or_else(|| AIError::ConfigError("No AI provider in the chain".to_string())))
+    }
+
+    // Like `call`, relaying each provider's output to `tokens`. Once any of it has been sent
+    // on, a failure ends the call: the next provider's reply would be spliced onto it
+    async fn stream<T, F, Fut>(
+        &self,
+        task: PromptKind,
+        prompt: &Prompt,
+        tokens: TokenSender,
+        request: F,
+    ) -> Result<Served<T>, AIError>
+    where
+        F: Fn(Arc<dyn AIProvider>, TokenSender) -> Fut,
+        Fut: Future<Output = Result<Metered<T>, AIError>>,
+    {
+        let mut last_error = None;
+
+        for link in &self.providers {
+            let (attempt_tokens, mut attempt_rx) = mpsc::channel::<String>(tokens.max_capacity());
+            let mut forwarded = String::new();
+            let relay = async {
+                while let Some(text) = attempt_rx.recv().await {
+                    forwarded.push_str(&text);
+                    let _ = tokens.send(text).await;
+                }
+            };
+            let (result, _) = tokio::join!(request(link.provider.clone(), attempt_tokens), relay);
+
+            match result {
+                Ok(Metered { value, usage }) => {
+                    return Ok(Served {
+                        value,
+                        served_by: link.profile.served_by(),
+                        usage: AIUsage::new(&link.profile, task, usage.prompt_tokens, usage.completion_tokens),
+                    });
+                }
+                Err(e) if forwarded.is_empty() && e.is_unavailable() => {
+                    tracing::warn!("AI provider {} unavailable, trying the next one: {}", link.profile.served_by(), e);
+                    last_error = Some(e);
+                }
+                Err(e) if forwarded.is_empty() => return Err(e),
+                Err(e) => {
+                    let prompt_text = format!("{}{}", prompt.system.as_deref().unwrap_or_default(), prompt.user);
+                    let usage = AIUsage::new(&link.profile, task, estimated_tokens(&prompt_text), estimated_tokens(&forwarded));
+                    return Err(AIError::StreamFailed {
+                        error: Box::new(e),
+                        usage: Box::new(usage),
+                    });
+                }
+            }
+        }
+
+        Err(last_error.unwrap_or_else(|| AIError::ConfigError("No AI provider in the chain".to_string())))
+    }
+}
+
+// Services only report usage once a stream finishes, so an interrupted one is estimated
+// at the usual four characters a token
+fn estimated_tokens(text: &str) -> u32 {
+    (text.chars().count() as u32).div_ceil(4)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::AIProvider as ProviderKind;
+    use crate::services::mock_ai::{MockFailure, MockOptions, MockProvider};
+
+    // A mock provider whose profile names `model`
+    fn link(model: &str, options: MockOptions) -> ChainedProvider {
+        ChainedProvider {
+            profile: AIConfig::new(ProviderKind::Mock, None, None, Some(model.to_string()), 1000, 0.7),
+            provider: Arc::new(MockProvider::with_options(options)),
+        }
+    }
+
+    fn failing(fail_with: MockFailure) -> MockOptions {
+        MockOptions {
+            fail_every: Some(1),
+            fail_with,
+            ..MockOptions::default()
+        }
+    }
+
+    fn answering(question: &str) -> MockOptions {
+        MockOptions {
+            questions: vec![question.to_string()],
+            ..MockOptions::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_falls_back_on_unavailable_provider() {
+        let chain = ProviderChain::new(vec![
+            link("primary", failing(MockFailure::Unavailable)),
+            link("backup", failing(MockFailure::Timeout)),
+            link("llama3.1", answering("What is a borrow?")),
+        ]);
+
+        let served = chain.generate_question(&Prompt::user("material")).await.unwrap();
+        assert_eq!(served.value, "What is a borrow?");
+        assert_eq!(served.served_by, "mock/llama3.1");
+        assert_eq!(served.usage.model, "llama3.1");
+        assert_eq!(served.usage.task, PromptKind::Question);
+        assert!(served.usage.prompt_tokens > 0 && served.usage.completion_tokens > 0);
+    }
+
+    #[tokio::test]
+    async fn test_first_available_provider_serves() {
+        let chain = ProviderChain::new(vec![
+            link("primary", answering("Primary?")),
+            link("backup", answering("Fallback?")),
+        ]);
+
+        let served = chain.generate_question(&Prompt::user("material")).await.unwrap();
+        assert_eq!(served.served_by, "mock/primary");
+    }
+
+    #[tokio::test]
+    async fn test_client_errors_do_not_fall_back() {
+        let chain = ProviderChain::new(vec![
+            link("primary", failing(MockFailure::RateLimited)),
+            link("backup", answering("Fallback?")),
+        ]);
+
+        let result = chain.generate_question(&Prompt::user("material")).await;
+        assert!(matches!(result, Err(AIError::RateLimited { .. })));
+    }
+
+    #[tokio::test]
+    async fn test_last_error_when_all_unavailable() {
+        let chain = ProviderChain::new(vec![
+            link("primary", failing(MockFailure::Unavailable)),
+            link("backup", failing(MockFailure::Timeout)),
+        ]);
+
+        let (tx, _rx) = tokio::sync::mpsc::channel(8);
+        let result = chain.stream_evaluation(&Prompt::user("material"), tx).await;
+        assert!(matches!(result, Err(AIError::Timeout(_))));
+    }
+
+    // Streams the start of a reply, then loses the connection
+    struct Interrupted;
+
+    #[async_trait::async_trait]
+    impl AIProvider for Interrupted {
+        async fn complete(&self, _prompt: &Prompt, _json: bool) -> Result<Metered<String>, AIError> {
+            Ok(Metered::new("Unused".to_string(), Default::default()))
+        }
+
+        async fn complete_stream(&self, _prompt: &Prompt, _json: bool, tokens: TokenSender) -> Result<Metered<String>, AIError> {
+            let _ = tokens.send("What is own".to_string()).await;
+            Err(AIError::Timeout("Stream stalled".to_string()))
+        }
+
+        async fn test_connection(&self) -> Result<bool, AIError> {
+            Ok(true)
+        }
+    }
+
+    #[tokio::test]
+    async fn test_stream_failing_after_output_does_not_fall_back() {
+        let chain = ProviderChain::new(vec![
+            ChainedProvider {
+                provider: Arc::new(Interrupted),
+                ..link("primary", MockOptions::default())
+            },
+            link("backup", answering("Fallback?")),
+        ]);
+
+        let (tx, mut rx) = tokio::sync::mpsc::channel(8);
+        let result = chain.stream_question(&Prompt::user("Rust ownership material"), tx).await;
+
+        let mut streamed = Vec::new();
+        while let Some(text) = rx.recv().await {
+            streamed.push(text);
+        }
+        assert_eq!(streamed, vec!["What is own"]);
+
+        let error = result.unwrap_err();
+        assert!(matches!(&error, AIError::StreamFailed { error, .. } if matches!(**error, AIError::Timeout(_))));
+        let usage = error.usage().unwrap();
+        assert_eq!(usage.model, "primary");
+        assert_eq!(usage.task, PromptKind::Question);
+        assert_eq!((usage.prompt_tokens, usage.completion_tokens), (6, 3));
+    }
+
+    #[tokio::test]
+    async fn test_stream_falls_back_before_any_output() {
+        let chain = ProviderChain::new(vec![
+            link("primary", failing(MockFailure::Unavailable)),
+            link("backup", answering("Fallback?")),
+        ]);
+
+        let (tx, mut rx) = tokio::sync::mpsc::channel(8);
+        let served = chain.stream_question(&Prompt::user("material"), tx).await.unwrap();
+        assert_eq!(served.served_by, "mock/backup");
+
+        let mut streamed = String::new();
+        while let Some(text) = rx.recv().await {
+            streamed.push_str(&text);
+        }
+        assert_eq!(streamed, served.value);
+    }
+}
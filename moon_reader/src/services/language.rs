@@ -0,0 +1,125 @@
+/// Languages a knowledge base can be set to, as (code, name used in prompts)
+pub const SUPPORTED_LANGUAGES: [(&str, &str); 10] = [
+    ("en", "English"),
+    ("zh", "Chinese"),
+    ("ja", "Japanese"),
+    ("ko", "Korean"),
+    ("fr", "French"),
+    ("de", "German"),
+    ("es", "Spanish"),
+    ("pt", "Portuguese"),
+    ("ru", "Russian"),
+    ("ar", "Arabic"),
+];
+
+// How much text to look at when detecting a language
+const DETECTION_SAMPLE_CHARS: usize = 4000;
+
+/// Prompt name for a language code, if supported
+pub fn language_name(code: &str) -> Option<&'static str> {
+    SUPPORTED_LANGUAGES
+        .iter()
+        .find(|(supported, _)| supported.eq_ignore_ascii_case(code))
+        .map(|(_, name)| *name)
+}
+
+/// The language to write in: the knowledge base setting, or one detected from `sample`
+pub fn resolve_language(setting: Option<&str>, sample: &str) -> &'static str {
+    setting
+        .and_then(language_name)
+        .or_else(|| language_name(detect_language(sample)))
+        .unwrap_or("English")
+}
+
+/// Guess the language code of `text` from its script, and for Latin text from common words
+pub fn detect_language(text: &str) -> &'static str {
+    let (mut han, mut kana, mut hangul, mut cyrillic, mut arabic, mut latin) = (0, 0, 0, 0, 0, 0);
+
+    for c in text.chars().take(DETECTION_SAMPLE_CHARS) {
+        match c {
+            '\u{3040}'..='\u{30FF}' => kana += 1,
+            '\u{4E00}'..='\u{9FFF}' | '\u{3400}'..='\u{4DBF}' => han += 1,
+            '\u{AC00}'..='\u{D7AF}' | '\u{1100}'..='\u{11FF}' => hangul += 1,
+            '\u{0400}'..='\u{04FF}' => cyrillic += 1,
+            '\u{0600}'..='\u{06FF}' => arabic += 1,
+            c if c.is_ascii_alphabetic() || ('\u{00C0}'..='\u{024F}').contains(&c) => latin += 1,
+            _ => {}
+        }
+    }
+
+    // Japanese text mixes kanji with kana; Chinese has none
+    let scripts = [
+        ("ja", if kana > 0 { kana + han } else { 0 }),
+        ("zh", if kana > 0 { 0 } else { han }),
+        ("ko", hangul),
+        ("ru", cyrillic),
+        ("ar", arabic),
+    ];
+    let (code, count) = scripts.iter().max_by_key(|(_, count)| *count).copied().unwrap_or(("en", 0));
+
+    // CJK characters carry roughly a word each, so they outweigh letters of Latin words
+    if count > 0 && count * 4 >= latin {
+        return code;
+    }
+
+    detect_latin_language(text)
+}
+
+// Pick among Latin-script languages by counting frequent function words
+fn detect_latin_language(text: &str) -> &'static str {
+    const MARKERS: [(&str, &[&str]); 5] = [
+        ("en", &["the", "and", "of", "to", "is", "that", "with"]),
+        ("fr", &["le", "la", "les", "et", "des", "est", "une"]),
+        ("de", &["der", "die", "und", "das", "ist", "nicht", "mit"]),
+        ("es", &["el", "los", "que", "y", "las", "por", "una"]),
+        ("pt", &["o", "os", "que", "e", "não", "uma", "com"]),
+    ];
+
+    let sample: String = text.chars().take(DETECTION_SAMPLE_CHARS).collect::<String>().to_lowercase();
+    let words: Vec<&str> = sample.split(|c: char| !c.is_alphabetic()).filter(|w| !w.is_empty()).collect();
+
+    MARKERS
+        .iter()
+        .map(|(code, markers)| (*code, words.iter().filter(|word| markers.contains(word)).count()))
+        .filter(|(_, hits)| *hits > 0)
+        .max_by_key(|(_, hits)| *hits)
+        .map(|(code, _)| code)
+        .unwrap_or("en")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_detect_language_by_script() {
+        assert_eq!(detect_language("所有权是Rust最独特的功能，它让Rust无需垃圾回收即可保障内存安全。"), "zh");
+        assert_eq!(detect_language("所有権はRustの最もユニークな機能です。"), "ja");
+        assert_eq!(detect_language("소유권은 러스트의 가장 독특한 기능입니다."), "ko");
+        assert_eq!(detect_language("Владение — самая уникальная особенность языка."), "ru");
+    }
+
+    #[test]
+    fn test_detect_latin_languages() {
+        assert_eq!(detect_language("Ownership is the most unique feature of Rust and it enables memory safety."), "en");
+        assert_eq!(detect_language("La propriété est la fonctionnalité la plus unique de Rust et elle est essentielle."), "fr");
+        assert_eq!(detect_language("Die Eigentümerschaft ist die einzigartigste Funktion und das ist nicht trivial."), "de");
+        assert_eq!(detect_language("12345"), "en");
+    }
+
+    #[test]
+    fn test_mostly_english_with_cjk_terms() {
+        assert_eq!(
+            detect_language("The word 所有权 means ownership, and the chapter explains how the borrow checker works."),
+            "en"
+        );
+    }
+
+    #[test]
+    fn test_resolve_language() {
+        assert_eq!(resolve_language(Some("zh"), "English text about the topic"), "Chinese");
+        assert_eq!(resolve_language(None, "所有权是Rust最独特的功能"), "Chinese");
+        assert_eq!(resolve_language(Some("xx"), "The text is English"), "English");
+        assert_eq!(language_name("FR"), Some("French"));
+    }
+}
@@ -0,0 +1,444 @@
+use async_trait::async_trait;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+
+use crate::models::{QuestionType, ReferenceAnswer};
+use crate::services::ai::{AIError, AIProvider, Metered, TokenSender, TokenUsage};
+use crate::services::prompts::Prompt;
+use crate::services::question_types::{self, GeneratedQuestion};
+use crate::services::reference_answers;
+use crate::services::retrieval::HashingEmbedder;
+
+/// Error a mock provider injects in place of a reply
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MockFailure {
+    #[default]
+    Unavailable,
+    Timeout,
+    RateLimited,
+    InvalidResponse,
+}
+
+/// Behaviour of the mock provider, read from the AI configuration's provider options
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct MockOptions {
+    /// Delay before every reply, in milliseconds
+    pub latency_ms: u64,
+    /// Replies to question prompts, used in turn and repeated; empty for generated questions.
+    /// Structured question types expect JSON in the shape their format instructions ask for
+    pub questions: Vec<String>,
+    /// Replies to evaluation prompts: strings are sent verbatim, anything else as JSON
+    pub evaluations: Vec<Value>,
+    /// Replies to reference answer prompts, sent like evaluations; empty for generated references
+    pub references: Vec<Value>,
+    /// Replies to tutoring prompts, sent verbatim; empty for generated replies
+    pub tutor_replies: Vec<String>,
+    /// Fail every n-th request (1 fails them all)
+    pub fail_every: Option<usize>,
+    pub fail_with: MockFailure,
+}
+
+/// Offline provider with scripted or deterministic replies, for development and tests
+#[derive(Debug, Default)]
+pub struct MockProvider {
+    options: MockOptions,
+    requests: AtomicUsize,
+    questions_served: AtomicUsize,
+    evaluations_served: AtomicUsize,
+    references_served: AtomicUsize,
+    tutor_replies_served: AtomicUsize,
+}
+
+impl MockProvider {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_options(options: MockOptions) -> Self {
+        Self {
+            options,
+            ..Self::default()
+        }
+    }
+
+    /// Build from the provider options JSON stored with an AI configuration
+    pub fn from_options_json(options: Option<&str>) -> Result<Self, AIError> {
+        let options = match options {
+            Some(options) => serde_json::from_str(options)
+                .map_err(|e| AIError::ConfigError(format!("Invalid mock options: {}", e)))?,
+            None => MockOptions::default(),
+        };
+        Ok(Self::with_options(options))
+    }
+
+    // Simulate the round trip: wait, then fail if this request is one to fail
+    async fn begin_request(&self) -> Result<(), AIError> {
+        if self.options.latency_ms > 0 {
+            tokio::time::sleep(Duration::from_millis(self.options.latency_ms)).await;
+        }
+
+        let request = self.requests.fetch_add(1, Ordering::SeqCst) + 1;
+        match self.options.fail_every {
+            Some(every) if every > 0 && request.is_multiple_of(every) => Err(self.injected_error()),
+            _ => Ok(()),
+        }
+    }
+
+    fn injected_error(&self) -> AIError {
+        match self.options.fail_with {
+            MockFailure::Unavailable => AIError::ApiError {
+                status: 503,
+                message: "Mock provider unavailable".to_string(),
+            },
+            MockFailure::Timeout => AIError::Timeout("mock provider did not respond".to_string()),
+            MockFailure::RateLimited => AIError::RateLimited {
+                retry_after: Some(Duration::from_secs(1)),
+            },
+            MockFailure::InvalidResponse => AIError::InvalidResponse("Mock provider returned no content".to_string()),
+        }
+    }
+
+    fn question_reply(&self, prompt: &Prompt, question_type: QuestionType) -> String {
+        let served = self.questions_served.fetch_add(1, Ordering::SeqCst);
+        match self.options.questions.get(served % self.options.questions.len().max(1)) {
+            Some(question) => question.clone(),
+            None if question_type == QuestionType::Open => generated_question(prompt),
+            None => generated_structured_question(prompt, question_type),
+        }
+    }
+
+    fn evaluation_reply(&self, prompt: &Prompt) -> String {
+        let served = self.evaluations_served.fetch_add(1, Ordering::SeqCst);
+        match self.options.evaluations.get(served % self.options.evaluations.len().max(1)) {
+            Some(Value::String(reply)) => reply.clone(),
+            Some(evaluation) => evaluation.to_string(),
+            None => generated_evaluation(prompt),
+        }
+    }
+
+    fn reference_reply(&self, prompt: &Prompt) -> String {
+        let served = self.references_served.fetch_add(1, Ordering::SeqCst);
+        match self.options.references.get(served % self.options.references.len().max(1)) {
+            Some(Value::String(reply)) => reply.clone(),
+            Some(reference) => reference.to_string(),
+            None => generated_reference(prompt),
+        }
+    }
+
+    fn tutor_reply_text(&self, prompt: &Prompt) -> String {
+        let served = self.tutor_replies_served.fetch_add(1, Ordering::SeqCst);
+        match self.options.tutor_replies.get(served % self.options.tutor_replies.len().max(1)) {
+            Some(reply) => reply.clone(),
+            None => generated_tutor_reply(prompt),
+        }
+    }
+}
+
+#[async_trait]
+impl AIProvider for MockProvider {
+    async fn complete(&self, prompt: &Prompt, json: bool) -> Result<Metered<String>, AIError> {
+        self.begin_request().await?;
+
+        // Only evaluations ask for JSON output here; structured questions have their own method
+        let reply = if json {
+            self.evaluation_reply(prompt)
+        } else {
+            self.question_reply(prompt, QuestionType::Open)
+        };
+        Ok(metered(prompt, reply))
+    }
+
+    async fn generate_structured_question(&self, prompt: &Prompt, question_type: QuestionType) -> Result<Metered<GeneratedQuestion>, AIError> {
+        self.begin_request().await?;
+        let reply = self.question_reply(prompt, question_type);
+        question_types::parse_reply(question_type, metered(prompt, reply))
+    }
+
+    async fn generate_reference_answer(&self, prompt: &Prompt) -> Result<Metered<ReferenceAnswer>, AIError> {
+        self.begin_request().await?;
+        let reply = self.reference_reply(prompt);
+        reference_answers::parse_reply(metered(prompt, reply))
+    }
+
+    async fn tutor_reply(&self, prompt: &Prompt) -> Result<Metered<String>, AIError> {
+        self.begin_request().await?;
+        Ok(metered(prompt, self.tutor_reply_text(prompt)))
+    }
+
+    async fn complete_stream(&self, prompt: &Prompt, json: bool, tokens: TokenSender) -> Result<Metered<String>, AIError> {
+        let reply = self.complete(prompt, json).await?;
+        for word in reply.value.split_inclusive(' ') {
+            let _ = tokens.send(word.to_string()).await;
+        }
+        Ok(reply)
+    }
+
+    async fn test_connection(&self) -> Result<bool, AIError> {
+        Ok(self.begin_request().await.is_ok())
+    }
+
+    async fn embed(&self, texts: &[String]) -> Result<Vec<Vec<f32>>, AIError> {
+        self.begin_request().await?;
+        Ok(texts.iter().map(|text| HashingEmbedder::embed(text)).collect())
+    }
+
+    async fn list_models(&self) -> Result<Vec<String>, AIError> {
+        Ok(vec!["mock".to_string()])
+    }
+}
+
+// Usage is counted in words, a stand-in for tokens
+fn word_count(text: &str) -> u32 {
+    text.split_whitespace().count() as u32
+}
+
+fn metered(prompt: &Prompt, reply: String) -> Metered<String> {
+    let usage = TokenUsage::new(
+        word_count(prompt.system.as_deref().unwrap_or_default()) + word_count(&prompt.user),
+        word_count(&reply),
+    );
+    Metered::new(reply, usage)
+}
+
+// FNV-1a, so replies are stable across runs and platforms
+fn stable_hash(text: &str) -> u64 {
+    text.bytes()
+        .fold(0xcbf29ce484222325, |hash, byte| (hash ^ u64::from(byte)).wrapping_mul(0x100000001b3))
+}
+
+// The first words of the longest line of the prompt, which is normally the material
+fn material_excerpt(prompt: &Prompt) -> Vec<&str> {
+    let material = prompt.user.lines().max_by_key(|line| line.chars().count()).unwrap_or_default();
+    material.split_whitespace().take(12).collect()
+}
+
+// A question quoting the start of the material
+fn generated_question(prompt: &Prompt) -> String {
+    let excerpt = material_excerpt(prompt);
+
+    if excerpt.is_empty() {
+        return "What is the main idea of this material?".to_string();
+    }
+
+    let excerpt = excerpt.join(" ");
+    match stable_hash(&prompt.user) % 3 {
+        0 => format!("What is the main idea behind \"{}\"?", excerpt),
+        1 => format!("How would you explain \"{}\" to someone new to the topic?", excerpt),
+        _ => format!("Why does \"{}\" matter, and what follows from it?", excerpt),
+    }
+}
+
+// A valid structured question about the start of the material, in the JSON its format instructions ask for
+fn generated_structured_question(prompt: &Prompt, question_type: QuestionType) -> String {
+    let mut excerpt = material_excerpt(prompt);
+    if excerpt.is_empty() {
+        excerpt = vec!["The", "material", "is", "empty"];
+    }
+    let quote = excerpt.join(" ");
+
+    match question_type {
+        QuestionType::MultipleChoice => {
+            let mut options = vec![
+                "The material does not cover this".to_string(),
+                "Something unrelated to the material".to_string(),
+                "None of the other options".to_string(),
+            ];
+            let correct = (stable_hash(&prompt.user) % 4) as usize;
+            options.insert(correct, quote);
+            json!({"question": "Which of these appears in the material?", "options": options, "correct": [correct]})
+        }
+        QuestionType::TrueFalse => json!({"statement": format!("The material says \"{}\".", quote), "answer": true}),
+        QuestionType::Cloze => {
+            let longest = (0..excerpt.len()).max_by_key(|&i| excerpt[i].chars().count()).unwrap_or_default();
+            let text: Vec<String> = excerpt
+                .iter()
+                .enumerate()
+                .map(|(i, word)| if i == longest { format!("[[{}]]", word) } else { word.to_string() })
+                .collect();
+            json!({"text": text.join(" ")})
+        }
+        QuestionType::ShortAnswer | QuestionType::Open => {
+            let (last, start) = excerpt.split_last().unwrap_or((&"material", &[]));
+            json!({
+                "question": format!("Which word follows \"{}\" in the material?", start.join(" ")),
+                "answer": last,
+            })
+        }
+    }
+    .to_string()
+}
+
+// A reference answer quoting the material, with its first sentences as key points
+fn generated_reference(prompt: &Prompt) -> String {
+    let material = prompt.user.lines().max_by_key(|line| line.chars().count()).unwrap_or_default();
+    let key_points: Vec<&str> = material
+        .split_inclusive(['.', '。'])
+        .map(str::trim)
+        .filter(|sentence| !sentence.is_empty())
+        .take(3)
+        .collect();
+    let answer = match material_excerpt(prompt) {
+        excerpt if excerpt.is_empty() => "The material does not answer this question.".to_string(),
+        excerpt => format!("According to the material, {}", excerpt.join(" ")),
+    };
+
+    json!({"answer": answer, "key_points": key_points}).to_string()
+}
+
+// A reply answering the learner's last message by quoting the first passage of the material
+fn generated_tutor_reply(prompt: &Prompt) -> String {
+    let message = prompt.user.rsplit("\nLearner: ").next().unwrap_or_default();
+    let passage = prompt
+        .user
+        .lines()
+        .skip_while(|line| line.trim() != "Learning material:")
+        .skip(1)
+        .take_while(|line| !line.starts_with("Question: "))
+        .find(|line| !line.trim().is_empty() && !line.starts_with('['));
+    match passage {
+        Some(passage) => format!("Mock tutor: about \"{}\", the material says \"{}\" [1].", message, passage.trim()),
+        None => format!("Mock tutor: the material does not cover \"{}\".", message),
+    }
+}
+
+// A valid evaluation whose score depends only on the prompt
+fn generated_evaluation(prompt: &Prompt) -> String {
+    let criterion = |name: &str| 50 + stable_hash(&format!("{}{}", name, prompt.user)) % 51;
+    let criteria = json!({
+        "accuracy": criterion("accuracy"),
+        "completeness": criterion("completeness"),
+        "depth": criterion("depth"),
+        "clarity": criterion("clarity"),
+    });
+    let score = 50 + stable_hash(&prompt.user) % 51;
+    let (matched, missed) = key_point_coverage(&prompt.user);
+    json!({
+        "score": score,
+        "criteria": criteria,
+        "feedback": format!("Mock evaluation: the answer scores {} out of 100.", score),
+        "suggestions": ["Refer to specific points in the material", "Explain the reasoning behind each point"],
+        "matched_key_points": matched,
+        "missed_key_points": missed,
+    })
+    .to_string()
+}
+
+// A key point counts as covered when the answer shares one of its longer words
+fn key_point_coverage(user: &str) -> (Vec<&str>, Vec<&str>) {
+    let answer = user
+        .lines()
+        .find_map(|line| line.strip_prefix("Learner's answer:"))
+        .unwrap_or_default()
+        .to_lowercase();
+    let points = user
+        .lines()
+        .skip_while(|line| line.trim() != "Key points:")
+        .skip(1)
+        .map_while(|line| line.strip_prefix("- "));
+
+    points.partition(|point| {
+        point
+            .split(|c: char| !c.is_alphanumeric())
+            .filter(|word| word.chars().count() > 3)
+            .any(|word| answer.contains(&word.to_lowercase()))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn options(value: Value) -> MockOptions {
+        serde_json::from_value(value).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_default_replies_are_deterministic() {
+        let prompt = Prompt::user("Generate a question based on the following learning material:\n\nOwnership gives Rust memory safety without a garbage collector.");
+
+        let first = MockProvider::new().generate_question(&prompt).await.unwrap().value;
+        let second = MockProvider::new().generate_question(&prompt).await.unwrap().value;
+        assert_eq!(first, second);
+        assert!(first.contains("Ownership gives Rust memory safety"));
+
+        let evaluation = MockProvider::new().evaluate_answer(&prompt).await.unwrap();
+        let score = evaluation.value.score.unwrap();
+        assert_eq!(evaluation.usage.prompt_tokens, 18);
+        assert!((50..=100).contains(&score));
+        assert_eq!(MockProvider::new().evaluate_answer(&prompt).await.unwrap().value.score, Some(score));
+        assert!(evaluation.value.criteria.is_some());
+    }
+
+    #[tokio::test]
+    async fn test_evaluation_key_point_coverage() {
+        let prompt = Prompt::user("Question: How does Rust free memory?\n\nReference answer: Through ownership.\nKey points:\n- Each value has one owner\n- Values are dropped at scope end\n\nLearner's answer: Every value has an owner.");
+
+        let evaluation = MockProvider::new().evaluate_answer(&prompt).await.unwrap().value;
+        assert_eq!(evaluation.matched_key_points, vec!["Each value has one owner"]);
+        assert_eq!(evaluation.missed_key_points, vec!["Values are dropped at scope end"]);
+    }
+
+    #[tokio::test]
+    async fn test_scripted_replies_cycle() {
+        let provider = MockProvider::with_options(options(json!({
+            "questions": ["First?", "Second?"],
+            "evaluations": [{"score": 90, "feedback": "Great", "suggestions": []}, "not json at all"],
+        })));
+        let prompt = Prompt::user("material");
+
+        assert_eq!(provider.generate_question(&prompt).await.unwrap().value, "First?");
+        assert_eq!(provider.generate_question(&prompt).await.unwrap().value, "Second?");
+        assert_eq!(provider.generate_question(&prompt).await.unwrap().value, "First?");
+
+        assert_eq!(provider.evaluate_answer(&prompt).await.unwrap().value.score, Some(90));
+        // The unparseable reply goes through the repair re-prompt, which gets the first script again,
+        // and the usage covers both requests
+        let repaired = provider.evaluate_answer(&prompt).await.unwrap();
+        assert_eq!(repaired.value.score, Some(90));
+        assert_eq!(repaired.usage.completion_tokens, 4 + 1);
+    }
+
+    #[tokio::test]
+    async fn test_reference_answers() {
+        let prompt = Prompt::user("Learning material:\nOwnership frees memory. Borrowing lends values.\n\nQuestion: How is memory freed?");
+        let generated = MockProvider::new().generate_reference_answer(&prompt).await.unwrap().value;
+        assert!(generated.answer.contains("Ownership frees memory."));
+        assert_eq!(generated.key_points, vec!["Ownership frees memory.", "Borrowing lends values."]);
+
+        let provider = MockProvider::with_options(options(json!({"references": [{"answer": "By dropping", "key_points": ["Drop"]}, "no json"]})));
+        assert_eq!(provider.generate_reference_answer(&prompt).await.unwrap().value.answer, "By dropping");
+        assert!(matches!(provider.generate_reference_answer(&prompt).await, Err(AIError::InvalidResponse(_))));
+    }
+
+    #[tokio::test]
+    async fn test_failure_injection() {
+        let provider = MockProvider::with_options(options(json!({"fail_every": 2, "fail_with": "rate_limited"})));
+        let prompt = Prompt::user("material");
+
+        assert!(provider.generate_question(&prompt).await.is_ok());
+        assert!(matches!(provider.generate_question(&prompt).await, Err(AIError::RateLimited { .. })));
+        assert!(provider.generate_question(&prompt).await.is_ok());
+
+        assert!(matches!(MockProvider::from_options_json(Some(r#"{"fail_evry": 2}"#)), Err(AIError::ConfigError(_))));
+    }
+
+    #[tokio::test]
+    async fn test_latency_and_streaming() {
+        let provider = MockProvider::with_options(options(json!({"latency_ms": 50, "questions": ["What is a borrow?"]})));
+        let (tx, mut rx) = tokio::sync::mpsc::channel(16);
+
+        let started = std::time::Instant::now();
+        let question = provider.stream_question(&Prompt::user("material"), tx).await.unwrap().value;
+        assert!(started.elapsed() >= Duration::from_millis(50));
+
+        let mut streamed = String::new();
+        while let Some(token) = rx.recv().await {
+            streamed.push_str(&token);
+        }
+        assert_eq!(streamed, question);
+    }
+}
@@ -1,19 +1,197 @@
 // Services module for business logic
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use chrono::{DateTime, Datelike, TimeZone, Utc};
 use sqlx::SqlitePool;
+use thiserror::Error;
+use tokio::sync::RwLock;
+
 use crate::database::DatabaseManager;
+use crate::models::{AIConfig, PromptKind};
+use crate::services::ai::{AIError, AIProvider, AIServiceFactory};
+use crate::services::fallback::{ChainedProvider, ProviderChain};
+use crate::services::secrets::SecretCipher;
 
 pub mod ai;
+pub mod difficulty;
+pub mod evaluation;
+pub mod fallback;
+pub mod language;
+pub mod mock_ai;
+pub mod prompts;
+pub mod question_types;
+pub mod reference_answers;
+pub mod resilience;
+pub mod retrieval;
+pub mod secrets;
+pub mod tutoring;
+
+/// Why the configured AI provider could not be obtained
+#[derive(Debug, Error)]
+pub enum ProviderLoadError {
+    #[error("AI not configured. Please configure AI settings first.")]
+    NotConfigured,
+    #[error("Failed to retrieve AI configuration: {0}")]
+    Database(#[from] sqlx::Error),
+    #[error("{0}")]
+    Provider(#[from] AIError),
+    #[error("AI profile '{profile}' has used its monthly budget of {budget}")]
+    BudgetExceeded { profile: String, budget: f64 },
+}
 
 // Application state that will be shared across handlers
 #[derive(Clone)]
 pub struct AppState {
     pub db: DatabaseManager,
+    // Providers built lazily from AI profiles, keyed by profile id, and reused across
+    // requests so each provider's HTTP connection pool is shared
+    ai_providers: Arc<RwLock<HashMap<i32, Arc<dyn AIProvider>>>>,
 }
 
 impl AppState {
     pub fn new(pool: SqlitePool) -> Self {
         Self { 
-            db: DatabaseManager::new(pool)
+            db: DatabaseManager::new(pool),
+            ai_providers: Arc::new(RwLock::new(HashMap::new())),
+        }
+    }
+    
+    /// State whose database encrypts API keys with `cipher`
+    pub fn with_secrets(pool: SqlitePool, cipher: SecretCipher) -> Self {
+        Self {
+            db: DatabaseManager::with_secrets(pool, cipher),
+            ai_providers: Arc::new(RwLock::new(HashMap::new())),
         }
     }
-}
\ No newline at end of file
+    
+    /// The provider for the default AI profile, built on first use
+    pub async fn ai_provider(&self) -> Result<Arc<dyn AIProvider>, ProviderLoadError> {
+        let config = self.db.get_ai_config().await?.ok_or(ProviderLoadError::NotConfigured)?;
+        self.profile_provider(&config).await
+    }
+    
+    /// The providers for the profile selected for `task` in a knowledge base, followed by its fallbacks
+    pub async fn provider_chain(
+        &self,
+        knowledge_base_id: &str,
+        task: PromptKind,
+    ) -> Result<ProviderChain, ProviderLoadError> {
+        let config = self
+            .db
+            .resolve_ai_profile(Some(knowledge_base_id), task)
+            .await?
+            .ok_or(ProviderLoadError::NotConfigured)?;
+        
+        // Only the selected profile's own list is followed, so fallbacks cannot loop
+        let mut profiles = vec![config.clone()];
+        for &id in &config.fallback_profile_ids {
+            if config.id == Some(id) {
+                continue;
+            }
+            if let Some(fallback) = self.db.get_ai_profile(id).await? {
+                profiles.push(fallback);
+            }
+        }
+        
+        // Profiles over budget are left out, so their fallbacks take over
+        let mut providers = Vec::new();
+        for (position, profile) in profiles.into_iter().enumerate() {
+            if self.over_budget(&profile).await? {
+                tracing::warn!("Skipping AI profile '{}': monthly budget used", profile.name);
+                continue;
+            }
+            match self.profile_provider(&profile).await {
+                Ok(provider) => providers.push(ChainedProvider { profile, provider }),
+                Err(e) if position == 0 => return Err(e),
+                Err(e) => tracing::warn!("Skipping fallback AI profile '{}': {}", profile.name, e),
+            }
+        }
+        
+        if providers.is_empty() {
+            return Err(ProviderLoadError::BudgetExceeded {
+                profile: config.name,
+                budget: config.monthly_budget.unwrap_or_default(),
+            });
+        }
+        
+        Ok(ProviderChain::new(providers))
+    }
+    
+    /// Whether a profile has spent its budget for the current calendar month
+    pub async fn over_budget(&self, profile: &AIConfig) -> Result<bool, sqlx::Error> {
+        let (Some(id), Some(budget)) = (profile.id, profile.monthly_budget) else {
+            return Ok(false);
+        };
+        
+        let spent = self.db.get_ai_profile_cost(id, month_start(Utc::now())).await?;
+        Ok(spent >= budget)
+    }
+    
+    /// The provider for a stored profile, reusing a cached instance
+    pub async fn profile_provider(&self, config: &AIConfig) -> Result<Arc<dyn AIProvider>, ProviderLoadError> {
+        let Some(id) = config.id else {
+            return Ok(Arc::from(AIServiceFactory::from_config(config)?));
+        };
+        
+        if let Some(provider) = self.ai_providers.read().await.get(&id) {
+            return Ok(provider.clone());
+        }
+        
+        let mut cached = self.ai_providers.write().await;
+        // Another request may have built it while we waited for the lock
+        if let Some(provider) = cached.get(&id) {
+            return Ok(provider.clone());
+        }
+        
+        let provider: Arc<dyn AIProvider> = Arc::from(AIServiceFactory::from_config(config)?);
+        cached.insert(id, provider.clone());
+        
+        Ok(provider)
+    }
+    
+    /// Drop the cached providers so the next use picks up changed settings
+    pub async fn invalidate_ai_provider(&self) {
+        self.ai_providers.write().await.clear();
+    }
+}
+
+/// Midnight UTC on the first day of the month containing `now`
+pub fn month_start(now: DateTime<Utc>) -> DateTime<Utc> {
+    Utc.with_ymd_and_hms(now.year(), now.month(), 1, 0, 0, 0)
+        .single()
+        .unwrap_or(now)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::create_connection_pool;
+    use crate::models::{AIConfig, AIProvider as ProviderKind};
+
+    #[tokio::test]
+    async fn test_ai_provider_cached_until_invalidated() {
+        let pool = create_connection_pool("sqlite::memory:").await.unwrap();
+        let state = AppState::new(pool);
+        
+        assert!(matches!(state.ai_provider().await, Err(ProviderLoadError::NotConfigured)));
+        
+        let config = AIConfig::new(ProviderKind::DeepSeek, Some("key".to_string()), None, None, 1000, 0.7);
+        state.db.save_ai_config(&config).await.unwrap();
+        
+        let first = state.ai_provider().await.unwrap();
+        let second = state.ai_provider().await.unwrap();
+        assert!(Arc::ptr_eq(&first, &second));
+        
+        // Invalid settings surface once the cache is dropped
+        let config = AIConfig::new(ProviderKind::Local, None, None, None, 1000, 0.7);
+        state.db.save_ai_config(&config).await.unwrap();
+        assert!(Arc::ptr_eq(&first, &state.ai_provider().await.unwrap()));
+        
+        state.invalidate_ai_provider().await;
+        assert!(matches!(
+            state.ai_provider().await,
+            Err(ProviderLoadError::Provider(AIError::ConfigError(_)))
+        ));
+    }
+}
@@ -0,0 +1,178 @@
+use serde::{Deserialize, Serialize};
+
+use crate::models::{PromptKind, PromptTemplate};
+
+/// Placeholder names a template may use, written as `{{name}}`
+pub const PROMPT_VARIABLES: [&str; 6] = ["context", "question", "answer", "language", "difficulty", "reference"];
+
+/// A rendered prompt ready to send to a model
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Prompt {
+    pub system: Option<String>,
+    pub user: String,
+}
+
+impl Prompt {
+    /// A prompt with only a user message
+    pub fn user(text: impl Into<String>) -> Self {
+        Self {
+            system: None,
+            user: text.into(),
+        }
+    }
+}
+
+/// Values substituted into a template; missing values render as empty text
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct PromptVariables {
+    pub context: Option<String>,
+    pub question: Option<String>,
+    pub answer: Option<String>,
+    pub language: Option<String>,
+    pub difficulty: Option<String>,
+    /// The question's reference answer and key points, for evaluations
+    pub reference: Option<String>,
+}
+
+impl PromptVariables {
+    fn get(&self, name: &str) -> Option<&str> {
+        match name {
+            "context" => self.context.as_deref(),
+            "question" => self.question.as_deref(),
+            "answer" => self.answer.as_deref(),
+            "language" => self.language.as_deref(),
+            "difficulty" => self.difficulty.as_deref(),
+            "reference" => self.reference.as_deref(),
+            _ => None,
+        }
+    }
+}
+
+/// Variables a template of the given kind must reference
+pub fn required_variables(kind: PromptKind) -> &'static [&'static str] {
+    match kind {
+        PromptKind::Question => &["context"],
+        PromptKind::Evaluation => &["context", "question", "answer"],
+    }
+}
+
+/// Fill in a template's placeholders
+pub fn render(template: &PromptTemplate, variables: &PromptVariables) -> Prompt {
+    Prompt {
+        system: template
+            .system_prompt
+            .as_deref()
+            .map(|text| render_text(text, variables))
+            .filter(|text| !text.trim().is_empty()),
+        user: render_text(&template.user_prompt, variables),
+    }
+}
+
+/// Check that a template only uses known placeholders and references every required one
+pub fn validate_template(kind: PromptKind, system_prompt: Option<&str>, user_prompt: &str) -> Result<(), String> {
+    let mut used = Vec::new();
+    for text in system_prompt.into_iter().chain(std::iter::once(user_prompt)) {
+        for (_, name) in placeholders(text) {
+            if !PROMPT_VARIABLES.contains(&name) {
+                return Err(format!(
+                    "Unknown variable {{{{{}}}}}; available variables are {}",
+                    name,
+                    PROMPT_VARIABLES.join(", ")
+                ));
+            }
+            used.push(name);
+        }
+    }
+
+    let missing: Vec<&str> = required_variables(kind)
+        .iter()
+        .copied()
+        .filter(|name| !used.contains(name))
+        .collect();
+    if !missing.is_empty() {
+        return Err(format!("A {} template must use {}", kind, missing.join(", ")));
+    }
+
+    Ok(())
+}
+
+fn render_text(text: &str, variables: &PromptVariables) -> String {
+    let mut rendered = String::with_capacity(text.len());
+    let mut last = 0;
+
+    for (range, name) in placeholders(text) {
+        rendered.push_str(&text[last..range.start]);
+        rendered.push_str(variables.get(name).unwrap_or_default());
+        last = range.end;
+    }
+    rendered.push_str(&text[last..]);
+
+    rendered
+}
+
+// `{{ name }}` placeholders with their byte ranges; braces around anything else are literal text
+fn placeholders(text: &str) -> Vec<(std::ops::Range<usize>, &str)> {
+    let mut found = Vec::new();
+    let mut offset = 0;
+
+    while let Some(start) = text[offset..].find("{{").map(|i| offset + i) {
+        let Some(len) = text[start + 2..].find("}}") else {
+            break;
+        };
+        let end = start + 2 + len + 2;
+        let name = text[start + 2..end - 2].trim();
+
+        if !name.is_empty() && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+            found.push((start..end, name));
+            offset = end;
+        } else {
+            offset = start + 2;
+        }
+    }
+
+    found
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn template(system: Option<&str>, user: &str) -> PromptTemplate {
+        PromptTemplate::new("test".to_string(), PromptKind::Evaluation, system.map(str::to_string), user.to_string())
+    }
+
+    #[test]
+    fn test_render_substitutes_variables() {
+        let variables = PromptVariables {
+            context: Some("Ownership rules".to_string()),
+            question: Some("What is a borrow?".to_string()),
+            ..Default::default()
+        };
+
+        let prompt = render(
+            &template(Some("Reply in {{ language }}."), "Q: {{question}}\nA: {{answer}}\n{{context}} {\"score\": 0}"),
+            &variables,
+        );
+
+        assert_eq!(prompt.system.as_deref(), Some("Reply in ."));
+        assert_eq!(prompt.user, "Q: What is a borrow?\nA: \nOwnership rules {\"score\": 0}");
+    }
+
+    #[test]
+    fn test_render_drops_empty_system_prompt() {
+        let prompt = render(&template(Some("{{language}}"), "{{context}}"), &PromptVariables::default());
+        assert_eq!(prompt.system, None);
+    }
+
+    #[test]
+    fn test_validate_template() {
+        assert!(validate_template(PromptKind::Question, None, "Ask about {{context}}").is_ok());
+        assert!(validate_template(PromptKind::Question, Some("Use {{context}}"), "Ask something").is_ok());
+
+        let unknown = validate_template(PromptKind::Question, None, "{{context}} {{topic}}").unwrap_err();
+        assert!(unknown.contains("{{topic}}"));
+
+        let missing = validate_template(PromptKind::Evaluation, None, "{{context}} {{question}}").unwrap_err();
+        assert!(missing.contains("answer"));
+    }
+}
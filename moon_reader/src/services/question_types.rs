@@ -0,0 +1,539 @@
+use serde_json::Value;
+use thiserror::Error;
+
+use crate::models::{Question, QuestionPayload, QuestionType};
+use crate::services::ai::{AIError, AIEvaluation, Metered};
+use crate::services::evaluation::json_objects;
+
+/// `served_by` of answers graded against the answer key instead of by a model
+pub const LOCAL_GRADER: &str = "local";
+
+// Shown in place of each cloze deletion
+const BLANK: &str = "____";
+const MAX_OPTIONS: usize = 8;
+
+/// A generated question, with the answer key of structured types
+#[derive(Debug, Clone, PartialEq)]
+pub struct GeneratedQuestion {
+    pub question_text: String,
+    pub payload: Option<QuestionPayload>,
+}
+
+impl GeneratedQuestion {
+    pub fn open(question_text: String) -> Self {
+        Self {
+            question_text,
+            payload: None,
+        }
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum QuestionParseError {
+    #[error("no JSON object found in the reply")]
+    NoJson,
+    #[error("field `{field}` {reason}")]
+    InvalidField { field: &'static str, reason: String },
+}
+
+/// Output format appended to the question prompt, for every type but open questions
+pub fn format_instructions(question_type: QuestionType) -> Option<&'static str> {
+    match question_type {
+        QuestionType::Open => None,
+        QuestionType::MultipleChoice => Some(
+            "Instead of an open question, write one multiple choice question with four options, exactly one of which is correct. Return only a JSON object: {\"question\": \"<question>\", \"options\": [\"<option>\", ...], \"correct\": [<0-based index of the correct option>]}",
+        ),
+        QuestionType::TrueFalse => Some(
+            "Instead of an open question, write one statement about the material that is either clearly true or clearly false. Return only a JSON object: {\"statement\": \"<statement>\", \"answer\": <true or false>}",
+        ),
+        QuestionType::Cloze => Some(
+            "Instead of an open question, take one key sentence from the material and wrap the one to three terms the learner must recall in double square brackets, e.g. \"Rust manages memory through [[ownership]].\". Return only a JSON object: {\"text\": \"<sentence with [[terms]]>\"}",
+        ),
+        QuestionType::ShortAnswer => Some(
+            "Instead of an open question, write one question whose answer is a term or short phrase from the material. Return only a JSON object: {\"question\": \"<question>\", \"answer\": \"<expected answer>\", \"accepted\": [\"<other correct wording>\", ...]}",
+        ),
+    }
+}
+
+/// Parse a generated question of the given type, tolerating code fences and surrounding prose
+pub fn parse_question(question_type: QuestionType, reply: &str) -> Result<GeneratedQuestion, QuestionParseError> {
+    if question_type == QuestionType::Open {
+        return Ok(GeneratedQuestion::open(reply.to_string()));
+    }
+
+    let mut last_error = QuestionParseError::NoJson;
+    for object in json_objects(reply) {
+        match validate(question_type, &object) {
+            Ok(question) => return Ok(question),
+            Err(e) => last_error = e,
+        }
+    }
+
+    Err(last_error)
+}
+
+/// Parse a model reply, reporting an unusable one as an invalid response
+pub fn parse_reply(question_type: QuestionType, reply: Metered<String>) -> Result<Metered<GeneratedQuestion>, AIError> {
+    parse_question(question_type, &reply.value)
+        .map(|question| Metered::new(question, reply.usage))
+        .map_err(|e| AIError::InvalidResponse(format!("Unusable {} question: {}", question_type, e)))
+}
+
+/// Grade an answer against the question's answer key, or `None` when it needs the AI
+///
+/// Multiple choice, true/false and cloze answers are always graded here. Short answers only
+/// when they match an expected wording, since other phrasings may still be right.
+pub fn grade(question: &Question, user_answer: &str) -> Option<AIEvaluation> {
+    match question.answer_key()? {
+        QuestionPayload::MultipleChoice { options, correct } => Some(grade_choice(&options, &correct, user_answer)),
+        QuestionPayload::TrueFalse { answer } => Some(grade_true_false(answer, user_answer)),
+        QuestionPayload::Cloze { answers } => Some(grade_cloze(&answers, user_answer)),
+        QuestionPayload::ShortAnswer { answer, accepted } => {
+            let given = normalize(user_answer);
+            std::iter::once(&answer)
+                .chain(&accepted)
+                .any(|expected| normalize(expected) == given)
+                .then(|| graded(100, "Correct.".to_string()))
+        }
+    }
+}
+
+// Check a candidate object against the fields the type's format instructions ask for
+fn validate(question_type: QuestionType, object: &Value) -> Result<GeneratedQuestion, QuestionParseError> {
+    let (question_text, payload) = match question_type {
+        QuestionType::Open => return Ok(GeneratedQuestion::open(text_field(object, "question")?)),
+        QuestionType::MultipleChoice => {
+            let question = text_field(object, "question")?;
+            let options = string_list(object, "options")?;
+            if !(2..=MAX_OPTIONS).contains(&options.len()) {
+                return Err(invalid("options", format!("must have between 2 and {} entries", MAX_OPTIONS)));
+            }
+
+            let mut correct = match object.get("correct") {
+                Some(Value::Array(items)) => items.iter().map(Value::as_u64).collect::<Option<Vec<_>>>(),
+                Some(Value::Number(n)) => n.as_u64().map(|n| vec![n]),
+                _ => None,
+            }
+            .ok_or_else(|| invalid("correct", "must be an array of option indexes".to_string()))?
+            .into_iter()
+            .map(|index| index as usize)
+            .collect::<Vec<_>>();
+            correct.sort_unstable();
+            correct.dedup();
+            if correct.is_empty() || correct.iter().any(|&index| index >= options.len()) {
+                return Err(invalid("correct", "must name at least one of the options".to_string()));
+            }
+
+            (question, QuestionPayload::MultipleChoice { options, correct })
+        }
+        QuestionType::TrueFalse => {
+            let statement = text_field(object, "statement")?;
+            let answer = match object.get("answer") {
+                Some(Value::Bool(answer)) => Some(*answer),
+                Some(Value::String(answer)) => parse_bool(answer),
+                _ => None,
+            }
+            .ok_or_else(|| invalid("answer", "must be true or false".to_string()))?;
+
+            (statement, QuestionPayload::TrueFalse { answer })
+        }
+        QuestionType::Cloze => {
+            let (text, answers) = blank_out(&text_field(object, "text")?);
+            if answers.is_empty() {
+                return Err(invalid("text", "must mark at least one term with [[ ]]".to_string()));
+            }
+
+            (text, QuestionPayload::Cloze { answers })
+        }
+        QuestionType::ShortAnswer => {
+            let question = text_field(object, "question")?;
+            let answer = text_field(object, "answer")?;
+            let accepted = match object.get("accepted") {
+                None | Some(Value::Null) => Vec::new(),
+                Some(_) => string_list(object, "accepted")?,
+            };
+
+            (question, QuestionPayload::ShortAnswer { answer, accepted })
+        }
+    };
+
+    Ok(GeneratedQuestion {
+        question_text,
+        payload: Some(payload),
+    })
+}
+
+/// Check an edited answer key against the question text it belongs to
+pub fn check_answer_key(question_text: &str, payload: &QuestionPayload) -> Result<(), QuestionParseError> {
+    match payload {
+        QuestionPayload::MultipleChoice { options, correct } => {
+            if !(2..=MAX_OPTIONS).contains(&options.len()) {
+                return Err(invalid("options", format!("must have between 2 and {} entries", MAX_OPTIONS)));
+            }
+            if options.iter().any(|option| option.trim().is_empty()) {
+                return Err(invalid("options", "must not contain empty entries".to_string()));
+            }
+            if correct.is_empty() || correct.iter().any(|&index| index >= options.len()) {
+                return Err(invalid("correct", "must name at least one of the options".to_string()));
+            }
+        }
+        QuestionPayload::TrueFalse { .. } => {}
+        QuestionPayload::Cloze { answers } => {
+            if answers.is_empty() || answers.iter().any(|answer| answer.trim().is_empty()) {
+                return Err(invalid("answers", "must list a non-empty answer for every blank".to_string()));
+            }
+            let blanks = question_text.matches(BLANK).count();
+            if blanks != answers.len() {
+                return Err(invalid(
+                    "answers",
+                    format!("has {} entries but the text has {} {} blanks", answers.len(), blanks, BLANK),
+                ));
+            }
+        }
+        QuestionPayload::ShortAnswer { answer, .. } => {
+            if answer.trim().is_empty() {
+                return Err(invalid("answer", "must be a non-empty string".to_string()));
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn invalid(field: &'static str, reason: String) -> QuestionParseError {
+    QuestionParseError::InvalidField { field, reason }
+}
+
+fn text_field(object: &Value, field: &'static str) -> Result<String, QuestionParseError> {
+    object
+        .get(field)
+        .and_then(Value::as_str)
+        .map(str::trim)
+        .filter(|text| !text.is_empty())
+        .map(str::to_string)
+        .ok_or_else(|| invalid(field, "must be a non-empty string".to_string()))
+}
+
+fn string_list(object: &Value, field: &'static str) -> Result<Vec<String>, QuestionParseError> {
+    object
+        .get(field)
+        .and_then(Value::as_array)
+        .and_then(|items| {
+            items
+                .iter()
+                .map(|item| item.as_str().map(str::trim).filter(|text| !text.is_empty()).map(str::to_string))
+                .collect::<Option<Vec<_>>>()
+        })
+        .ok_or_else(|| invalid(field, "must be an array of non-empty strings".to_string()))
+}
+
+// Replace every `[[term]]` with a blank, returning the blanked text and the terms in order
+fn blank_out(text: &str) -> (String, Vec<String>) {
+    let mut blanked = String::new();
+    let mut answers = Vec::new();
+    let mut rest = text;
+
+    while let Some(start) = rest.find("[[") {
+        let Some(len) = rest[start + 2..].find("]]") else {
+            break;
+        };
+        let term = rest[start + 2..start + 2 + len].trim();
+
+        blanked.push_str(&rest[..start]);
+        if term.is_empty() {
+            blanked.push_str(&rest[start..start + len + 4]);
+        } else {
+            blanked.push_str(BLANK);
+            answers.push(term.to_string());
+        }
+        rest = &rest[start + len + 4..];
+    }
+
+    blanked.push_str(rest);
+    (blanked, answers)
+}
+
+fn graded(score: u8, feedback: String) -> AIEvaluation {
+    AIEvaluation {
+        score: Some(score),
+        feedback,
+        suggestions: Vec::new(),
+        criteria: None,
+        matched_key_points: Vec::new(),
+        missed_key_points: Vec::new(),
+    }
+}
+
+pub(crate) fn option_label(index: usize) -> char {
+    char::from(b'A' + index as u8)
+}
+
+// Every wrong pick cancels a right one, so selecting all options scores nothing
+fn grade_choice(options: &[String], correct: &[usize], user_answer: &str) -> AIEvaluation {
+    let selected = selected_options(options, user_answer);
+    let hits = selected.iter().filter(|index| correct.contains(index)).count();
+    let misses = selected.len() - hits;
+    let score = (hits.saturating_sub(misses) * 100 / correct.len()) as u8;
+
+    if score == 100 {
+        return graded(100, "Correct.".to_string());
+    }
+
+    let key = correct
+        .iter()
+        .map(|&index| format!("{}) {}", option_label(index), options[index]))
+        .collect::<Vec<_>>()
+        .join("; ");
+    let verdict = if selected.is_empty() {
+        "No option was recognized; answer with the letter of your choice."
+    } else if score > 0 {
+        "Partly correct."
+    } else {
+        "Incorrect."
+    };
+    let key = if correct.len() > 1 {
+        format!("The correct answers are {}.", key)
+    } else {
+        format!("The correct answer is {}.", key)
+    };
+
+    graded(score, format!("{} {}", verdict, key))
+}
+
+// Options named in an answer by letter ("B", "a, c"), 1-based number or full text
+fn selected_options(options: &[String], user_answer: &str) -> Vec<usize> {
+    let given = normalize(user_answer);
+    if let Some(index) = options.iter().position(|option| normalize(option) == given) {
+        return vec![index];
+    }
+
+    let mut selected = Vec::new();
+    let tokens = user_answer
+        .split(|c: char| c == ',' || c == ';' || c == '/' || c.is_whitespace())
+        .map(|token| token.trim_matches(|c: char| !c.is_alphanumeric()))
+        .filter(|token| !token.is_empty());
+
+    for token in tokens {
+        let index = match token.parse::<usize>() {
+            Ok(number) => number.checked_sub(1),
+            Err(_) => {
+                let mut chars = token.chars();
+                match (chars.next(), chars.next()) {
+                    (Some(letter), None) if letter.is_ascii_alphabetic() => {
+                        Some(usize::from(letter.to_ascii_uppercase() as u8 - b'A'))
+                    }
+                    _ => None,
+                }
+            }
+        };
+
+        // Anything but option labels is prose, which is too ambiguous to grade
+        match index {
+            Some(index) if index < options.len() => {
+                if !selected.contains(&index) {
+                    selected.push(index);
+                }
+            }
+            _ => return Vec::new(),
+        }
+    }
+
+    selected
+}
+
+fn grade_true_false(answer: bool, user_answer: &str) -> AIEvaluation {
+    let key = format!("The statement is {}.", answer);
+    match parse_bool(user_answer) {
+        Some(given) if given == answer => graded(100, "Correct.".to_string()),
+        Some(_) => graded(0, format!("Incorrect. {}", key)),
+        None => graded(0, format!("Answer \"true\" or \"false\". {}", key)),
+    }
+}
+
+fn parse_bool(text: &str) -> Option<bool> {
+    match normalize(text).as_str() {
+        "true" | "t" | "yes" | "y" | "correct" | "right" | "1" | "对" | "正确" | "是" => Some(true),
+        "false" | "f" | "no" | "n" | "incorrect" | "wrong" | "0" | "错" | "错误" | "否" => Some(false),
+        _ => None,
+    }
+}
+
+// A single blank takes the whole answer; several are separated by newlines, semicolons or commas
+fn grade_cloze(answers: &[String], user_answer: &str) -> AIEvaluation {
+    let given: Vec<&str> = if answers.len() == 1 {
+        vec![user_answer]
+    } else {
+        user_answer
+            .split(['\n', ';', ',', '；', '，'])
+            .map(str::trim)
+            .filter(|part| !part.is_empty())
+            .collect()
+    };
+
+    let missed: Vec<String> = answers
+        .iter()
+        .enumerate()
+        .filter(|(i, expected)| given.get(*i).is_none_or(|given| normalize(given) != normalize(expected)))
+        .map(|(i, expected)| format!("{}. {}", i + 1, expected))
+        .collect();
+
+    if missed.is_empty() {
+        return graded(100, "Correct.".to_string());
+    }
+
+    let right = answers.len() - missed.len();
+    graded(
+        (right * 100 / answers.len()) as u8,
+        format!("{} of {} blanks correct. Expected: {}.", right, answers.len(), missed.join("; ")),
+    )
+}
+
+// Compare answers ignoring case, surrounding punctuation and repeated whitespace
+fn normalize(text: &str) -> String {
+    text.split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .trim_matches(|c: char| c.is_ascii_punctuation() || "。，！？；：“”‘’".contains(c))
+        .trim()
+        .to_lowercase()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn question_with(payload: QuestionPayload) -> Question {
+        Question::new("kb".to_string(), "Q?".to_string(), None).with_payload(&payload)
+    }
+
+    #[test]
+    fn test_parse_multiple_choice() {
+        let reply = "```json\n{\"question\": \"What frees memory in Rust?\", \"options\": [\"A GC\", \"Ownership\", \"Manual free\"], \"correct\": [1]}\n```";
+        let question = parse_question(QuestionType::MultipleChoice, reply).unwrap();
+        assert_eq!(question.question_text, "What frees memory in Rust?");
+        assert_eq!(
+            question.payload,
+            Some(QuestionPayload::MultipleChoice {
+                options: vec!["A GC".to_string(), "Ownership".to_string(), "Manual free".to_string()],
+                correct: vec![1],
+            })
+        );
+
+        assert!(matches!(
+            parse_question(QuestionType::MultipleChoice, r#"{"question": "Q?", "options": ["a", "b"], "correct": [2]}"#),
+            Err(QuestionParseError::InvalidField { field: "correct", .. })
+        ));
+        assert!(matches!(
+            parse_question(QuestionType::MultipleChoice, "Which is it?"),
+            Err(QuestionParseError::NoJson)
+        ));
+    }
+
+    #[test]
+    fn test_parse_cloze_blanks_terms() {
+        let question = parse_question(QuestionType::Cloze, r#"{"text": "Rust manages memory through [[ownership]] and [[ borrowing ]]."}"#).unwrap();
+        assert_eq!(question.question_text, "Rust manages memory through ____ and ____.");
+        assert_eq!(
+            question.payload,
+            Some(QuestionPayload::Cloze { answers: vec!["ownership".to_string(), "borrowing".to_string()] })
+        );
+
+        assert!(parse_question(QuestionType::Cloze, r#"{"text": "Nothing to fill in."}"#).is_err());
+    }
+
+    #[test]
+    fn test_parse_true_false_and_short_answer() {
+        let question = parse_question(QuestionType::TrueFalse, r#"{"statement": "Rust has a GC.", "answer": "false"}"#).unwrap();
+        assert_eq!(question.payload, Some(QuestionPayload::TrueFalse { answer: false }));
+
+        let question = parse_question(QuestionType::ShortAnswer, r#"{"question": "What moves a value?", "answer": "Assignment"}"#).unwrap();
+        assert_eq!(
+            question.payload,
+            Some(QuestionPayload::ShortAnswer { answer: "Assignment".to_string(), accepted: Vec::new() })
+        );
+    }
+
+    #[test]
+    fn test_grade_multiple_choice() {
+        let question = question_with(QuestionPayload::MultipleChoice {
+            options: vec!["A GC".to_string(), "Ownership".to_string(), "Manual free".to_string()],
+            correct: vec![1],
+        });
+
+        assert_eq!(grade(&question, "B").unwrap().score, Some(100));
+        assert_eq!(grade(&question, " b) ").unwrap().score, Some(100));
+        assert_eq!(grade(&question, "2").unwrap().score, Some(100));
+        assert_eq!(grade(&question, "ownership").unwrap().score, Some(100));
+
+        let wrong = grade(&question, "A").unwrap();
+        assert_eq!(wrong.score, Some(0));
+        assert_eq!(wrong.feedback, "Incorrect. The correct answer is B) Ownership.");
+
+        // Prose is never guessed at
+        let prose = grade(&question, "I think it is B").unwrap();
+        assert_eq!(prose.score, Some(0));
+        assert!(prose.feedback.starts_with("No option was recognized"));
+    }
+
+    #[test]
+    fn test_grade_multiple_select_partial_credit() {
+        let question = question_with(QuestionPayload::MultipleChoice {
+            options: vec!["a".to_string(), "b".to_string(), "c".to_string(), "d".to_string()],
+            correct: vec![0, 2],
+        });
+
+        assert_eq!(grade(&question, "A, C").unwrap().score, Some(100));
+        assert_eq!(grade(&question, "A").unwrap().score, Some(50));
+        assert_eq!(grade(&question, "A B").unwrap().score, Some(0));
+        assert_eq!(grade(&question, "a b c d").unwrap().score, Some(0));
+    }
+
+    #[test]
+    fn test_grade_true_false_and_cloze() {
+        let question = question_with(QuestionPayload::TrueFalse { answer: false });
+        assert_eq!(grade(&question, "False.").unwrap().score, Some(100));
+        assert_eq!(grade(&question, "错").unwrap().score, Some(100));
+        assert_eq!(grade(&question, "yes").unwrap().score, Some(0));
+
+        let question = question_with(QuestionPayload::Cloze {
+            answers: vec!["ownership".to_string(), "borrowing".to_string()],
+        });
+        assert_eq!(grade(&question, "Ownership; borrowing").unwrap().score, Some(100));
+        let partial = grade(&question, "ownership, lifetimes").unwrap();
+        assert_eq!(partial.score, Some(50));
+        assert_eq!(partial.feedback, "1 of 2 blanks correct. Expected: 2. borrowing.");
+    }
+
+    #[test]
+    fn test_short_answers_fall_back_to_ai_unless_matched() {
+        let question = question_with(QuestionPayload::ShortAnswer {
+            answer: "Ownership".to_string(),
+            accepted: vec!["the ownership system".to_string()],
+        });
+
+        assert_eq!(grade(&question, "ownership").unwrap().score, Some(100));
+        assert_eq!(grade(&question, "The  ownership system.").unwrap().score, Some(100));
+        assert!(grade(&question, "Owning values").is_none());
+
+        // Open questions always go to the AI
+        assert!(grade(&Question::new("kb".to_string(), "Q?".to_string(), None), "anything").is_none());
+    }
+
+    #[test]
+    fn test_check_answer_key() {
+        let cloze = QuestionPayload::Cloze {
+            answers: vec!["ownership".to_string(), "borrowing".to_string()],
+        };
+        assert!(check_answer_key("Rust uses ____ and ____.", &cloze).is_ok());
+        assert!(check_answer_key("Rust uses ____.", &cloze).is_err());
+
+        let choice = QuestionPayload::MultipleChoice {
+            options: vec!["Ownership".to_string(), "GC".to_string()],
+            correct: vec![2],
+        };
+        assert!(check_answer_key("Q?", &choice).is_err());
+
+        let short = QuestionPayload::ShortAnswer { answer: " ".to_string(), accepted: Vec::new() };
+        assert!(check_answer_key("Q?", &short).is_err());
+    }
+}
@@ -0,0 +1,154 @@
+use serde_json::Value;
+
+use crate::models::{QuestionPayload, ReferenceAnswer};
+use crate::services::ai::{AIError, Metered};
+use crate::services::evaluation::json_objects;
+use crate::services::prompts::Prompt;
+use crate::services::question_types::option_label;
+
+// More than this and grading turns into a checklist of trivia
+const MAX_KEY_POINTS: usize = 6;
+
+/// Prompt for the model answer to a generated question, from the passage it was generated from
+pub fn reference_prompt(context: &str, question: &str, language: &str) -> Prompt {
+    Prompt {
+        system: Some(format!(
+            "You are a professional educational assistant. Answer the question using only the learning material; learners' answers will be graded against your answer. Write in {}. Return only a JSON object: {{\"answer\": \"<model answer>\", \"key_points\": [\"<point a good answer must make>\", ...]}} with two to five key points.",
+            language
+        )),
+        user: format!("Learning material:\n{}\n\nQuestion: {}", context, question),
+    }
+}
+
+/// Parse a reference answer, tolerating code fences and surrounding prose
+pub fn parse_reference(reply: &str) -> Option<ReferenceAnswer> {
+    json_objects(reply).iter().find_map(|object| {
+        let answer = object.get("answer")?.as_str()?.trim();
+        if answer.is_empty() {
+            return None;
+        }
+
+        let key_points = match object.get("key_points") {
+            Some(Value::Array(points)) => points
+                .iter()
+                .filter_map(Value::as_str)
+                .map(str::trim)
+                .filter(|point| !point.is_empty())
+                .take(MAX_KEY_POINTS)
+                .map(str::to_string)
+                .collect(),
+            _ => Vec::new(),
+        };
+
+        Some(ReferenceAnswer {
+            answer: answer.to_string(),
+            key_points,
+        })
+    })
+}
+
+/// Parse a model reply, reporting an unusable one as an invalid response
+pub fn parse_reply(reply: Metered<String>) -> Result<Metered<ReferenceAnswer>, AIError> {
+    match parse_reference(&reply.value) {
+        Some(reference) => Ok(Metered::new(reference, reply.usage)),
+        None => Err(AIError::InvalidResponse("Unusable reference answer: expected a JSON object with an answer".to_string())),
+    }
+}
+
+/// The reference answer of a structured question, spelled out from its answer key
+pub fn from_answer_key(payload: &QuestionPayload) -> ReferenceAnswer {
+    let answer = match payload {
+        QuestionPayload::MultipleChoice { options, correct } => correct
+            .iter()
+            .filter_map(|&index| Some(format!("{}. {}", option_label(index), options.get(index)?)))
+            .collect::<Vec<_>>()
+            .join("; "),
+        QuestionPayload::TrueFalse { answer } => if *answer { "True" } else { "False" }.to_string(),
+        QuestionPayload::Cloze { answers } => answers
+            .iter()
+            .enumerate()
+            .map(|(i, answer)| format!("{}. {}", i + 1, answer))
+            .collect::<Vec<_>>()
+            .join("; "),
+        QuestionPayload::ShortAnswer { answer, .. } => answer.clone(),
+    };
+
+    ReferenceAnswer {
+        answer,
+        key_points: Vec::new(),
+    }
+}
+
+/// Text substituted for `{{reference}}` in evaluation templates, labelled in `language`, the
+/// language resolved for the knowledge base; empty when there is no reference
+pub fn evaluation_reference(reference: Option<&ReferenceAnswer>, language: &str) -> String {
+    let Some(reference) = reference else {
+        return String::new();
+    };
+
+    let (answer_label, key_points_label) = reference_labels(language);
+    let mut text = format!("{}{}\n", answer_label, reference.answer);
+    if !reference.key_points.is_empty() {
+        text.push_str(key_points_label);
+        text.push('\n');
+        for point in &reference.key_points {
+            text.push_str(&format!("- {}\n", point));
+        }
+    }
+    text.push('\n');
+    text
+}
+
+// Chinese labels when the feedback is in Chinese, English labels for every other language
+fn reference_labels(language: &str) -> (&'static str, &'static str) {
+    match language {
+        "Chinese" => ("参考答案：", "要点："),
+        _ => ("Reference answer: ", "Key points:"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_reference() {
+        let reply = "Here you go:\n```json\n{\"answer\": \" Ownership frees memory. \", \"key_points\": [\"Each value has one owner\", \"\", 3]}\n```";
+        let reference = parse_reference(reply).unwrap();
+        assert_eq!(reference.answer, "Ownership frees memory.");
+        assert_eq!(reference.key_points, vec!["Each value has one owner"]);
+
+        assert!(parse_reference("{\"answer\": \"\"}").is_none());
+        assert!(parse_reference("Ownership frees memory.").is_none());
+    }
+
+    #[test]
+    fn test_reference_from_answer_key() {
+        let choice = QuestionPayload::MultipleChoice {
+            options: vec!["GC".to_string(), "Ownership".to_string(), "Borrowing".to_string()],
+            correct: vec![1, 2],
+        };
+        assert_eq!(from_answer_key(&choice).answer, "B. Ownership; C. Borrowing");
+
+        let cloze = QuestionPayload::Cloze { answers: vec!["ownership".to_string(), "borrowing".to_string()] };
+        assert_eq!(from_answer_key(&cloze).answer, "1. ownership; 2. borrowing");
+    }
+
+    #[test]
+    fn test_evaluation_reference() {
+        assert_eq!(evaluation_reference(None, "English"), "");
+
+        let reference = ReferenceAnswer {
+            answer: "Through ownership.".to_string(),
+            key_points: vec!["Each value has one owner".to_string()],
+        };
+        assert_eq!(
+            evaluation_reference(Some(&reference), "English"),
+            "Reference answer: Through ownership.\nKey points:\n- Each value has one owner\n\n"
+        );
+        assert_eq!(
+            evaluation_reference(Some(&reference), "Chinese"),
+            "参考答案：Through ownership.\n要点：\n- Each value has one owner\n\n"
+        );
+    }
+}
@@ -0,0 +1,487 @@
+// Timeouts, retries and circuit breaking for outbound AI requests.
+//
+// Every provider sends its HTTP requests through a `ResilientClient`. A request
+// that times out, fails to connect or gets a retryable status (429, 5xx gateway
+// errors) is retried with exponential backoff and full jitter, waiting for the
+// server's `Retry-After` instead when it sends one. Calls that still fail count
+// towards a circuit breaker, as do rejected credentials (401, 403); other client
+// errors count neither way. Once it trips, calls fail fast until a cool-down has
+// passed, after which a single trial call decides whether it closes again.
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use rand::Rng;
+use reqwest::{RequestBuilder, Response, StatusCode};
+
+use crate::services::ai::AIError;
+
+pub const DEFAULT_CONNECT_TIMEOUT_SECS: u64 = 10;
+pub const DEFAULT_REQUEST_TIMEOUT_SECS: u64 = 120;
+pub const DEFAULT_MAX_RETRIES: u32 = 2;
+
+#[derive(Debug, Clone)]
+pub struct ResilienceConfig {
+    pub connect_timeout: Duration,
+    /// Upper bound on a single attempt: on waiting for the response headers, and for
+    /// requests sent with `send_buffered` on reading the body as well. Streamed bodies
+    /// are not limited, so streamed replies can take as long as they need
+    pub request_timeout: Duration,
+    /// Retries after the first attempt
+    pub max_retries: u32,
+    pub initial_backoff: Duration,
+    pub max_backoff: Duration,
+    /// Consecutive failed calls that open the circuit
+    pub failure_threshold: u32,
+    /// How long an open circuit rejects calls before allowing a trial call
+    pub open_duration: Duration,
+}
+
+impl Default for ResilienceConfig {
+    fn default() -> Self {
+        Self {
+            connect_timeout: Duration::from_secs(DEFAULT_CONNECT_TIMEOUT_SECS),
+            request_timeout: Duration::from_secs(DEFAULT_REQUEST_TIMEOUT_SECS),
+            max_retries: DEFAULT_MAX_RETRIES,
+            initial_backoff: Duration::from_millis(500),
+            max_backoff: Duration::from_secs(30),
+            failure_threshold: 5,
+            open_duration: Duration::from_secs(30),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum CircuitState {
+    Closed,
+    Open { until: Instant },
+    // A trial call is in flight; other calls are rejected until it settles,
+    // or until another cool-down passes in case the trial call was abandoned
+    HalfOpen { since: Instant },
+}
+
+// What a finished call says about the provider's health
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Outcome {
+    Success,
+    Failure,
+    // A client error: the provider answered, but that says nothing about later calls
+    Inconclusive,
+}
+
+#[derive(Debug)]
+struct CircuitBreaker {
+    state: CircuitState,
+    consecutive_failures: u32,
+}
+
+/// HTTP client with timeouts, retries and a circuit breaker shared by its clones
+#[derive(Debug, Clone)]
+pub struct ResilientClient {
+    client: reqwest::Client,
+    config: ResilienceConfig,
+    breaker: Arc<Mutex<CircuitBreaker>>,
+}
+
+impl Default for ResilientClient {
+    fn default() -> Self {
+        Self::new(ResilienceConfig::default())
+    }
+}
+
+impl ResilientClient {
+    pub fn new(config: ResilienceConfig) -> Self {
+        let client = reqwest::Client::builder()
+            .connect_timeout(config.connect_timeout)
+            .build()
+            .unwrap_or_default();
+
+        Self {
+            client,
+            config,
+            breaker: Arc::new(Mutex::new(CircuitBreaker {
+                state: CircuitState::Closed,
+                consecutive_failures: 0,
+            })),
+        }
+    }
+
+    /// The underlying client, for building requests to pass to `send`
+    pub fn client(&self) -> &reqwest::Client {
+        &self.client
+    }
+
+    /// Send a request whose body is read in full, so the request timeout also bounds
+    /// reading it; a body that stalls fails with a timeout error when it is read.
+    pub async fn send_buffered(&self, request: RequestBuilder) -> Result<Response, AIError> {
+        self.send(request.timeout(self.config.request_timeout)).await
+    }
+
+    /// Send a request, retrying transient failures. Non-retryable error statuses
+    /// are returned as responses for the caller to interpret.
+    pub async fn send(&self, request: RequestBuilder) -> Result<Response, AIError> {
+        self.acquire()?;
+
+        let mut attempt = 0;
+        loop {
+            let attempt_request = request
+                .try_clone()
+                .ok_or_else(|| AIError::ConfigError("Request body cannot be retried".to_string()))?;
+
+            // Only the wait for the headers is bounded, so a streamed body is never cut off
+            let sent = tokio::time::timeout(self.config.request_timeout, attempt_request.send()).await;
+
+            let (error, retry_after) = match sent {
+                Ok(Ok(response)) if !is_retryable_status(response.status()) => {
+                    self.record(status_outcome(response.status()));
+                    return Ok(response);
+                }
+                Ok(Ok(response)) => {
+                    let retry_after = parse_retry_after(&response);
+                    (status_error(response, retry_after).await, retry_after)
+                }
+                Ok(Err(e)) if e.is_timeout() => (AIError::Timeout(e.to_string()), None),
+                Ok(Err(e)) if e.is_connect() || e.is_request() => (AIError::HttpError(e), None),
+                Ok(Err(e)) => {
+                    self.record(Outcome::Failure);
+                    return Err(AIError::HttpError(e));
+                }
+                Err(_) => (
+                    AIError::Timeout(format!("No response within {:?}", self.config.request_timeout)),
+                    None,
+                ),
+            };
+
+            if attempt >= self.config.max_retries {
+                self.record(Outcome::Failure);
+                return Err(if attempt == 0 {
+                    error
+                } else {
+                    AIError::RetriesExhausted {
+                        attempts: attempt + 1,
+                        last_error: Box::new(error),
+                    }
+                });
+            }
+
+            let delay = retry_after
+                .map(|delay| delay.min(self.config.max_backoff))
+                .unwrap_or_else(|| self.backoff(attempt));
+            tracing::warn!("AI request failed ({}), retrying in {:?}", error, delay);
+            tokio::time::sleep(delay).await;
+            attempt += 1;
+        }
+    }
+
+    // Exponential backoff with full jitter: uniform in [0, min(max, initial * 2^attempt)]
+    fn backoff(&self, attempt: u32) -> Duration {
+        let ceiling = self
+            .config
+            .initial_backoff
+            .saturating_mul(2u32.saturating_pow(attempt))
+            .min(self.config.max_backoff);
+        ceiling.mul_f64(rand::thread_rng().gen_range(0.0..=1.0))
+    }
+
+    // Reject the call while the circuit is open; let one trial call through once it may close
+    fn acquire(&self) -> Result<(), AIError> {
+        let mut breaker = self.breaker.lock().unwrap();
+        match breaker.state {
+            CircuitState::Closed => Ok(()),
+            CircuitState::Open { until } if Instant::now() >= until => {
+                breaker.state = CircuitState::HalfOpen { since: Instant::now() };
+                Ok(())
+            }
+            CircuitState::HalfOpen { since } if since.elapsed() >= self.config.open_duration => {
+                breaker.state = CircuitState::HalfOpen { since: Instant::now() };
+                Ok(())
+            }
+            CircuitState::Open { until } => Err(AIError::CircuitOpen {
+                retry_in: until.saturating_duration_since(Instant::now()),
+            }),
+            CircuitState::HalfOpen { since } => Err(AIError::CircuitOpen {
+                retry_in: self.config.open_duration.saturating_sub(since.elapsed()),
+            }),
+        }
+    }
+
+    fn record(&self, outcome: Outcome) {
+        let mut breaker = self.breaker.lock().unwrap();
+        match outcome {
+            Outcome::Success => {
+                breaker.state = CircuitState::Closed;
+                breaker.consecutive_failures = 0;
+                return;
+            }
+            // A trial call that settles nothing hands the trial to the next call
+            Outcome::Inconclusive => {
+                if matches!(breaker.state, CircuitState::HalfOpen { .. }) {
+                    breaker.state = CircuitState::Open { until: Instant::now() };
+                }
+                return;
+            }
+            Outcome::Failure => {}
+        }
+
+        breaker.consecutive_failures += 1;
+        let trial_failed = matches!(breaker.state, CircuitState::HalfOpen { .. });
+        if trial_failed || breaker.consecutive_failures >= self.config.failure_threshold {
+            breaker.state = CircuitState::Open {
+                until: Instant::now() + self.config.open_duration,
+            };
+        }
+    }
+}
+
+// 529 is Anthropic's "overloaded" status
+fn is_retryable_status(status: StatusCode) -> bool {
+    matches!(status.as_u16(), 408 | 429 | 500 | 502 | 503 | 504 | 529)
+}
+
+// Rejected credentials fail every later call too, unlike other client errors
+fn status_outcome(status: StatusCode) -> Outcome {
+    if status.is_success() {
+        Outcome::Success
+    } else if matches!(status, StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN) {
+        Outcome::Failure
+    } else {
+        Outcome::Inconclusive
+    }
+}
+
+// `Retry-After` is either a number of seconds or an HTTP date
+fn parse_retry_after(response: &Response) -> Option<Duration> {
+    let value = response.headers().get(reqwest::header::RETRY_AFTER)?.to_str().ok()?.trim();
+
+    if let Ok(seconds) = value.parse::<u64>() {
+        return Some(Duration::from_secs(seconds));
+    }
+
+    let date = chrono::DateTime::parse_from_rfc2822(value).ok()?;
+    (date.with_timezone(&chrono::Utc) - chrono::Utc::now()).to_std().ok().or(Some(Duration::ZERO))
+}
+
+async fn status_error(response: Response, retry_after: Option<Duration>) -> AIError {
+    let status = response.status().as_u16();
+    if status == 429 {
+        return AIError::RateLimited { retry_after };
+    }
+
+    let message = response.text().await.unwrap_or_else(|_| "Unknown error".to_string());
+    AIError::ApiError { status, message }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use axum::{http::StatusCode as AxumStatus, routing::post, Router};
+
+    fn fast_config() -> ResilienceConfig {
+        ResilienceConfig {
+            connect_timeout: Duration::from_secs(1),
+            request_timeout: Duration::from_millis(200),
+            max_retries: 2,
+            initial_backoff: Duration::from_millis(1),
+            max_backoff: Duration::from_millis(5),
+            failure_threshold: 2,
+            open_duration: Duration::from_millis(100),
+        }
+    }
+
+    // Serve `/` with a status chosen per request from `statuses` (the last one repeats)
+    async fn spawn_server(statuses: Vec<u16>, delay: Duration, hits: Arc<AtomicUsize>) -> String {
+        let app = Router::new().route(
+            "/",
+            post(move || {
+                let statuses = statuses.clone();
+                let hits = hits.clone();
+                async move {
+                    let n = hits.fetch_add(1, Ordering::SeqCst);
+                    tokio::time::sleep(delay).await;
+                    let status = statuses[n.min(statuses.len() - 1)];
+                    (
+                        AxumStatus::from_u16(status).unwrap(),
+                        [("retry-after", "0")],
+                        format!("status {}", status),
+                    )
+                }
+            }),
+        );
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            axum::serve(listener, app).await.unwrap();
+        });
+
+        format!("http://{}/", addr)
+    }
+
+    #[tokio::test]
+    async fn test_retries_transient_statuses() {
+        let hits = Arc::new(AtomicUsize::new(0));
+        let url = spawn_server(vec![503, 429, 200], Duration::ZERO, hits.clone()).await;
+        let http = ResilientClient::new(fast_config());
+
+        let response = http.send(http.client().post(&url).body("{}")).await.unwrap();
+        assert_eq!(response.status(), 200);
+        assert_eq!(hits.load(Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test]
+    async fn test_non_retryable_status_returned_immediately() {
+        let hits = Arc::new(AtomicUsize::new(0));
+        let url = spawn_server(vec![401], Duration::ZERO, hits.clone()).await;
+        let http = ResilientClient::new(fast_config());
+
+        let response = http.send(http.client().post(&url)).await.unwrap();
+        assert_eq!(response.status(), 401);
+        assert_eq!(hits.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn test_retries_exhausted_and_rate_limited() {
+        let hits = Arc::new(AtomicUsize::new(0));
+        let url = spawn_server(vec![429], Duration::ZERO, hits.clone()).await;
+        let http = ResilientClient::new(fast_config());
+
+        match http.send(http.client().post(&url)).await {
+            Err(AIError::RetriesExhausted { attempts, last_error }) => {
+                assert_eq!(attempts, 3);
+                assert!(matches!(*last_error, AIError::RateLimited { retry_after: Some(d) } if d.is_zero()));
+            }
+            other => panic!("Expected exhausted retries, got {:?}", other),
+        }
+        assert_eq!(hits.load(Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test]
+    async fn test_request_timeout() {
+        let hits = Arc::new(AtomicUsize::new(0));
+        let url = spawn_server(vec![200], Duration::from_secs(2), hits).await;
+        let http = ResilientClient::new(ResilienceConfig {
+            max_retries: 0,
+            ..fast_config()
+        });
+
+        assert!(matches!(http.send(http.client().post(&url)).await, Err(AIError::Timeout(_))));
+    }
+
+    #[tokio::test]
+    async fn test_streamed_body_outlives_request_timeout() {
+        use axum::body::Body;
+        use tokio_stream::wrappers::ReceiverStream;
+
+        // Headers go out at once; the body trickles in over longer than the request timeout
+        let app = Router::new().route(
+            "/",
+            post(|| async {
+                let (chunks, body) = tokio::sync::mpsc::channel::<Result<String, std::io::Error>>(4);
+                tokio::spawn(async move {
+                    for i in 0..3 {
+                        tokio::time::sleep(Duration::from_millis(150)).await;
+                        let _ = chunks.send(Ok(format!("chunk {}\n", i))).await;
+                    }
+                });
+                Body::from_stream(ReceiverStream::new(body))
+            }),
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            axum::serve(listener, app).await.unwrap();
+        });
+
+        let http = ResilientClient::new(ResilienceConfig {
+            max_retries: 0,
+            ..fast_config()
+        });
+        let response = http.send(http.client().post(format!("http://{}/", addr))).await.unwrap();
+        assert_eq!(response.text().await.unwrap(), "chunk 0\nchunk 1\nchunk 2\n");
+    }
+
+    #[tokio::test]
+    async fn test_buffered_body_is_bounded_by_request_timeout() {
+        use axum::body::Body;
+        use tokio_stream::wrappers::ReceiverStream;
+
+        // Headers go out at once, then the body never arrives
+        let app = Router::new().route(
+            "/",
+            post(|| async {
+                let (chunks, body) = tokio::sync::mpsc::channel::<Result<String, std::io::Error>>(1);
+                tokio::spawn(async move {
+                    tokio::time::sleep(Duration::from_secs(5)).await;
+                    drop(chunks);
+                });
+                Body::from_stream(ReceiverStream::new(body))
+            }),
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            axum::serve(listener, app).await.unwrap();
+        });
+
+        let http = ResilientClient::new(ResilienceConfig {
+            max_retries: 0,
+            ..fast_config()
+        });
+        let started = Instant::now();
+        let response = http.send_buffered(http.client().post(format!("http://{}/", addr))).await.unwrap();
+        let error = response.text().await.unwrap_err();
+        assert!(error.is_timeout());
+        assert!(started.elapsed() < Duration::from_secs(2));
+    }
+
+    #[tokio::test]
+    async fn test_client_errors_and_the_circuit_breaker() {
+        let config = ResilienceConfig {
+            max_retries: 0,
+            ..fast_config()
+        };
+
+        // A bad request neither trips the breaker nor resets its count of failures
+        let hits = Arc::new(AtomicUsize::new(0));
+        let url = spawn_server(vec![500, 400, 500, 200], Duration::ZERO, hits.clone()).await;
+        let http = ResilientClient::new(config.clone());
+        assert!(http.send(http.client().post(&url)).await.is_err());
+        assert_eq!(http.send(http.client().post(&url)).await.unwrap().status(), 400);
+        assert!(http.send(http.client().post(&url)).await.is_err());
+        assert!(matches!(http.send(http.client().post(&url)).await, Err(AIError::CircuitOpen { .. })));
+        assert_eq!(hits.load(Ordering::SeqCst), 3);
+
+        // Rejected credentials count as failures, though the response is still returned
+        let hits = Arc::new(AtomicUsize::new(0));
+        let url = spawn_server(vec![401], Duration::ZERO, hits.clone()).await;
+        let http = ResilientClient::new(config);
+        for _ in 0..2 {
+            assert_eq!(http.send(http.client().post(&url)).await.unwrap().status(), 401);
+        }
+        assert!(matches!(http.send(http.client().post(&url)).await, Err(AIError::CircuitOpen { .. })));
+        assert_eq!(hits.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn test_circuit_breaker_opens_and_recovers() {
+        let hits = Arc::new(AtomicUsize::new(0));
+        let url = spawn_server(vec![500, 500, 200], Duration::ZERO, hits.clone()).await;
+        let http = ResilientClient::new(ResilienceConfig {
+            max_retries: 0,
+            ..fast_config()
+        });
+
+        // Two failed calls trip the breaker
+        for _ in 0..2 {
+            assert!(matches!(http.send(http.client().post(&url)).await, Err(AIError::ApiError { status: 500, .. })));
+        }
+        assert!(matches!(http.send(http.client().post(&url)).await, Err(AIError::CircuitOpen { .. })));
+        assert_eq!(hits.load(Ordering::SeqCst), 2);
+
+        // After the cool-down a trial call goes through and closes the circuit
+        tokio::time::sleep(Duration::from_millis(150)).await;
+        assert_eq!(http.send(http.client().post(&url)).await.unwrap().status(), 200);
+        assert_eq!(http.send(http.client().post(&url)).await.unwrap().status(), 200);
+    }
+}
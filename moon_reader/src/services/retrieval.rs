@@ -0,0 +1,787 @@
+// Local semantic retrieval over document chunks.
+//
+// Documents are split into passage-sized chunks, and every chunk is encoded
+// into a fixed-size hashed term vector that is stored in SQLite. At query time
+// the vectors are re-weighted with inverse document frequencies computed over
+// the knowledge base, so ranking is plain TF-IDF cosine similarity. Everything
+// runs in-process on the CPU and needs no external service or model files.
+use std::collections::HashMap;
+
+use rand::seq::SliceRandom;
+use serde::{Deserialize, Serialize};
+
+use crate::database::DatabaseManager;
+use crate::models::{ChunkCoverage, Document, DocumentChunk, DocumentType, Question};
+use crate::parsers::PAGE_BREAK;
+
+/// Number of hashed feature buckets per chunk vector
+pub const EMBEDDING_DIMENSIONS: usize = 1024;
+
+/// Default upper bound on chunk length, in characters
+pub const DEFAULT_CHUNK_CHARS: usize = 800;
+
+/// Related passages retrieved alongside the source passage when evaluating an answer
+pub const DEFAULT_EVIDENCE_PASSAGES: usize = 3;
+
+/// A span of a document's text produced by the chunker
+#[derive(Debug, Clone, PartialEq)]
+pub struct TextChunk {
+    pub content: String,
+    pub start_offset: usize,
+    pub end_offset: usize,
+}
+
+/// Splits document text into passages along paragraph and sentence boundaries
+pub struct TextChunker;
+
+impl TextChunker {
+    pub fn chunk(text: &str, max_chars: usize) -> Vec<TextChunk> {
+        let chars: Vec<char> = text.chars().collect();
+        let max_chars = max_chars.max(1);
+
+        // Break the text into units no longer than max_chars
+        let mut units = Vec::new();
+        for (start, end) in Self::paragraph_spans(&chars) {
+            if end - start <= max_chars {
+                units.push((start, end));
+                continue;
+            }
+
+            for (s_start, s_end) in Self::sentence_spans(&chars, start, end) {
+                let mut pos = s_start;
+                while s_end - pos > max_chars {
+                    // Prefer cutting at whitespace so words stay intact
+                    let cut = (pos + max_chars / 2..pos + max_chars)
+                        .rev()
+                        .find(|&i| chars[i].is_whitespace())
+                        .map(|i| i + 1)
+                        .unwrap_or(pos + max_chars);
+                    units.push((pos, cut));
+                    pos = cut;
+                }
+                units.push((pos, s_end));
+            }
+        }
+
+        // Greedily pack consecutive units into chunks
+        let mut chunks = Vec::new();
+        let mut current: Option<(usize, usize)> = None;
+        for (start, end) in units {
+            current = match current {
+                Some((c_start, _)) if end - c_start <= max_chars => Some((c_start, end)),
+                Some(span) => {
+                    Self::push_trimmed(&chars, span, &mut chunks);
+                    Some((start, end))
+                }
+                None => Some((start, end)),
+            };
+        }
+        if let Some(span) = current {
+            Self::push_trimmed(&chars, span, &mut chunks);
+        }
+
+        chunks
+    }
+
+    // Spans separated by one or more blank lines
+    fn paragraph_spans(chars: &[char]) -> Vec<(usize, usize)> {
+        let mut spans = Vec::new();
+        let mut start = 0;
+        let mut i = 0;
+
+        while i < chars.len() {
+            if chars[i] == '\n' {
+                let mut j = i + 1;
+                while j < chars.len() && chars[j] != '\n' && chars[j].is_whitespace() {
+                    j += 1;
+                }
+                if j < chars.len() && chars[j] == '\n' {
+                    if i > start {
+                        spans.push((start, i));
+                    }
+                    while j < chars.len() && chars[j].is_whitespace() {
+                        j += 1;
+                    }
+                    start = j;
+                    i = j;
+                    continue;
+                }
+            }
+            i += 1;
+        }
+        if start < chars.len() {
+            spans.push((start, chars.len()));
+        }
+
+        spans
+    }
+
+    // Spans ending after sentence punctuation (Latin and CJK)
+    fn sentence_spans(chars: &[char], start: usize, end: usize) -> Vec<(usize, usize)> {
+        let mut spans = Vec::new();
+        let mut s_start = start;
+
+        for (i, ch) in chars.iter().enumerate().take(end).skip(start) {
+            if matches!(ch, '.' | '!' | '?' | '。' | '！' | '？' | '；' | '\n') {
+                spans.push((s_start, i + 1));
+                s_start = i + 1;
+            }
+        }
+        if s_start < end {
+            spans.push((s_start, end));
+        }
+
+        spans
+    }
+
+    fn push_trimmed(chars: &[char], (mut start, mut end): (usize, usize), chunks: &mut Vec<TextChunk>) {
+        while start < end && chars[start].is_whitespace() {
+            start += 1;
+        }
+        while end > start && chars[end - 1].is_whitespace() {
+            end -= 1;
+        }
+        if start < end {
+            chunks.push(TextChunk {
+                content: chars[start..end].iter().collect(),
+                start_offset: start,
+                end_offset: end,
+            });
+        }
+    }
+}
+
+/// Encodes text into hashed, sublinear term-frequency vectors
+pub struct HashingEmbedder;
+
+impl HashingEmbedder {
+    /// Lowercased alphanumeric words, plus character bigrams for CJK runs
+    pub fn tokenize(text: &str) -> Vec<String> {
+        let mut tokens = Vec::new();
+        let mut word = String::new();
+        let mut cjk_run: Vec<char> = Vec::new();
+
+        let flush_cjk = |run: &mut Vec<char>, tokens: &mut Vec<String>| {
+            if run.len() == 1 {
+                tokens.push(run[0].to_string());
+            } else {
+                for pair in run.windows(2) {
+                    tokens.push(pair.iter().collect());
+                }
+            }
+            run.clear();
+        };
+
+        for ch in text.chars() {
+            if Self::is_cjk(ch) {
+                if !word.is_empty() {
+                    tokens.push(std::mem::take(&mut word));
+                }
+                cjk_run.push(ch);
+            } else if ch.is_alphanumeric() {
+                if !cjk_run.is_empty() {
+                    flush_cjk(&mut cjk_run, &mut tokens);
+                }
+                word.extend(ch.to_lowercase());
+            } else {
+                if !word.is_empty() {
+                    tokens.push(std::mem::take(&mut word));
+                }
+                if !cjk_run.is_empty() {
+                    flush_cjk(&mut cjk_run, &mut tokens);
+                }
+            }
+        }
+        if !word.is_empty() {
+            tokens.push(word);
+        }
+        if !cjk_run.is_empty() {
+            flush_cjk(&mut cjk_run, &mut tokens);
+        }
+
+        tokens
+    }
+
+    pub fn embed(text: &str) -> Vec<f32> {
+        let mut vector = vec![0.0f32; EMBEDDING_DIMENSIONS];
+        for token in Self::tokenize(text) {
+            vector[Self::bucket(&token)] += 1.0;
+        }
+        for value in vector.iter_mut() {
+            if *value > 0.0 {
+                *value = 1.0 + value.ln();
+            }
+        }
+        vector
+    }
+
+    /// Cosine similarity of two embeddings, 0 when either is empty
+    pub fn cosine(a: &[f32], b: &[f32]) -> f32 {
+        let dot: f32 = a.iter().zip(b).map(|(x, y)| x * y).sum();
+        let norm_a = a.iter().map(|x| x * x).sum::<f32>().sqrt();
+        let norm_b = b.iter().map(|x| x * x).sum::<f32>().sqrt();
+        if norm_a == 0.0 || norm_b == 0.0 {
+            0.0
+        } else {
+            dot / (norm_a * norm_b)
+        }
+    }
+
+    pub fn to_bytes(vector: &[f32]) -> Vec<u8> {
+        vector.iter().flat_map(|v| v.to_le_bytes()).collect()
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Vec<f32> {
+        bytes
+            .chunks_exact(4)
+            .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+            .collect()
+    }
+
+    // FNV-1a, so buckets stay stable across processes and releases
+    fn bucket(token: &str) -> usize {
+        let mut hash: u64 = 0xcbf29ce484222325;
+        for byte in token.as_bytes() {
+            hash ^= *byte as u64;
+            hash = hash.wrapping_mul(0x100000001b3);
+        }
+        (hash % EMBEDDING_DIMENSIONS as u64) as usize
+    }
+
+    fn is_cjk(ch: char) -> bool {
+        matches!(ch,
+            '\u{3040}'..='\u{30FF}' // Hiragana and Katakana
+            | '\u{3400}'..='\u{4DBF}' // CJK Extension A
+            | '\u{4E00}'..='\u{9FFF}' // CJK Unified Ideographs
+            | '\u{AC00}'..='\u{D7AF}' // Hangul syllables
+            | '\u{F900}'..='\u{FAFF}' // CJK Compatibility Ideographs
+        )
+    }
+}
+
+/// How to pick the passage a new question is generated from
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SourceStrategy {
+    /// Any chunk, uniformly at random
+    Random,
+    /// A chunk with the fewest questions generated from it so far
+    #[default]
+    LeastCovered,
+    /// An unquizzed chunk if any, otherwise the one with the lowest average answer score
+    Weakest,
+}
+
+impl SourceStrategy {
+    // Lower sorts first: unquizzed chunks, then answered chunks by average score,
+    // then chunks whose questions have not been answered yet
+    fn weakness(coverage: Option<&ChunkCoverage>) -> (u8, f64) {
+        match coverage {
+            None => (0, 0.0),
+            Some(c) if c.question_count == 0 => (0, 0.0),
+            Some(c) => match c.average_score {
+                Some(score) => (1, score),
+                None => (2, 0.0),
+            },
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SearchHit {
+    pub chunk: DocumentChunk,
+    pub score: f32,
+}
+
+/// A passage an answer was evaluated against
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct EvidencePassage {
+    pub chunk_id: Option<String>,
+    pub document_id: Option<String>,
+    pub page_number: Option<i32>,
+    pub content: String,
+    /// Similarity to the question; `None` for the question's own source passage
+    pub score: Option<f32>,
+    /// Whether this is the passage the question was generated from
+    pub is_source: bool,
+}
+
+impl EvidencePassage {
+    fn from_chunk(chunk: DocumentChunk, score: Option<f32>, is_source: bool) -> Self {
+        Self {
+            chunk_id: Some(chunk.id),
+            document_id: Some(chunk.document_id),
+            page_number: chunk.page_number,
+            content: chunk.content,
+            score,
+            is_source,
+        }
+    }
+
+    /// Render passages as numbered reference material for an evaluation prompt
+    pub fn format_context(passages: &[EvidencePassage]) -> String {
+        passages
+            .iter()
+            .enumerate()
+            .map(|(i, passage)| match passage.page_number {
+                Some(page) => format!("[{}] (p. {})\n{}", i + 1, page, passage.content),
+                None => format!("[{}]\n{}", i + 1, passage.content),
+            })
+            .collect::<Vec<_>>()
+            .join("\n\n")
+    }
+}
+
+/// Indexes documents into chunks and answers top-k similarity queries
+pub struct Retriever;
+
+impl Retriever {
+    /// (Re)build the chunk index for a single document
+    pub async fn index_document(db: &DatabaseManager, document: &Document) -> Result<usize, sqlx::Error> {
+        let content = document.content_text.as_deref().unwrap_or_default();
+        let paginated = matches!(document.file_type, DocumentType::Pdf);
+
+        // Character offsets at which each page after the first begins
+        let page_starts: Vec<usize> = content
+            .chars()
+            .enumerate()
+            .filter(|(_, ch)| *ch == PAGE_BREAK)
+            .map(|(i, _)| i + 1)
+            .collect();
+
+        let chunks: Vec<(DocumentChunk, Vec<u8>)> = TextChunker::chunk(content, DEFAULT_CHUNK_CHARS)
+            .into_iter()
+            .enumerate()
+            .map(|(index, chunk)| {
+                let embedding = HashingEmbedder::to_bytes(&HashingEmbedder::embed(&chunk.content));
+                let mut stored = DocumentChunk::new(
+                    document.id.clone(),
+                    document.knowledge_base_id.clone(),
+                    index as i32,
+                    chunk.content,
+                    chunk.start_offset as i64,
+                    chunk.end_offset as i64,
+                );
+                if paginated {
+                    let page = page_starts.partition_point(|&start| start <= chunk.start_offset) + 1;
+                    stored.page_number = Some(page as i32);
+                }
+                (stored, embedding)
+            })
+            .collect();
+
+        db.replace_document_chunks(&document.id, &chunks).await?;
+
+        Ok(chunks.len())
+    }
+
+    /// Index any documents in the knowledge base that have no chunks yet
+    pub async fn ensure_indexed(db: &DatabaseManager, knowledge_base_id: &str) -> Result<(), sqlx::Error> {
+        for document_id in db.get_unindexed_documents(knowledge_base_id).await? {
+            if let Some(document) = db.get_document_by_id(&document_id).await? {
+                let count = Self::index_document(db, &document).await?;
+                tracing::info!("Indexed document {} into {} chunks", document_id, count);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Return the top_k chunks of a knowledge base most similar to the query
+    pub async fn search(
+        db: &DatabaseManager,
+        knowledge_base_id: &str,
+        query: &str,
+        top_k: usize,
+    ) -> Result<Vec<SearchHit>, sqlx::Error> {
+        Self::ensure_indexed(db, knowledge_base_id).await?;
+
+        let candidates: Vec<(DocumentChunk, Vec<f32>)> = db
+            .get_chunk_embeddings_by_knowledge_base(knowledge_base_id)
+            .await?
+            .into_iter()
+            .map(|(chunk, bytes)| (chunk, HashingEmbedder::from_bytes(&bytes)))
+            .collect();
+
+        Ok(Self::rank(&HashingEmbedder::embed(query), candidates, top_k))
+    }
+
+    /// Pick a chunk to generate a question from, optionally restricted to one document
+    pub async fn select_source(
+        db: &DatabaseManager,
+        knowledge_base_id: &str,
+        strategy: SourceStrategy,
+        document_id: Option<&str>,
+    ) -> Result<Option<DocumentChunk>, sqlx::Error> {
+        Self::ensure_indexed(db, knowledge_base_id).await?;
+
+        let chunks = db.get_chunks_by_knowledge_base(knowledge_base_id, document_id).await?;
+        let counts = match strategy {
+            SourceStrategy::LeastCovered => db.get_question_counts_by_chunk(knowledge_base_id).await?,
+            _ => Default::default(),
+        };
+        let coverage: HashMap<String, ChunkCoverage> = match strategy {
+            SourceStrategy::Weakest => db
+                .get_chunk_coverage(knowledge_base_id, document_id)
+                .await?
+                .into_iter()
+                .map(|c| (c.chunk_id.clone(), c))
+                .collect(),
+            _ => Default::default(),
+        };
+
+        // ThreadRng is not Send, so it must not be held across an await point
+        let mut rng = rand::thread_rng();
+        let selected = match strategy {
+            SourceStrategy::Random => chunks.choose(&mut rng).cloned(),
+            SourceStrategy::LeastCovered => {
+                let count_of = |chunk: &DocumentChunk| counts.get(&chunk.id).copied().unwrap_or(0);
+
+                let least = chunks.iter().map(count_of).min();
+                let candidates: Vec<&DocumentChunk> = chunks
+                    .iter()
+                    .filter(|chunk| Some(count_of(chunk)) == least)
+                    .collect();
+                candidates.choose(&mut rng).map(|chunk| (*chunk).clone())
+            }
+            SourceStrategy::Weakest => {
+                let weakness_of = |chunk: &DocumentChunk| SourceStrategy::weakness(coverage.get(&chunk.id));
+
+                let weakest = chunks
+                    .iter()
+                    .map(weakness_of)
+                    .min_by(|a, b| a.0.cmp(&b.0).then(a.1.total_cmp(&b.1)));
+                let candidates: Vec<&DocumentChunk> = chunks
+                    .iter()
+                    .filter(|chunk| Some(weakness_of(chunk)) == weakest)
+                    .collect();
+                candidates.choose(&mut rng).map(|chunk| (*chunk).clone())
+            }
+        };
+
+        Ok(selected)
+    }
+
+    /// Collect the passages to evaluate an answer against: the question's source
+    /// passage first, then the `related` most similar passages in its knowledge base
+    pub async fn gather_evidence(
+        db: &DatabaseManager,
+        question: &Question,
+        related: usize,
+    ) -> Result<Vec<EvidencePassage>, sqlx::Error> {
+        let mut evidence = Vec::new();
+
+        if let Some(chunk_id) = &question.chunk_id
+            && let Some(chunk) = db.get_chunk_by_id(chunk_id).await?
+        {
+            evidence.push(EvidencePassage::from_chunk(chunk, None, true));
+        }
+
+        // Ask for one extra hit in case the source passage is among the results
+        let hits = Self::search(db, &question.knowledge_base_id, &question.question_text, related + 1).await?;
+        evidence.extend(
+            hits.into_iter()
+                .filter(|hit| question.chunk_id.as_deref() != Some(hit.chunk.id.as_str()))
+                .take(related)
+                .map(|hit| EvidencePassage::from_chunk(hit.chunk, Some(hit.score), false)),
+        );
+
+        // Questions generated before passages were tracked only kept a snippet
+        if evidence.is_empty()
+            && let Some(snippet) = &question.context_snippet
+        {
+            evidence.push(EvidencePassage {
+                chunk_id: None,
+                document_id: question.document_id.clone(),
+                page_number: question.page_number,
+                content: snippet.clone(),
+                score: None,
+                is_source: true,
+            });
+        }
+
+        Ok(evidence)
+    }
+
+    /// Rank candidates by TF-IDF cosine similarity to the query vector
+    pub fn rank(query: &[f32], candidates: Vec<(DocumentChunk, Vec<f32>)>, top_k: usize) -> Vec<SearchHit> {
+        if candidates.is_empty() || top_k == 0 {
+            return Vec::new();
+        }
+
+        // Inverse document frequency per bucket, smoothed
+        let total = candidates.len() as f32;
+        let mut document_frequency = vec![0.0f32; EMBEDDING_DIMENSIONS];
+        for (_, vector) in &candidates {
+            for (df, value) in document_frequency.iter_mut().zip(vector) {
+                if *value > 0.0 {
+                    *df += 1.0;
+                }
+            }
+        }
+        let idf: Vec<f32> = document_frequency
+            .iter()
+            .map(|df| ((1.0 + total) / (1.0 + df)).ln() + 1.0)
+            .collect();
+
+        let weighted_query: Vec<f32> = query.iter().zip(&idf).map(|(q, w)| q * w).collect();
+        let query_norm = weighted_query.iter().map(|v| v * v).sum::<f32>().sqrt();
+        if query_norm == 0.0 {
+            return Vec::new();
+        }
+
+        let mut hits: Vec<SearchHit> = candidates
+            .into_iter()
+            .filter_map(|(chunk, vector)| {
+                let mut dot = 0.0;
+                let mut norm = 0.0;
+                for ((value, weight), q) in vector.iter().zip(&idf).zip(&weighted_query) {
+                    let weighted = value * weight;
+                    dot += weighted * q;
+                    norm += weighted * weighted;
+                }
+                if dot <= 0.0 || norm == 0.0 {
+                    return None;
+                }
+                Some(SearchHit {
+                    chunk,
+                    score: dot / (norm.sqrt() * query_norm),
+                })
+            })
+            .collect();
+
+        hits.sort_by(|a, b| b.score.total_cmp(&a.score));
+        hits.truncate(top_k);
+
+        hits
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::create_connection_pool;
+    use crate::models::DocumentType;
+
+    #[test]
+    fn test_chunker_respects_paragraphs_and_offsets() {
+        let text = "First paragraph here.\n\nSecond paragraph, a bit longer than the first.\n\n\nThird.";
+        let chunks = TextChunker::chunk(text, 50);
+
+        assert_eq!(chunks.len(), 3);
+        for chunk in &chunks {
+            let original: String = text.chars().skip(chunk.start_offset).take(chunk.end_offset - chunk.start_offset).collect();
+            assert_eq!(original, chunk.content);
+        }
+        assert_eq!(chunks[0].content, "First paragraph here.");
+        assert_eq!(chunks[2].content, "Third.");
+    }
+
+    #[test]
+    fn test_chunker_splits_long_paragraphs() {
+        let text = "这是第一句话。这是第二句话。这是第三句话。".repeat(20);
+        let chunks = TextChunker::chunk(&text, 50);
+
+        assert!(chunks.len() > 1);
+        assert!(chunks.iter().all(|c| c.content.chars().count() <= 50));
+    }
+
+    #[test]
+    fn test_tokenize_mixed_text() {
+        let tokens = HashingEmbedder::tokenize("Rust 所有权 Ownership!");
+        assert_eq!(tokens, vec!["rust", "所有", "有权", "ownership"]);
+    }
+
+    #[test]
+    fn test_embedding_roundtrip() {
+        let vector = HashingEmbedder::embed("memory safety without garbage collection");
+        assert_eq!(vector.len(), EMBEDDING_DIMENSIONS);
+        assert_eq!(HashingEmbedder::from_bytes(&HashingEmbedder::to_bytes(&vector)), vector);
+    }
+
+    #[test]
+    fn test_rank_prefers_relevant_chunk() {
+        let make = |index: i32, text: &str| {
+            (
+                DocumentChunk::new("doc".to_string(), "kb".to_string(), index, text.to_string(), 0, 0),
+                HashingEmbedder::embed(text),
+            )
+        };
+        let candidates = vec![
+            make(0, "Photosynthesis converts light energy into chemical energy in plants."),
+            make(1, "The borrow checker enforces ownership and lifetimes in Rust programs."),
+            make(2, "Mitochondria are the powerhouse of the cell."),
+        ];
+
+        let hits = Retriever::rank(&HashingEmbedder::embed("How does Rust ownership work?"), candidates, 2);
+        assert!(!hits.is_empty());
+        assert_eq!(hits[0].chunk.chunk_index, 1);
+    }
+
+    #[tokio::test]
+    async fn test_search_indexes_documents_lazily() {
+        let pool = create_connection_pool("sqlite::memory:").await.unwrap();
+        let db = DatabaseManager::new(pool);
+
+        let kb = db.create_knowledge_base("Retrieval KB", None).await.unwrap();
+        let document = Document::new(
+            kb.id.clone(),
+            "notes.txt".to_string(),
+            DocumentType::Txt,
+            "/tmp/notes.txt".to_string(),
+            100,
+            Some("光合作用把光能转化为化学能。\n\n所有权系统保证了内存安全，无需垃圾回收。".to_string()),
+        );
+        db.save_document(&document).await.unwrap();
+
+        let hits = Retriever::search(&db, &kb.id, "内存安全", 1).await.unwrap();
+        assert_eq!(hits.len(), 1);
+        assert!(hits[0].chunk.content.contains("内存安全"));
+
+        let chunks = db.get_chunks_by_document(&document.id).await.unwrap();
+        assert_eq!(chunks.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_index_records_pdf_page_numbers() {
+        let pool = create_connection_pool("sqlite::memory:").await.unwrap();
+        let db = DatabaseManager::new(pool);
+
+        let kb = db.create_knowledge_base("Paged KB", None).await.unwrap();
+        let pages = ["Page one text.".repeat(40), "Page two text.".repeat(40)];
+        let document = Document::new(
+            kb.id.clone(),
+            "book.pdf".to_string(),
+            DocumentType::Pdf,
+            "/tmp/book.pdf".to_string(),
+            100,
+            Some(pages.join(&PAGE_BREAK.to_string())),
+        );
+        db.save_document(&document).await.unwrap();
+        Retriever::index_document(&db, &document).await.unwrap();
+
+        let chunks = db.get_chunks_by_document(&document.id).await.unwrap();
+        assert_eq!(chunks.first().unwrap().page_number, Some(1));
+        assert_eq!(chunks.last().unwrap().page_number, Some(2));
+        assert!(chunks.iter().filter(|c| c.content.starts_with("Page two")).all(|c| c.page_number == Some(2)));
+    }
+
+    #[tokio::test]
+    async fn test_select_source_least_covered() {
+        let pool = create_connection_pool("sqlite::memory:").await.unwrap();
+        let db = DatabaseManager::new(pool);
+
+        let kb = db.create_knowledge_base("Coverage KB", None).await.unwrap();
+        let document = Document::new(
+            kb.id.clone(),
+            "notes.txt".to_string(),
+            DocumentType::Txt,
+            "/tmp/notes.txt".to_string(),
+            100,
+            Some(format!("{}\n\n{}", "Alpha passage. ".repeat(40), "Beta passage. ".repeat(40))),
+        );
+        db.save_document(&document).await.unwrap();
+        Retriever::index_document(&db, &document).await.unwrap();
+
+        let chunks = db.get_chunks_by_document(&document.id).await.unwrap();
+        assert_eq!(chunks.len(), 2);
+
+        // Cover the first chunk; the second must be picked next
+        let question = Question::new(kb.id.clone(), "Q?".to_string(), None).with_source(&chunks[0]);
+        db.save_question(&question).await.unwrap();
+
+        let selected = Retriever::select_source(&db, &kb.id, SourceStrategy::LeastCovered, None).await.unwrap();
+        assert_eq!(selected.unwrap().id, chunks[1].id);
+
+        // Restricting to another document yields nothing
+        let none = Retriever::select_source(&db, &kb.id, SourceStrategy::Random, Some("other-doc")).await.unwrap();
+        assert!(none.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_gather_evidence_source_first_without_duplicates() {
+        let pool = create_connection_pool("sqlite::memory:").await.unwrap();
+        let db = DatabaseManager::new(pool);
+
+        let kb = db.create_knowledge_base("Evidence KB", None).await.unwrap();
+        let paragraphs = [
+            "Ownership moves values between bindings. ".repeat(15),
+            "Borrowing lends references without moving ownership. ".repeat(12),
+            "Async tasks are polled by an executor. ".repeat(15),
+        ];
+        let document = Document::new(
+            kb.id.clone(),
+            "rust.txt".to_string(),
+            DocumentType::Txt,
+            "/tmp/rust.txt".to_string(),
+            100,
+            Some(paragraphs.join("\n\n")),
+        );
+        db.save_document(&document).await.unwrap();
+        Retriever::index_document(&db, &document).await.unwrap();
+        let chunks = db.get_chunks_by_document(&document.id).await.unwrap();
+
+        let question = Question::new(kb.id.clone(), "How does ownership move?".to_string(), None).with_source(&chunks[0]);
+        let evidence = Retriever::gather_evidence(&db, &question, 2).await.unwrap();
+
+        assert!(evidence[0].is_source);
+        assert_eq!(evidence[0].chunk_id.as_deref(), Some(chunks[0].id.as_str()));
+        assert!(evidence.len() <= 3);
+        assert_eq!(evidence.iter().filter(|p| p.chunk_id == evidence[0].chunk_id).count(), 1);
+        assert_eq!(evidence[1].chunk_id.as_deref(), Some(chunks[1].id.as_str()));
+
+        let context = EvidencePassage::format_context(&evidence);
+        assert!(context.starts_with("[1]\nOwnership"));
+
+        // Legacy questions without a source or matches fall back to their snippet
+        let legacy = Question::new(kb.id.clone(), "Unrelated zzz?".to_string(), Some("Saved snippet".to_string()));
+        let evidence = Retriever::gather_evidence(&db, &legacy, 2).await.unwrap();
+        assert_eq!(evidence.len(), 1);
+        assert_eq!(evidence[0].content, "Saved snippet");
+    }
+
+    #[tokio::test]
+    async fn test_select_source_weakest() {
+        let pool = create_connection_pool("sqlite::memory:").await.unwrap();
+        let db = DatabaseManager::new(pool);
+
+        let kb = db.create_knowledge_base("Weakest KB", None).await.unwrap();
+        let document = Document::new(
+            kb.id.clone(),
+            "notes.txt".to_string(),
+            DocumentType::Txt,
+            "/tmp/notes.txt".to_string(),
+            100,
+            Some(["Alpha passage. ", "Beta passage. ", "Gamma passage. "].map(|p| p.repeat(40)).join("\n\n")),
+        );
+        db.save_document(&document).await.unwrap();
+        Retriever::index_document(&db, &document).await.unwrap();
+        let chunks = db.get_chunks_by_document(&document.id).await.unwrap();
+        assert_eq!(chunks.len(), 3);
+
+        let answer_with = |question: &Question, score: i32| {
+            let mut answer = crate::models::Answer::new(question.id.clone(), "A".to_string());
+            answer.ai_score = Some(score);
+            answer
+        };
+
+        // Quiz the first two chunks; the untouched third one comes first
+        let strong = Question::new(kb.id.clone(), "Q1?".to_string(), None).with_source(&chunks[0]);
+        let weak = Question::new(kb.id.clone(), "Q2?".to_string(), None).with_source(&chunks[1]);
+        db.save_question(&strong).await.unwrap();
+        db.save_question(&weak).await.unwrap();
+        db.save_answer(&answer_with(&strong, 90)).await.unwrap();
+        db.save_answer(&answer_with(&weak, 40)).await.unwrap();
+
+        let selected = Retriever::select_source(&db, &kb.id, SourceStrategy::Weakest, None).await.unwrap();
+        assert_eq!(selected.unwrap().id, chunks[2].id);
+
+        // Once everything is covered, the lowest-scoring chunk is targeted
+        let third = Question::new(kb.id.clone(), "Q3?".to_string(), None).with_source(&chunks[2]);
+        db.save_question(&third).await.unwrap();
+        db.save_answer(&answer_with(&third, 70)).await.unwrap();
+
+        let selected = Retriever::select_source(&db, &kb.id, SourceStrategy::Weakest, None).await.unwrap();
+        assert_eq!(selected.unwrap().id, chunks[1].id);
+    }
+}
@@ -0,0 +1,203 @@
+use aes_gcm::aead::{Aead, AeadCore, KeyInit, OsRng};
+use aes_gcm::{Aes256Gcm, Key, Nonce};
+use base64::{engine::general_purpose::STANDARD, Engine as _};
+use std::path::{Path, PathBuf};
+use thiserror::Error;
+
+/// Base64-encoded 32-byte key; takes precedence over the key file
+pub const SECRET_KEY_ENV: &str = "MOON_READER_SECRET_KEY";
+/// Path of a file holding the base64-encoded key
+pub const SECRET_KEY_FILE_ENV: &str = "MOON_READER_SECRET_KEY_FILE";
+/// Key file used, and created on first start, when neither variable is set
+pub const DEFAULT_SECRET_KEY_FILE: &str = "moon_reader.key";
+
+// Marks a stored value as sealed by `SecretCipher`; anything else is legacy plaintext
+const SEALED_PREFIX: &str = "enc:v1:";
+const NONCE_LEN: usize = 12;
+
+#[derive(Debug, Error)]
+pub enum SecretError {
+    #[error("Invalid secret key: {0}")]
+    InvalidKey(String),
+    #[error("Failed to access the secret key file: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Failed to encrypt secret")]
+    Encrypt,
+    #[error("Failed to decrypt secret; is this the key it was encrypted with?")]
+    Decrypt,
+    #[error("Secret is encrypted but no secret key is configured")]
+    MissingKey,
+}
+
+/// Where the secret key was loaded from, which decides where a rotated key goes
+#[derive(Debug, Clone, PartialEq)]
+pub enum SecretKeySource {
+    Env,
+    File(PathBuf),
+}
+
+/// Seals secrets such as API keys with AES-256-GCM before they are stored
+#[derive(Clone)]
+pub struct SecretCipher {
+    cipher: Aes256Gcm,
+}
+
+impl SecretCipher {
+    pub fn new(key: &[u8; 32]) -> Self {
+        Self {
+            cipher: Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(key)),
+        }
+    }
+
+    /// A fresh random key, base64-encoded as it is stored in the environment or key file
+    pub fn generate_key() -> String {
+        STANDARD.encode(Aes256Gcm::generate_key(OsRng))
+    }
+
+    pub fn from_base64(encoded: &str) -> Result<Self, SecretError> {
+        let bytes = STANDARD
+            .decode(encoded.trim())
+            .map_err(|e| SecretError::InvalidKey(format!("not valid base64: {}", e)))?;
+        let key: [u8; 32] = bytes
+            .try_into()
+            .map_err(|bytes: Vec<u8>| SecretError::InvalidKey(format!("expected 32 bytes, got {}", bytes.len())))?;
+        Ok(Self::new(&key))
+    }
+
+    pub fn from_key_file(path: &Path) -> Result<Self, SecretError> {
+        Self::from_base64(&std::fs::read_to_string(path)?)
+    }
+
+    /// Load the key from `MOON_READER_SECRET_KEY` or the key file, creating the default key file if missing
+    pub fn load() -> Result<(Self, SecretKeySource), SecretError> {
+        if let Ok(encoded) = std::env::var(SECRET_KEY_ENV) {
+            return Ok((Self::from_base64(&encoded)?, SecretKeySource::Env));
+        }
+
+        let path = match std::env::var(SECRET_KEY_FILE_ENV) {
+            Ok(path) => PathBuf::from(path),
+            Err(_) => {
+                let path = PathBuf::from(DEFAULT_SECRET_KEY_FILE);
+                if !path.exists() {
+                    tracing::warn!(
+                        "No secret key configured, generating {}; keep it out of database backups",
+                        path.display()
+                    );
+                    write_key_file(&path, &Self::generate_key())?;
+                }
+                path
+            }
+        };
+
+        Ok((Self::from_key_file(&path)?, SecretKeySource::File(path)))
+    }
+
+    /// Encrypt `plaintext` under a fresh nonce into a self-describing string
+    pub fn seal(&self, plaintext: &str) -> Result<String, SecretError> {
+        let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+        let ciphertext = self
+            .cipher
+            .encrypt(&nonce, plaintext.as_bytes())
+            .map_err(|_| SecretError::Encrypt)?;
+
+        let mut sealed = nonce.to_vec();
+        sealed.extend_from_slice(&ciphertext);
+        Ok(format!("{}{}", SEALED_PREFIX, STANDARD.encode(sealed)))
+    }
+
+    /// Decrypt a sealed value; plaintext stored before encryption was enabled is returned as is
+    pub fn open(&self, stored: &str) -> Result<String, SecretError> {
+        let Some(encoded) = stored.strip_prefix(SEALED_PREFIX) else {
+            return Ok(stored.to_string());
+        };
+
+        let sealed = STANDARD.decode(encoded).map_err(|_| SecretError::Decrypt)?;
+        if sealed.len() < NONCE_LEN {
+            return Err(SecretError::Decrypt);
+        }
+        let (nonce, ciphertext) = sealed.split_at(NONCE_LEN);
+        let plaintext = self
+            .cipher
+            .decrypt(Nonce::from_slice(nonce), ciphertext)
+            .map_err(|_| SecretError::Decrypt)?;
+
+        String::from_utf8(plaintext).map_err(|_| SecretError::Decrypt)
+    }
+}
+
+/// Whether a stored value was sealed by `SecretCipher`
+pub fn is_sealed(stored: &str) -> bool {
+    stored.starts_with(SEALED_PREFIX)
+}
+
+/// Write a base64 key to a new file readable only by its owner
+pub fn write_key_file(path: &Path, encoded_key: &str) -> Result<(), SecretError> {
+    use std::io::Write;
+
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+
+    let mut file = options.open(path)?;
+    writeln!(file, "{}", encoded_key)?;
+    file.sync_all()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_seal_and_open() {
+        let cipher = SecretCipher::from_base64(&SecretCipher::generate_key()).unwrap();
+
+        let sealed = cipher.seal("sk-live-123").unwrap();
+        assert!(is_sealed(&sealed));
+        assert!(!sealed.contains("sk-live-123"));
+        // A fresh nonce every time
+        assert_ne!(sealed, cipher.seal("sk-live-123").unwrap());
+        assert_eq!(cipher.open(&sealed).unwrap(), "sk-live-123");
+
+        // Legacy plaintext passes through
+        assert_eq!(cipher.open("sk-plain").unwrap(), "sk-plain");
+    }
+
+    #[test]
+    fn test_wrong_key_and_tampering_fail() {
+        let cipher = SecretCipher::from_base64(&SecretCipher::generate_key()).unwrap();
+        let other = SecretCipher::from_base64(&SecretCipher::generate_key()).unwrap();
+        let sealed = cipher.seal("sk-live-123").unwrap();
+
+        assert!(matches!(other.open(&sealed), Err(SecretError::Decrypt)));
+
+        let mut tampered = sealed.clone();
+        tampered.pop();
+        tampered.push(if sealed.ends_with('A') { 'B' } else { 'A' });
+        assert!(matches!(cipher.open(&tampered), Err(SecretError::Decrypt)));
+    }
+
+    #[test]
+    fn test_invalid_keys_rejected() {
+        assert!(matches!(SecretCipher::from_base64("not base64!"), Err(SecretError::InvalidKey(_))));
+        assert!(matches!(SecretCipher::from_base64(&STANDARD.encode([0u8; 16])), Err(SecretError::InvalidKey(_))));
+    }
+
+    #[test]
+    fn test_key_file_round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("secret.key");
+        let key = SecretCipher::generate_key();
+
+        write_key_file(&path, &key).unwrap();
+        // Existing key files are never overwritten
+        assert!(write_key_file(&path, &SecretCipher::generate_key()).is_err());
+
+        let sealed = SecretCipher::from_base64(&key).unwrap().seal("sk-live-123").unwrap();
+        assert_eq!(SecretCipher::from_key_file(&path).unwrap().open(&sealed).unwrap(), "sk-live-123");
+    }
+}
@@ -0,0 +1,108 @@
+use crate::models::{Answer, AnswerMessage, ReferenceAnswer, ThreadRole};
+use crate::services::prompts::Prompt;
+use crate::services::reference_answers;
+
+/// Earlier messages sent back to the model; older ones are dropped to bound the prompt
+pub const MAX_THREAD_HISTORY: usize = 12;
+
+/// What the tutor is told about the answer under discussion
+pub struct TutoringContext<'a> {
+    /// The source material, formatted as numbered passages
+    pub material: &'a str,
+    pub question: &'a str,
+    pub reference: Option<&'a ReferenceAnswer>,
+    pub answer: &'a Answer,
+    pub language: &'a str,
+}
+
+/// Prompt for the tutor's reply to a follow-up message about an answer's feedback
+pub fn tutoring_prompt(context: &TutoringContext<'_>, thread: &[AnswerMessage], message: &str) -> Prompt {
+    let mut user = format!(
+        "Learning material:\n{}\n\nQuestion: {}\n\n{}Learner's answer: {}\n\n",
+        context.material,
+        context.question,
+        // The tutoring prompt is written in English whatever language the reply is in
+        reference_answers::evaluation_reference(context.reference, "English"),
+        context.answer.user_answer
+    );
+    if let Some(score) = context.answer.ai_score {
+        user.push_str(&format!("Score: {}/100\n", score));
+    }
+    if let Some(feedback) = &context.answer.ai_feedback {
+        user.push_str(&format!("Feedback: {}\n", feedback));
+    }
+
+    let earlier = &thread[thread.len().saturating_sub(MAX_THREAD_HISTORY)..];
+    if !earlier.is_empty() {
+        user.push_str("\nConversation so far:\n");
+        for message in earlier {
+            user.push_str(&format!("{}: {}\n", speaker(message.role), message.content));
+        }
+    }
+    user.push_str(&format!("\nLearner: {}", message));
+
+    Prompt {
+        system: Some(format!(
+            "You are a patient tutor. The learner answered a question and received feedback, and is now asking about it. Explain using only the learning material, citing passages by their number, and say so when the material does not cover something. Do not change the score. Reply in {} as plain text, in a few short paragraphs at most.",
+            context.language
+        )),
+        user,
+    }
+}
+
+fn speaker(role: ThreadRole) -> &'static str {
+    match role {
+        ThreadRole::Learner => "Learner",
+        ThreadRole::Tutor => "Tutor",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tutoring_prompt() {
+        let mut answer = Answer::new("question-id".to_string(), "The garbage collector".to_string());
+        answer.ai_score = Some(20);
+        answer.ai_feedback = Some("Rust has no garbage collector.".to_string());
+        let context = TutoringContext {
+            material: "[1]\nRust frees memory through ownership.",
+            question: "How does Rust free memory?",
+            reference: None,
+            answer: &answer,
+            language: "English",
+        };
+
+        let prompt = tutoring_prompt(&context, &[], "Why is that wrong?");
+        assert!(prompt.system.unwrap().contains("Reply in English"));
+        assert_eq!(
+            prompt.user,
+            "Learning material:\n[1]\nRust frees memory through ownership.\n\nQuestion: How does Rust free memory?\n\nLearner's answer: The garbage collector\n\nScore: 20/100\nFeedback: Rust has no garbage collector.\n\nLearner: Why is that wrong?"
+        );
+    }
+
+    #[test]
+    fn test_tutoring_prompt_keeps_recent_history() {
+        let answer = Answer::new("question-id".to_string(), "Ownership".to_string());
+        let context = TutoringContext {
+            material: "",
+            question: "How does Rust free memory?",
+            reference: None,
+            answer: &answer,
+            language: "English",
+        };
+        let thread: Vec<AnswerMessage> = (0..MAX_THREAD_HISTORY + 2)
+            .map(|i| {
+                let role = if i % 2 == 0 { ThreadRole::Learner } else { ThreadRole::Tutor };
+                AnswerMessage::new(answer.id.clone(), role, format!("message {}", i))
+            })
+            .collect();
+
+        let prompt = tutoring_prompt(&context, &thread, "And borrowing?");
+        assert!(!prompt.user.contains("Learner: message 0\n"));
+        assert!(!prompt.user.contains("Tutor: message 1\n"));
+        assert!(prompt.user.contains("\nConversation so far:\nLearner: message 2\nTutor: message 3\n"));
+        assert!(prompt.user.ends_with("Tutor: message 13\n\nLearner: And borrowing?"));
+    }
+}
@@ -1,7 +1,7 @@
 use moon_reader::{
     database::create_connection_pool,
     services::AppState,
-    models::{KnowledgeBase, Document, DocumentType},
+    models::{Document, DocumentType},
 };
 use std::io::Write;
 use tempfile::NamedTempFile;
@@ -22,7 +22,7 @@ async fn create_test_app() -> (Router, SqlitePool, AppState) {
     let database_url = "sqlite::memory:";
     
     // Create connection pool
-    let pool = create_connection_pool(&database_url).await.unwrap();
+    let pool = create_connection_pool(database_url).await.unwrap();
     
     // Run migrations manually for tests
     sqlx::migrate!("./migrations").run(&pool).await.unwrap();
@@ -59,6 +59,10 @@ fn create_app() -> Router<AppState> {
                axum::routing::post(ai_quiz::generate_question))
         .route("/api/questions/:id/answer",
                axum::routing::post(ai_quiz::submit_answer))
+        // Tutoring routes
+        .route("/api/answers/:id/messages",
+               axum::routing::get(tutoring::get_answer_thread)
+               .post(tutoring::send_answer_message))
         // Review routes
         .route("/api/knowledge-bases/:id/review/random",
                axum::routing::get(review::get_random_review_question))
@@ -126,7 +130,7 @@ async fn test_complete_knowledge_base_to_quiz_workflow() {
     
     // Step 3: Verify document was uploaded
     let request = Request::builder()
-        .uri(&format!("/api/knowledge-bases/{}/documents", kb_id))
+        .uri(format!("/api/knowledge-bases/{}/documents", kb_id))
         .body(Body::empty())
         .unwrap();
     
@@ -142,12 +146,20 @@ async fn test_complete_knowledge_base_to_quiz_workflow() {
     // Step 4: Configure AI service (mock configuration)
     let ai_config = AIConfig {
         id: None,
+        name: "Default".to_string(),
+        is_default: true,
         provider: AIProvider::DeepSeek,
         api_key: Some("test-api-key".to_string()),
         api_url: Some("https://api.deepseek.com/v1".to_string()),
         model_name: Some("deepseek-chat".to_string()),
         max_tokens: 1000,
         temperature: 0.7,
+        provider_options: None,
+        connect_timeout_secs: None,
+        request_timeout_secs: None,
+        max_retries: None,
+        fallback_profile_ids: Vec::new(),
+        monthly_budget: None,
         updated_at: chrono::Utc::now(),
     };
     
@@ -160,7 +172,7 @@ async fn test_complete_knowledge_base_to_quiz_workflow() {
     });
     
     let request = Request::builder()
-        .uri(&format!("/api/knowledge-bases/{}/generate-question", kb_id))
+        .uri(format!("/api/knowledge-bases/{}/generate-question", kb_id))
         .method("POST")
         .header("content-type", "application/json")
         .body(Body::from(question_payload.to_string()))
@@ -193,7 +205,7 @@ async fn test_complete_knowledge_base_to_quiz_workflow() {
     
     // Try to generate question from empty knowledge base
     let request = Request::builder()
-        .uri(&format!("/api/knowledge-bases/{}/generate-question", empty_kb_id))
+        .uri(format!("/api/knowledge-bases/{}/generate-question", empty_kb_id))
         .method("POST")
         .header("content-type", "application/json")
         .body(Body::from(json!({}).to_string()))
@@ -209,7 +221,7 @@ async fn test_complete_knowledge_base_to_quiz_workflow() {
 
 #[tokio::test]
 async fn test_document_upload_and_parsing_workflow() {
-    let (mut app, _pool, app_state) = create_test_app().await;
+    let (app, _pool, app_state) = create_test_app().await;
     
     // Create a knowledge base
     let kb = app_state.db.create_knowledge_base("Document Test KB", Some("Testing document parsing")).await.unwrap();
@@ -268,7 +280,7 @@ async fn test_document_upload_and_parsing_workflow() {
     
     // Test document retrieval API
     let request = Request::builder()
-        .uri(&format!("/api/knowledge-bases/{}/documents", kb.id))
+        .uri(format!("/api/knowledge-bases/{}/documents", kb.id))
         .body(Body::empty())
         .unwrap();
     
@@ -351,7 +363,7 @@ async fn test_ai_quiz_and_review_workflow() {
     
     // Test 3: Question Generation (will likely fail without real AI service, but we test the endpoint)
     let question_request = Request::builder()
-        .uri(&format!("/api/knowledge-bases/{}/generate-question", kb.id))
+        .uri(format!("/api/knowledge-bases/{}/generate-question", kb.id))
         .method("POST")
         .header("content-type", "application/json")
         .body(Body::from(json!({}).to_string()))
@@ -381,7 +393,7 @@ async fn test_ai_quiz_and_review_workflow() {
     
     // Test 5: Review functionality - get random question
     let request = Request::builder()
-        .uri(&format!("/api/knowledge-bases/{}/review/random", kb.id))
+        .uri(format!("/api/knowledge-bases/{}/review/random", kb.id))
         .body(Body::empty())
         .unwrap();
     
@@ -395,7 +407,7 @@ async fn test_ai_quiz_and_review_workflow() {
     
     // Test 6: Get question history
     let request = Request::builder()
-        .uri(&format!("/api/knowledge-bases/{}/history", kb.id))
+        .uri(format!("/api/knowledge-bases/{}/history", kb.id))
         .body(Body::empty())
         .unwrap();
     
@@ -409,6 +421,123 @@ async fn test_ai_quiz_and_review_workflow() {
     assert_eq!(items[0]["question"]["question_text"], "What is the difference between AI and Machine Learning?");
 }
 
+#[tokio::test]
+async fn test_offline_quiz_flow_with_mock_provider() {
+    let (mut app, _pool, app_state) = create_test_app().await;
+    
+    let kb = app_state.db.create_knowledge_base("Offline KB", None).await.unwrap();
+    let content = "Ownership lets Rust guarantee memory safety without a garbage collector.";
+    let document = Document::new(
+        kb.id.clone(),
+        "ownership.txt".to_string(),
+        DocumentType::Txt,
+        "/tmp/ownership.txt".to_string(),
+        content.len() as i64,
+        Some(content.to_string()),
+    );
+    app_state.db.save_document(&document).await.unwrap();
+    
+    // Configure the mock provider through the API; it needs no key or URL
+    let ai_config_payload = json!({
+        "provider": "mock",
+        "provider_options": {
+            "evaluations": [{"score": 88, "feedback": "Accurate", "suggestions": ["Mention borrowing"]}]
+        }
+    });
+    let request = Request::builder()
+        .uri("/api/ai-config")
+        .method("POST")
+        .header("content-type", "application/json")
+        .body(Body::from(ai_config_payload.to_string()))
+        .unwrap();
+    let response = app.call(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    
+    let request = Request::builder()
+        .uri("/api/ai-config/test")
+        .method("POST")
+        .body(Body::empty())
+        .unwrap();
+    let response = app.call(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    
+    // Generate: the default question quotes the material
+    let request = Request::builder()
+        .uri(format!("/api/knowledge-bases/{}/generate-question", kb.id))
+        .method("POST")
+        .header("content-type", "application/json")
+        .body(Body::from(json!({}).to_string()))
+        .unwrap();
+    let response = app.call(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    let question: Value = serde_json::from_slice(&body).unwrap();
+    let question_text = question["question_text"].as_str().unwrap().to_string();
+    assert!(question_text.contains("Ownership lets Rust guarantee memory safety"));
+    let question_id = question["id"].as_str().unwrap();
+    
+    // Answer and evaluate
+    let request = Request::builder()
+        .uri(format!("/api/questions/{}/answer", question_id))
+        .method("POST")
+        .header("content-type", "application/json")
+        .body(Body::from(json!({"user_answer": "Each value has a single owner"}).to_string()))
+        .unwrap();
+    let response = app.call(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    let answer: Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(answer["ai_score"], 88);
+    assert_eq!(answer["ai_suggestions"], json!(["Mention borrowing"]));
+    
+    // Ask the tutor a follow-up about the feedback
+    let request = Request::builder()
+        .uri(format!("/api/answers/{}/messages", answer["id"].as_str().unwrap()))
+        .method("POST")
+        .header("content-type", "application/json")
+        .body(Body::from(json!({"message": "Why mention borrowing?"}).to_string()))
+        .unwrap();
+    let response = app.call(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    let exchange: Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(exchange["reply"]["role"], "tutor");
+    assert!(exchange["reply"]["content"].as_str().unwrap().contains("Ownership lets Rust guarantee memory safety"));
+    
+    // Review the answered question
+    let request = Request::builder()
+        .uri(format!("/api/knowledge-bases/{}/review/random", kb.id))
+        .body(Body::empty())
+        .unwrap();
+    let response = app.call(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    let review: Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(review["question"]["question_text"], question_text.as_str());
+    
+    let request = Request::builder()
+        .uri(format!("/api/knowledge-bases/{}/history", kb.id))
+        .body(Body::empty())
+        .unwrap();
+    let response = app.oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    let history: Value = serde_json::from_slice(&body).unwrap();
+    let items = history["items"].as_array().unwrap();
+    assert_eq!(items.len(), 1);
+    assert_eq!(items[0]["answer"]["ai_score"], 88);
+    let messages = items[0]["messages"].as_array().unwrap();
+    assert_eq!(messages.len(), 2);
+    assert_eq!(messages[0]["role"], "learner");
+    assert_eq!(messages[0]["content"], "Why mention borrowing?");
+    assert_eq!(messages[1]["content"], exchange["reply"]["content"]);
+}
+
 #[tokio::test]
 async fn test_error_handling_and_edge_cases() {
     let (mut app, _pool, app_state) = create_test_app().await;
@@ -478,7 +607,7 @@ async fn test_error_handling_and_edge_cases() {
     let kb = app_state.db.create_knowledge_base("No AI Config KB", None).await.unwrap();
     
     let request = Request::builder()
-        .uri(&format!("/api/knowledge-bases/{}/generate-question", kb.id))
+        .uri(format!("/api/knowledge-bases/{}/generate-question", kb.id))
         .method("POST")
         .header("content-type", "application/json")
         .body(Body::from(json!({}).to_string()))
@@ -488,6 +617,33 @@ async fn test_error_handling_and_edge_cases() {
     // Should return error about missing AI configuration
     assert!(response.status() == StatusCode::BAD_REQUEST || response.status() == StatusCode::SERVICE_UNAVAILABLE);
     
+    // A malformed body is rejected instead of being read as the defaults
+    let request = Request::builder()
+        .uri(format!("/api/knowledge-bases/{}/generate-question", kb.id))
+        .method("POST")
+        .header("content-type", "application/json")
+        .body(Body::from(r#"{"question_type": "essay"}"#))
+        .unwrap();
+    
+    let response = app.call(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    let error: Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(error["error"], "Invalid request body");
+    
+    // Without a body the defaults are used, so the request gets as far as the empty knowledge base
+    let request = Request::builder()
+        .uri(format!("/api/knowledge-bases/{}/generate-question", kb.id))
+        .method("POST")
+        .body(Body::empty())
+        .unwrap();
+    
+    let response = app.call(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    let error: Value = serde_json::from_slice(&body).unwrap();
+    assert!(error["error"].as_str().unwrap().starts_with("No documents found"));
+    
     // Test 7: Invalid AI configuration
     let invalid_ai_config = json!({
         "provider": "invalid-provider",
@@ -510,7 +666,7 @@ async fn test_error_handling_and_edge_cases() {
     let empty_kb = app_state.db.create_knowledge_base("Empty Review KB", None).await.unwrap();
     
     let request = Request::builder()
-        .uri(&format!("/api/knowledge-bases/{}/review/random", empty_kb.id))
+        .uri(format!("/api/knowledge-bases/{}/review/random", empty_kb.id))
         .body(Body::empty())
         .unwrap();
     
@@ -20,7 +20,7 @@ async fn create_test_app() -> (Router, SqlitePool) {
     let database_url = "sqlite::memory:";
     
     // Create connection pool
-    let pool = create_connection_pool(&database_url).await.unwrap();
+    let pool = create_connection_pool(database_url).await.unwrap();
     
     // Run migrations manually for tests
     sqlx::migrate!("./migrations").run(&pool).await.unwrap();
@@ -183,15 +183,35 @@ async fn test_update_knowledge_base() {
     let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
     let created_kb: Value = serde_json::from_slice(&body).unwrap();
     let kb_id = created_kb["id"].as_str().unwrap();
+    assert_eq!(created_kb["rubric_weights"]["accuracy"], 0.4);
+    assert_eq!(created_kb["reference_answers"], true);
+    
+    // Weights that are all zero can't score anything
+    let invalid_payload = json!({
+        "name": "Updated Name",
+        "rubric_weights": {"accuracy": 0.0, "completeness": 0.0, "depth": 0.0, "clarity": 0.0}
+    });
+    
+    let request = Request::builder()
+        .uri(format!("/api/knowledge-bases/{}", kb_id))
+        .method("PUT")
+        .header("content-type", "application/json")
+        .body(Body::from(invalid_payload.to_string()))
+        .unwrap();
+    
+    let response = app.call(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
     
     // Update the knowledge base
     let update_payload = json!({
         "name": "Updated Name",
-        "description": "Updated description"
+        "description": "Updated description",
+        "rubric_weights": {"accuracy": 1.0, "completeness": 1.0, "depth": 2.0, "clarity": 0.0},
+        "reference_answers": false
     });
     
     let request = Request::builder()
-        .uri(&format!("/api/knowledge-bases/{}", kb_id))
+        .uri(format!("/api/knowledge-bases/{}", kb_id))
         .method("PUT")
         .header("content-type", "application/json")
         .body(Body::from(update_payload.to_string()))
@@ -206,6 +226,8 @@ async fn test_update_knowledge_base() {
     assert_eq!(updated_kb["name"], "Updated Name");
     assert_eq!(updated_kb["description"], "Updated description");
     assert_eq!(updated_kb["id"], kb_id);
+    assert_eq!(updated_kb["rubric_weights"]["depth"], 2.0);
+    assert_eq!(updated_kb["reference_answers"], false);
 }
 
 #[tokio::test]
@@ -259,7 +281,7 @@ async fn test_delete_knowledge_base() {
     
     // Delete the knowledge base
     let request = Request::builder()
-        .uri(&format!("/api/knowledge-bases/{}", kb_id))
+        .uri(format!("/api/knowledge-bases/{}", kb_id))
         .method("DELETE")
         .body(Body::empty())
         .unwrap();
@@ -290,4 +312,53 @@ async fn test_delete_nonexistent_knowledge_base() {
     let json: Value = serde_json::from_slice(&body).unwrap();
     
     assert_eq!(json["error"], "Resource not found");
-}
\ No newline at end of file
+}
+
+#[tokio::test]
+async fn test_knowledge_base_language_setting() {
+    let (app, _pool) = create_test_app().await;
+    
+    let send = |method: &str, uri: String, payload: Value| {
+        Request::builder()
+            .uri(uri)
+            .method(method)
+            .header("content-type", "application/json")
+            .body(Body::from(payload.to_string()))
+            .unwrap()
+    };
+    
+    // Unsupported languages are rejected
+    let response = app.clone()
+        .oneshot(send("POST", "/api/knowledge-bases".to_string(), json!({"name": "Bad", "language": "xx"})))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    
+    let response = app.clone()
+        .oneshot(send("POST", "/api/knowledge-bases".to_string(), json!({"name": "Rust", "language": "zh"})))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    let created: Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(created["language"], "zh");
+    let uri = format!("/api/knowledge-bases/{}", created["id"].as_str().unwrap());
+    
+    // Omitting the language keeps it
+    let response = app.clone()
+        .oneshot(send("PUT", uri.clone(), json!({"name": "Rust book"})))
+        .await
+        .unwrap();
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    let updated: Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(updated["language"], "zh");
+    
+    // "auto" switches back to detection
+    let response = app.clone()
+        .oneshot(send("PUT", uri, json!({"name": "Rust book", "language": "auto"})))
+        .await
+        .unwrap();
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    let updated: Value = serde_json::from_slice(&body).unwrap();
+    assert!(updated["language"].is_null());
+}
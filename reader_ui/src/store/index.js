@@ -509,6 +509,11 @@ export default createStore({
       }
     },
 
+    async fetchAIModels() {
+      const response = await axios.get("/ai-config/models");
+      return response.data.models;
+    },
+
     async testAIConnection({ commit }, config) {
       commit("SET_MODULE_LOADING", { module: "aiConfig", loading: true });
       commit("CLEAR_MODULE_ERROR", "aiConfig");